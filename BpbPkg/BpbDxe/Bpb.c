@@ -19,7 +19,7 @@ static EFI_GUID MyVendorGuid = {
   0xf08ae394,
   0x4e98,
   0x46e6,
-  { 0xb3, 0xb0, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d }
+  { 0xb0, 0xb3, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d }
 };
 
 static UINT8 BpbBuffer[4096];
@@ -0,0 +1,1097 @@
+//! ACPI table layouts and a parser for the tables reachable
+//! from the RSDP. Everything here works on byte slices so the
+//! same code runs in firmware and on the host.
+
+use core::{fmt, mem, slice};
+use core::num::Wrapping;
+
+pub const ACPI_1_FADT_SIGNATURE: u32 = 0x50434146;
+pub const ACPI_1_FADT_REVISION: u8 = 0x01;
+
+pub const ACPI_3_FADT_SIGNATURE: u32 = 0x50434146;
+pub const ACPI_3_FADT_REVISION: u8 = 0x04;
+
+/// RSD_PTR Revision
+pub const ACPI_1_RSDP_REVISION: u8 = 0x01;
+pub const ACPI_2_RSDP_REVISION: u8 = 0x02;
+
+/// The common ACPI description table header. This
+/// structure prefaces most ACPI tables.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct DescriptionHeader {
+    pub signature: u32,
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: u64,
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32
+}
+
+/// Offset of the `checksum` field within `DescriptionHeader`.
+pub const DESCRIPTION_HEADER_CHECKSUM_OFFSET: usize = 9;
+
+impl DescriptionHeader {
+    /// A header with every field set to zero. Useful as a
+    /// placeholder in tables passed to `TableBuilder::table`.
+    pub const fn zeroed() -> DescriptionHeader {
+        DescriptionHeader {
+            signature: 0,
+            length: 0,
+            revision: 0,
+            checksum: 0,
+            oem_id: [0; 6],
+            oem_table_id: 0,
+            oem_revision: 0,
+            creator_id: 0,
+            creator_revision: 0
+        }
+    }
+}
+
+/// Root System Description Pointer Structure
+#[repr(C, packed)]
+pub struct RootSystemDescriptionPointer3 {
+    pub signature: u64,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3]
+}
+
+/// ACPI 3.0 Generic Address Space Address IDs
+pub const ACPI_3_SYSTEM_MEMORY: u8 = 0x0;
+pub const ACPI_3_SYSTEM_IO: u8 = 0x1;
+pub const ACPI_3_PCI_CONFIGURATION_SPACE: u8 = 0x2;
+pub const ACPI_3_EMBEDDED_CONTROLLER: u8 = 0x3;
+pub const ACPI_3_SMBUS: u8 = 0x4;
+pub const ACPI_3_FUNCTIONAL_FIXED_HARDWARE: u8 = 0x7f;
+
+/// ACPI 3.0 Generic Address Space definition
+#[repr(C, packed)]
+pub struct GenericAddressSpace {
+    pub address_space_id: u8,
+    pub register_bit_width: u8,
+    pub register_bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl Default for GenericAddressSpace {
+    fn default() -> GenericAddressSpace {
+        GenericAddressSpace::new()
+    }
+}
+
+impl GenericAddressSpace {
+    pub fn new() -> GenericAddressSpace {
+        GenericAddressSpace {
+            address_space_id: 0,
+            register_bit_width: 0,
+            register_bit_offset: 0,
+            access_size: 0,
+            address: 0,
+        }
+    }
+}
+
+/// Fixed ACPI Description Table Structure (FADT).
+#[repr(C, packed)]
+pub struct FixedDescriptionTable3 {
+    pub header: DescriptionHeader,
+    // Physical memory address of the FACS, where OSPM and
+    // Firmware exchange control information. See Section
+    // 5.2.6, “Root System Description Table,” for a
+    // description of the FACS. If the X_FIRMWARE_CTRL field
+    // contains a non zero value then this field must be
+    // zero. A zero value indicates that no FACS is
+    // specified by this field
+    pub firmware_ctrl: u32,
+    // Physical memory address (0-4 GB) of the DSDT.
+    pub dsdt: u32,
+    // ACPI 1.0 defined this offset as a field named
+    // INT_MODEL, which was eliminated in ACPI
+    // 2.0. Platforms should set this field to zero but
+    // field values of one are also allowed to maintain
+    // compatibility with ACPI 1.0.
+    pub reserved0: u8,
+    // This field is set by the OEM to convey the preferred
+    // power management profile to OSPM. OSPM can use this
+    // field to set default power management policy
+    // parameters during OS installation.
+    pub preferred_pm_profile: u8,
+    // System vector the SCI interrupt is wired to in 8259
+    // mode. On systems that do not contain the 8259, this
+    // field contains the Global System interrupt number of
+    // the SCI interrupt. OSPM is required to treat the ACPI
+    // SCI interrupt as a sharable, level, active low
+    // interrupt.
+    pub sci_int: u16,
+    // System port address of the SMI Command Port. During
+    // ACPI OS initialization, OSPM can determine that the
+    // ACPI hardware registers are owned by SMI (by way of
+    // the SCI_EN bit), in which case the ACPI OS issues the
+    // ACPI_ENABLE command to the SMI_CMD port. The SCI_EN
+    // bit effectively tracks the ownership of the ACPI
+    // hardware registers. OSPM issues commands to the
+    // SMI_CMD port synchronously from the boot
+    // processor. This field is reserved and must be zero on
+    // system that does not support System Management mode
+    pub smi_cmd: u32,
+    // The value to write to SMI_CMD to disable SMI
+    // ownership of the ACPI hardware registers. The last
+    // action SMI does to relinquish ownership is to set the
+    // SCI_EN bit. During the OS initialization process,
+    // OSPM will synchronously wait for the transfer of SMI
+    // ownership to complete, so the ACPI system releases
+    // SMI ownership as quickly as possible. This field is
+    // reserved and must be zero on systems that do not
+    // support Legacy Mode.
+    pub acpi_enable: u8,
+    // The value to write to SMI_CMD to re-enable SMI
+    // ownership of the ACPI hardware registers. This can
+    // only be done when ownership was originally acquired
+    // from SMI by OSPM using ACPI_ENABLE. An OS can hand
+    // ownership back to SMI by relinquishing use to the
+    // ACPI hardware registers, masking off all SCI
+    // interrupts, clearing the SCI_EN bit and then writing
+    // ACPI_DISABLE to the SMI_CMD port from the boot
+    // processor. This field is reserved and must be zero on
+    // systems that do not support Legacy Mode
+    pub acpi_disable: u8,
+    // The value to write to SMI_CMD to enter the S4BIOS
+    // state.  The S4BIOS state provides an alternate way to
+    // enter the S4 state where the firmware saves and
+    // restores the memory context. A value of zero in
+    // S4BIOS_F indicates S4BIOS_REQ is not supported. (See
+    // Table 5-38)
+    pub s4_bios_req: u8,
+    // If non-zero, this field contains the value OSPM
+    // writes to the SMI_CMD register to assume processor
+    // performance state control responsibility.
+    pub pstate_cnt: u8,
+    // System port address of the PM1a Event Register
+    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
+    // a hardware description layout of this register
+    // block. This is a required field. This field is
+    // superseded by the X_PM1a_EVT_BLK field.
+    pub pm1a_evt_blk: u32,
+    // System port address of the PM1b Event Register
+    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
+    // a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM1b_EVT_BLK field.
+    pub pm1b_evt_blk: u32,
+    // System port address of the PM1a Control Register
+    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
+    // for a hardware description layout of this register
+    // block. This is a required field. This field is
+    // superseded by the X_PM1a_CNT_BLK field.
+    pub pm1a_cnt_blk: u32,
+    // System port address of the PM1b Control Register
+    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
+    // for a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM1b_CNT_BLK field.
+    pub pm1b_cnt_blk: u32,
+    // System port address of the PM2 Control Register
+    // Block. See Section 4.8.3.4, “PM2 Control (PM2_CNT),”
+    // for a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM2_CNT_BLK field.
+    pub pm2_cnt_blk: u32,
+    // System port address of the Power Management Timer
+    // Control Register Block. See Section 4.8.3.3, “Power
+    // Management Timer (PM_TMR),” for a hardware
+    // description layout of this register block. This is an
+    // optional field; if this register block is not
+    // supported, this field contains zero. This field is
+    // superseded by the X_PM_TMR_BLK field.
+    pub pm_tmr_blk: u32,
+    // System port address of General-Purpose Event 0
+    // Register Block. See Section 4.8.4.1, “General-Purpose
+    // Event Register Blocks,” for a hardware description of
+    // this register block. This is an optional field; if
+    // this register block is not supported, this field
+    // contains zero. This field is superseded by the
+    // X_GPE0_BLK field.
+    pub gpe0_blk: u32,
+    // System port address of General-Purpose Event 1
+    // Register Block. See Section 4.8.4.1, “General-Purpose
+    // Event Register Blocks,” for a hardware description of
+    // this register block. This is an optional field; if
+    // this register block is not supported, this field
+    // contains zero. This field is superseded by the
+    // X_GPE1_BLK field.
+    pub gpe1_blk: u32,
+    // Number of bytes decoded by PM1a_EVT_BLK and, if
+    // supported, PM1b_EVT_BLK. This value is >= 4.
+    pub pm1_evt_len: u8,
+    // Number of bytes decoded by PM1a_CNT_BLK and, if
+    // supported, PM1b_CNT_BLK. This value is >= 2.
+    pub pm1_cnt_len: u8,
+    // Number of bytes decoded by PM2_CNT_BLK. Support for
+    // the PM2 register block is optional. If supported,
+    // this value is >= 1. If not supported, this field
+    // contains zero.
+    pub pm2_cnt_len: u8,
+    // Number of bytes decoded by PM_TMR_BLK. If the PM
+    // Timer is supported, this field’s value must be 4. If
+    // not supported, this field contains zero.
+    pub pm_tm_len: u8,
+    // Number of bytes decoded by GPE0_BLK. The value is a
+    // non- negative multiple of 2.
+    pub gpe0_blk_len: u8,
+    // Number of bytes decoded by GPE1_BLK. The value is a
+    // non- negative multiple of 2.
+    pub gpe1_blk_len: u8,
+    // Offset within the ACPI general-purpose event model
+    // where GPE1 based events start.
+    pub gpe1_base: u8,
+    // If non-zero, this field contains the value OSPM
+    // writes to the SMI_CMD register to indicate OS support
+    // for the _CST object and C States Changed
+    // notification.
+    pub cst_cnt: u8,
+    // The worst-case hardware latency, in microseconds, to
+    // enter and exit a C2 state. A value > 100 indicates
+    // the system does not support a C2 state.
+    pub p_lvl2_lat: u16,
+    // The worst-case hardware latency, in microseconds, to
+    // enter and exit a C3 state. A value > 1000 indicates
+    // the system does not support a C3 state.
+    pub p_lvl3_lat: u16,
+    // If WBINVD=0, the value of this field is the number of
+    // flush strides that need to be read (using cacheable
+    // addresses) to completely flush dirty lines from any
+    // processor’s memory caches. Notice that the value in
+    // FLUSH_STRIDE is typically the smallest cache line
+    // width on any of the processor’s caches (for more
+    // information, see the FLUSH_STRIDE field
+    // definition). If the system does not support a method
+    // for flushing the processor’s caches, then FLUSH_SIZE
+    // and WBINVD are set to zero. Notice that this method
+    // of flushing the processor caches has limitations, and
+    // WBINVD=1 is the preferred way to flush the processors
+    // caches. This value is typically at least 2 times the
+    // cache size. The maximum allowed value for FLUSH_SIZE
+    // multiplied by FLUSH_STRIDE is 2 MB for a typical
+    // maximum supported cache size of 1 MB. Larger cache
+    // sizes are supported using WBINVD=1.  This value is
+    // ignored if WBINVD=1.  This field is maintained for
+    // ACPI 1.0 processor compatibility on existing
+    // systems. Processors in new ACPI-compatible systems
+    // are required to support the WBINVD function and
+    // indicate this to OSPM by setting the WBINVD field =
+    // 1.
+    pub flush_size: u16,
+    // If WBINVD=0, the value of this field is the cache
+    // line width, in bytes, of the processor’s memory
+    // caches. This value is typically the smallest cache
+    // line width on any of the processor’s caches. For more
+    // information, see the description of the FLUSH_SIZE
+    // field.  This value is ignored if WBINVD=1.  This
+    // field is maintained for ACPI 1.0 processor
+    // compatibility on existing systems. Processors in new
+    // ACPI-compatible systems are required to support the
+    // WBINVD function and indicate this to OSPM by setting
+    // the WBINVD field = 1.
+    pub flush_stride: u16,
+    // The zero-based index of where the processor’s duty
+    // cycle setting is within the processor’s P_CNT
+    // register.
+    pub duty_offset: u8,
+    // The bit width of the processor’s duty cycle setting
+    // value in the P_CNT register. Each processor’s duty
+    // cycle setting allows the software to select a nominal
+    // processor frequency below its absolute frequency as
+    // defined by: THTL_EN = 1 BF * DC/(2 DUTY_WIDTH )
+    // Where: BF–Base frequency DC–Duty cycle setting When
+    // THTL_EN is 0, the processor runs at its absolute
+    // BF. A DUTY_WIDTH value of 0 indicates that processor
+    // duty cycle is not supported and the processor
+    // continuously runs at its base frequency.
+    pub duty_width: u8,
+    // The RTC CMOS RAM index to the day-of-month alarm
+    // value.  If this field contains a zero, then the RTC
+    // day of the month alarm feature is not supported. If
+    // this field has a non-zero value, then this field
+    // contains an index into RTC RAM space that OSPM can
+    // use to program the day of the month alarm.  See
+    // Section 4.8.2.4 “Real Time Clock Alarm,” for a
+    // description of how the hardware works.
+    pub day_alrm: u8,
+    // The RTC CMOS RAM index to the month of year alarm
+    // value.  If this field contains a zero, then the RTC
+    // month of the year alarm feature is not supported. If
+    // this field has a non-zero value, then this field
+    // contains an index into RTC RAM space that OSPM can
+    // use to program the month of the year alarm. If this
+    // feature is supported, then the DAY_ALRM feature must
+    // be supported also.
+    pub mon_alrm: u8,
+    // The RTC CMOS RAM index to the century of data value
+    // (hundred and thousand year decimals). If this field
+    // contains a zero, then the RTC centenary feature is
+    // not supported. If this field has a non-zero value,
+    // then this field contains an index into RTC RAM space
+    // that OSPM can use to program the centenary field.
+    pub century: u8,
+    // IA-PC Boot Architecture Flags. See Table 5-36 for a
+    // description of this field.
+    pub iapc_boot_arch: u16,
+    // Must be 0.
+    pub reserved1: u8,
+    // Fixed feature flags. See Table 5-35 for a description
+    // of this field.
+    pub flags: u32,
+    pub reset_reg: GenericAddressSpace,
+    pub reset_value: u8,
+    pub reserved2: u8,
+    pub reserved3: u8,
+    pub reserved4: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_evt_blk: GenericAddressSpace,
+    pub x_pm1b_evt_blk: GenericAddressSpace,
+    pub x_pm1a_cnt_blk: GenericAddressSpace,
+    pub x_pm1b_cnt_blk: GenericAddressSpace,
+    pub x_pm2_cnt_blk: GenericAddressSpace,
+    pub x_pm_tmr_blk: GenericAddressSpace,
+    pub x_gpe0_blk: GenericAddressSpace,
+    pub x_gpe1_blk: GenericAddressSpace,
+}
+
+/// Fixed ACPI Description Table Structure (FADT).
+#[repr(C, packed)]
+pub struct FixedDescriptionTable1 {
+    pub header: DescriptionHeader,
+    // Physical memory address of the FACS, where OSPM and
+    // Firmware exchange control information. See Section
+    // 5.2.6, “Root System Description Table,” for a
+    // description of the FACS. If the X_FIRMWARE_CTRL field
+    // contains a non zero value then this field must be
+    // zero. A zero value indicates that no FACS is
+    // specified by this field
+    pub firmware_ctrl: u32,
+    // Physical memory address (0-4 GB) of the DSDT.
+    pub dsdt: u32,
+    // ACPI 1.0 defined this offset as a field named
+    // INT_MODEL, which was eliminated in ACPI
+    // 2.0. Platforms should set this field to zero but
+    // field values of one are also allowed to maintain
+    // compatibility with ACPI 1.0.
+    pub int_model: u8,
+    // Must be zero.
+    pub reserved0: u8,
+    // System vector the SCI interrupt is wired to in 8259
+    // mode. On systems that do not contain the 8259, this
+    // field contains the Global System interrupt number of
+    // the SCI interrupt. OSPM is required to treat the ACPI
+    // SCI interrupt as a sharable, level, active low
+    // interrupt.
+    pub sci_int: u16,
+    // System port address of the SMI Command Port. During
+    // ACPI OS initialization, OSPM can determine that the
+    // ACPI hardware registers are owned by SMI (by way of
+    // the SCI_EN bit), in which case the ACPI OS issues the
+    // ACPI_ENABLE command to the SMI_CMD port. The SCI_EN
+    // bit effectively tracks the ownership of the ACPI
+    // hardware registers. OSPM issues commands to the
+    // SMI_CMD port synchronously from the boot
+    // processor. This field is reserved and must be zero on
+    // system that does not support System Management mode
+    pub smi_cmd: u32,
+    // The value to write to SMI_CMD to disable SMI
+    // ownership of the ACPI hardware registers. The last
+    // action SMI does to relinquish ownership is to set the
+    // SCI_EN bit. During the OS initialization process,
+    // OSPM will synchronously wait for the transfer of SMI
+    // ownership to complete, so the ACPI system releases
+    // SMI ownership as quickly as possible. This field is
+    // reserved and must be zero on systems that do not
+    // support Legacy Mode.
+    pub acpi_enable: u8,
+    // The value to write to SMI_CMD to re-enable SMI
+    // ownership of the ACPI hardware registers. This can
+    // only be done when ownership was originally acquired
+    // from SMI by OSPM using ACPI_ENABLE. An OS can hand
+    // ownership back to SMI by relinquishing use to the
+    // ACPI hardware registers, masking off all SCI
+    // interrupts, clearing the SCI_EN bit and then writing
+    // ACPI_DISABLE to the SMI_CMD port from the boot
+    // processor. This field is reserved and must be zero on
+    // systems that do not support Legacy Mode
+    pub acpi_disable: u8,
+    // The value to write to SMI_CMD to enter the S4BIOS
+    // state.  The S4BIOS state provides an alternate way to
+    // enter the S4 state where the firmware saves and
+    // restores the memory context. A value of zero in
+    // S4BIOS_F indicates S4BIOS_REQ is not supported. (See
+    // Table 5-38)
+    pub s4_bios_req: u8,
+    // Must be zero.
+    pub reserved1: u8,
+    // System port address of the PM1a Event Register
+    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
+    // a hardware description layout of this register
+    // block. This is a required field. This field is
+    // superseded by the X_PM1a_EVT_BLK field.
+    pub pm1a_evt_blk: u32,
+    // System port address of the PM1b Event Register
+    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
+    // a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM1b_EVT_BLK field.
+    pub pm1b_evt_blk: u32,
+    // System port address of the PM1a Control Register
+    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
+    // for a hardware description layout of this register
+    // block. This is a required field. This field is
+    // superseded by the X_PM1a_CNT_BLK field.
+    pub pm1a_cnt_blk: u32,
+    // System port address of the PM1b Control Register
+    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
+    // for a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM1b_CNT_BLK field.
+    pub pm1b_cnt_blk: u32,
+    // System port address of the PM2 Control Register
+    // Block. See Section 4.8.3.4, “PM2 Control (PM2_CNT),”
+    // for a hardware description layout of this register
+    // block. This field is optional; if this register block
+    // is not supported, this field contains zero.  This
+    // field is superseded by the X_PM2_CNT_BLK field.
+    pub pm2_cnt_blk: u32,
+    // System port address of the Power Management Timer
+    // Control Register Block. See Section 4.8.3.3, “Power
+    // Management Timer (PM_TMR),” for a hardware
+    // description layout of this register block. This is an
+    // optional field; if this register block is not
+    // supported, this field contains zero. This field is
+    // superseded by the X_PM_TMR_BLK field.
+    pub pm_tmr_blk: u32,
+    // System port address of General-Purpose Event 0
+    // Register Block. See Section 4.8.4.1, “General-Purpose
+    // Event Register Blocks,” for a hardware description of
+    // this register block. This is an optional field; if
+    // this register block is not supported, this field
+    // contains zero. This field is superseded by the
+    // X_GPE0_BLK field.
+    pub gpe0_blk: u32,
+    // System port address of General-Purpose Event 1
+    // Register Block. See Section 4.8.4.1, “General-Purpose
+    // Event Register Blocks,” for a hardware description of
+    // this register block. This is an optional field; if
+    // this register block is not supported, this field
+    // contains zero. This field is superseded by the
+    // X_GPE1_BLK field.
+    pub gpe1_blk: u32,
+    // Number of bytes decoded by PM1a_EVT_BLK and, if
+    // supported, PM1b_EVT_BLK. This value is >= 4.
+    pub pm1_evt_len: u8,
+    // Number of bytes decoded by PM1a_CNT_BLK and, if
+    // supported, PM1b_CNT_BLK. This value is >= 2.
+    pub pm1_cnt_len: u8,
+    // Number of bytes decoded by PM2_CNT_BLK. Support for
+    // the PM2 register block is optional. If supported,
+    // this value is >= 1. If not supported, this field
+    // contains zero.
+    pub pm2_cnt_len: u8,
+    // Number of bytes decoded by PM_TMR_BLK. If the PM
+    // Timer is supported, this field’s value must be 4. If
+    // not supported, this field contains zero.
+    pub pm_tm_len: u8,
+    // Number of bytes decoded by GPE0_BLK. The value is a
+    // non- negative multiple of 2.
+    pub gpe0_blk_len: u8,
+    // Number of bytes decoded by GPE1_BLK. The value is a
+    // non- negative multiple of 2.
+    pub gpe1_blk_len: u8,
+    // Offset within the ACPI general-purpose event model
+    // where GPE1 based events start.
+    pub gpe1_base: u8,
+    // Must be 0.
+    pub reserved2: u8,
+    // The worst-case hardware latency, in microseconds, to
+    // enter and exit a C2 state. A value > 100 indicates
+    // the system does not support a C2 state.
+    pub p_lvl2_lat: u16,
+    // The worst-case hardware latency, in microseconds, to
+    // enter and exit a C3 state. A value > 1000 indicates
+    // the system does not support a C3 state.
+    pub p_lvl3_lat: u16,
+    // If WBINVD=0, the value of this field is the number of
+    // flush strides that need to be read (using cacheable
+    // addresses) to completely flush dirty lines from any
+    // processor’s memory caches. Notice that the value in
+    // FLUSH_STRIDE is typically the smallest cache line
+    // width on any of the processor’s caches (for more
+    // information, see the FLUSH_STRIDE field
+    // definition). If the system does not support a method
+    // for flushing the processor’s caches, then FLUSH_SIZE
+    // and WBINVD are set to zero. Notice that this method
+    // of flushing the processor caches has limitations, and
+    // WBINVD=1 is the preferred way to flush the processors
+    // caches. This value is typically at least 2 times the
+    // cache size. The maximum allowed value for FLUSH_SIZE
+    // multiplied by FLUSH_STRIDE is 2 MB for a typical
+    // maximum supported cache size of 1 MB. Larger cache
+    // sizes are supported using WBINVD=1.  This value is
+    // ignored if WBINVD=1.  This field is maintained for
+    // ACPI 1.0 processor compatibility on existing
+    // systems. Processors in new ACPI-compatible systems
+    // are required to support the WBINVD function and
+    // indicate this to OSPM by setting the WBINVD field =
+    // 1.
+    pub flush_size: u16,
+    // If WBINVD=0, the value of this field is the cache
+    // line width, in bytes, of the processor’s memory
+    // caches. This value is typically the smallest cache
+    // line width on any of the processor’s caches. For more
+    // information, see the description of the FLUSH_SIZE
+    // field.  This value is ignored if WBINVD=1.  This
+    // field is maintained for ACPI 1.0 processor
+    // compatibility on existing systems. Processors in new
+    // ACPI-compatible systems are required to support the
+    // WBINVD function and indicate this to OSPM by setting
+    // the WBINVD field = 1.
+    pub flush_stride: u16,
+    // The zero-based index of where the processor’s duty
+    // cycle setting is within the processor’s P_CNT
+    // register.
+    pub duty_offset: u8,
+    // The bit width of the processor’s duty cycle setting
+    // value in the P_CNT register. Each processor’s duty
+    // cycle setting allows the software to select a nominal
+    // processor frequency below its absolute frequency as
+    // defined by: THTL_EN = 1 BF * DC/(2 DUTY_WIDTH )
+    // Where: BF–Base frequency DC–Duty cycle setting When
+    // THTL_EN is 0, the processor runs at its absolute
+    // BF. A DUTY_WIDTH value of 0 indicates that processor
+    // duty cycle is not supported and the processor
+    // continuously runs at its base frequency.
+    pub duty_width: u8,
+    // The RTC CMOS RAM index to the day-of-month alarm
+    // value.  If this field contains a zero, then the RTC
+    // day of the month alarm feature is not supported. If
+    // this field has a non-zero value, then this field
+    // contains an index into RTC RAM space that OSPM can
+    // use to program the day of the month alarm.  See
+    // Section 4.8.2.4 “Real Time Clock Alarm,” for a
+    // description of how the hardware works.
+    pub day_alrm: u8,
+    // The RTC CMOS RAM index to the month of year alarm
+    // value.  If this field contains a zero, then the RTC
+    // month of the year alarm feature is not supported. If
+    // this field has a non-zero value, then this field
+    // contains an index into RTC RAM space that OSPM can
+    // use to program the month of the year alarm. If this
+    // feature is supported, then the DAY_ALRM feature must
+    // be supported also.
+    pub mon_alrm: u8,
+    // The RTC CMOS RAM index to the century of data value
+    // (hundred and thousand year decimals). If this field
+    // contains a zero, then the RTC centenary feature is
+    // not supported. If this field has a non-zero value,
+    // then this field contains an index into RTC RAM space
+    // that OSPM can use to program the centenary field.
+    pub century: u8,
+    // Must be 0.
+    pub reserved3: u8,
+    // Must be 0.
+    pub reserved4: u8,
+    // Must be 0.
+    pub reserved5: u8,
+    // Fixed feature flags. See Table 5-35 for a description
+    // of this field.
+    pub flags: u32,
+}
+
+/// Marker for `#[repr(C, packed)]` structures that may be
+/// viewed as plain bytes.
+///
+/// # Safety
+///
+/// The type must have no padding, no pointers and no invariants
+/// beyond its bit pattern.
+pub unsafe trait Plain: Sized {}
+
+unsafe impl Plain for DescriptionHeader {}
+unsafe impl Plain for GenericAddressSpace {}
+unsafe impl Plain for FixedDescriptionTable1 {}
+unsafe impl Plain for FixedDescriptionTable3 {}
+
+/// View a plain structure as a byte slice.
+pub fn as_bytes<T: Plain>(value: &T) -> &[u8] {
+    // SAFETY: Plain guarantees every byte of T is initialized
+    unsafe {
+        slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+    }
+}
+
+/// Compute a value for the checksum byte so that the whole
+/// buffer sums to zero.
+pub fn checksum(buffer: &[u8]) -> u8 {
+    let sum = buffer.iter()
+        .copied()
+        .map(Wrapping)
+        .sum::<Wrapping<u8>>();
+    (Wrapping(0u8) - sum).0
+}
+
+/// Checksum of the bytes of `value`.
+///
+/// # Safety
+///
+/// Every byte of `value` must be initialized.
+pub unsafe fn checksum8<T: Sized>(value: &T) -> u8 {
+    let buffer = slice::from_raw_parts(
+        value as *const T as *const u8,
+        mem::size_of::<T>(),
+    );
+    checksum(buffer)
+}
+
+/// Check that `table` is a complete description table: the
+/// header fits, `length` matches the buffer and the entire
+/// table sums to zero.
+pub fn validate_table(table: &[u8]) -> Result<(), ParseError> {
+    if table.len() < mem::size_of::<DescriptionHeader>() {
+        return Err(ParseError::Truncated);
+    }
+    if read_u32(table, 4) != Some(table.len() as u32) {
+        return Err(ParseError::BadLength);
+    }
+    if checksum(table) != 0 {
+        return Err(ParseError::BadChecksum);
+    }
+    Ok(())
+}
+
+pub const RSDP_SIGNATURE: u64 = 0x2052_5450_2044_5352;  // "RSD PTR "
+pub const XSDT_SIGNATURE: u32 = 0x5444_5358;            // "XSDT"
+pub const RSDT_SIGNATURE: u32 = 0x5444_5352;            // "RSDT"
+pub const DSDT_SIGNATURE: u32 = 0x5444_5344;            // "DSDT"
+pub const SSDT_SIGNATURE: u32 = 0x5444_5353;            // "SSDT"
+pub const SSDT_REVISION: u8 = 0x2;
+
+/// Size of the ACPI 1.0 part of the RSDP covered by `checksum`.
+pub const ACPI_1_RSDP_LENGTH: usize = 20;
+
+/// Offsets of the DSDT pointers within the FADT.
+const FADT_DSDT_OFFSET: usize = 40;
+const FADT_X_DSDT_OFFSET: usize = 140;
+
+/// Why a table could not be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The buffer ends before the structure does.
+    Truncated,
+    /// The structure carries an unexpected signature.
+    BadSignature,
+    /// The length field is smaller than the structure header.
+    BadLength,
+    /// The bytes covered by `checksum` do not sum to zero.
+    BadChecksum,
+    /// The bytes covered by `extended_checksum` do not sum to zero.
+    BadExtendedChecksum,
+    /// Nothing is mapped at the given physical address.
+    Unmapped(u64),
+    /// No table with the requested signature is listed.
+    NotFound(u32),
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let mut value = [0; 4];
+    value.copy_from_slice(bytes.get(offset..offset + 4)?);
+    Some(u32::from_le_bytes(value))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let mut value = [0; 8];
+    value.copy_from_slice(bytes.get(offset..offset + 8)?);
+    Some(u64::from_le_bytes(value))
+}
+
+/// Printable form of a table signature.
+pub fn signature_str(signature: &u32) -> &str {
+    // SAFETY: u32 has no padding and is at least as aligned as u8
+    let bytes = unsafe {
+        slice::from_raw_parts(signature as *const u32 as *const u8, 4)
+    };
+    core::str::from_utf8(bytes).unwrap_or("????")
+}
+
+/// Validated contents of the Root System Description Pointer.
+#[derive(Clone, Copy, Debug)]
+pub struct Rsdp {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub rsdt_address: u32,
+    pub xsdt_address: Option<u64>,
+}
+
+impl Rsdp {
+    /// Parse and validate an RSDP. `bytes` must start at the
+    /// signature and may extend past the structure.
+    pub fn parse(bytes: &[u8]) -> Result<Rsdp, ParseError> {
+        if bytes.len() < ACPI_1_RSDP_LENGTH {
+            return Err(ParseError::Truncated);
+        }
+        if read_u64(bytes, 0) != Some(RSDP_SIGNATURE) {
+            return Err(ParseError::BadSignature);
+        }
+        if checksum(&bytes[..ACPI_1_RSDP_LENGTH]) != 0 {
+            return Err(ParseError::BadChecksum);
+        }
+        let mut oem_id = [0; 6];
+        oem_id.copy_from_slice(&bytes[9..15]);
+        let revision = bytes[15];
+        let rsdt_address = read_u32(bytes, 16).ok_or(ParseError::Truncated)?;
+        if revision < ACPI_2_RSDP_REVISION {
+            return Ok(Rsdp {
+                revision,
+                oem_id,
+                rsdt_address,
+                xsdt_address: None,
+            });
+        }
+
+        if bytes.len() < mem::size_of::<RootSystemDescriptionPointer3>() {
+            return Err(ParseError::Truncated);
+        }
+        let length = read_u32(bytes, 20).ok_or(ParseError::Truncated)? as usize;
+        if length < mem::size_of::<RootSystemDescriptionPointer3>() {
+            return Err(ParseError::BadLength);
+        }
+        let extended = bytes.get(..length).ok_or(ParseError::Truncated)?;
+        if checksum(extended) != 0 {
+            return Err(ParseError::BadExtendedChecksum);
+        }
+        let xsdt_address = read_u64(bytes, 24).ok_or(ParseError::Truncated)?;
+        Ok(Rsdp {
+            revision,
+            oem_id,
+            rsdt_address,
+            xsdt_address: if xsdt_address != 0 { Some(xsdt_address) } else { None },
+        })
+    }
+
+    /// Address of the XSDT if there is one, the RSDT otherwise.
+    pub fn root_address(&self) -> u64 {
+        self.xsdt_address.unwrap_or(self.rsdt_address as u64)
+    }
+}
+
+/// A validated description table and its raw bytes.
+#[derive(Clone, Copy)]
+pub struct Table<'a> {
+    pub address: u64,
+    pub header: DescriptionHeader,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Table<'a> {
+    /// Parse and validate a description table. `bytes` must start
+    /// at the header and may extend past the table.
+    pub fn parse(address: u64, bytes: &'a [u8]) -> Result<Table<'a>, ParseError> {
+        if bytes.len() < mem::size_of::<DescriptionHeader>() {
+            return Err(ParseError::Truncated);
+        }
+        let length = read_u32(bytes, 4).ok_or(ParseError::Truncated)? as usize;
+        if length < mem::size_of::<DescriptionHeader>() {
+            return Err(ParseError::BadLength);
+        }
+        let bytes = bytes.get(..length).ok_or(ParseError::Truncated)?;
+        if checksum(bytes) != 0 {
+            return Err(ParseError::BadChecksum);
+        }
+        // SAFETY: the slice covers a whole header
+        let header = unsafe {
+            (bytes.as_ptr() as *const DescriptionHeader)
+                .read_unaligned()
+        };
+        Ok(Table { address, header, bytes })
+    }
+
+    /// Bytes that follow the description header.
+    pub fn body(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<DescriptionHeader>()..]
+    }
+}
+
+impl<'a> fmt::Debug for Table<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signature = self.header.signature;
+        f.debug_struct("Table")
+            .field("signature", &signature_str(&signature))
+            .field("address", &format_args!("{:#x}", self.address))
+            .field("length", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Physical addresses listed in an XSDT or RSDT.
+#[derive(Clone)]
+pub struct RootEntries<'a> {
+    entries: &'a [u8],
+    entry_size: usize,
+}
+
+impl<'a> RootEntries<'a> {
+    pub fn new(root: &Table<'a>) -> Result<RootEntries<'a>, ParseError> {
+        let entry_size = match root.header.signature {
+            XSDT_SIGNATURE => 8,
+            RSDT_SIGNATURE => 4,
+            _ => return Err(ParseError::BadSignature),
+        };
+        let entries = root.body();
+        let entries = &entries[..entries.len() - entries.len() % entry_size];
+        Ok(RootEntries { entries, entry_size })
+    }
+}
+
+impl<'a> Iterator for RootEntries<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let address = match self.entry_size {
+            8 => read_u64(self.entries, 0),
+            _ => read_u32(self.entries, 0).map(u64::from),
+        };
+        self.entries = &self.entries[self.entry_size..];
+        address
+    }
+}
+
+/// The platform's description tables reachable from an RSDP.
+///
+/// `map` returns the bytes starting at a physical address and
+/// covering at least the whole table found there. On the host
+/// it can serve table dumps from `/sys/firmware/acpi/tables`.
+pub struct AcpiTables<'a, F> {
+    pub rsdp: Rsdp,
+    pub root: Table<'a>,
+    map: F,
+}
+
+impl<'a, F> AcpiTables<'a, F>
+where
+    F: Fn(u64) -> Option<&'a [u8]>,
+{
+    pub fn new(rsdp: &[u8], map: F) -> Result<AcpiTables<'a, F>, ParseError> {
+        let rsdp = Rsdp::parse(rsdp)?;
+        let root = load_table(&map, rsdp.root_address())?;
+        match root.header.signature {
+            XSDT_SIGNATURE | RSDT_SIGNATURE => (),
+            _ => return Err(ParseError::BadSignature),
+        }
+        Ok(AcpiTables { rsdp, root, map })
+    }
+
+    /// Every table listed in the root table. Broken entries are
+    /// reported rather than skipped.
+    pub fn iter(&self) -> TableIter<'_, 'a, F> {
+        TableIter {
+            entries: RootEntries::new(&self.root)
+                .unwrap_or(RootEntries { entries: &[], entry_size: 8 }),
+            map: &self.map,
+        }
+    }
+
+    /// The first valid table with the given signature.
+    pub fn find(&self, signature: u32) -> Option<Table<'a>> {
+        self.iter()
+            .filter_map(|table| table.ok())
+            .find(|table| table.header.signature == signature)
+    }
+
+    /// The DSDT referenced by the FADT.
+    pub fn dsdt(&self) -> Result<Table<'a>, ParseError> {
+        let fadt = self.find(ACPI_3_FADT_SIGNATURE)
+            .ok_or(ParseError::NotFound(ACPI_3_FADT_SIGNATURE))?;
+        let address = read_u64(fadt.bytes, FADT_X_DSDT_OFFSET)
+            .filter(|&address| address != 0)
+            .or_else(|| read_u32(fadt.bytes, FADT_DSDT_OFFSET).map(u64::from))
+            .ok_or(ParseError::Truncated)?;
+        let dsdt = load_table(&self.map, address)?;
+        if dsdt.header.signature != DSDT_SIGNATURE {
+            return Err(ParseError::BadSignature);
+        }
+        Ok(dsdt)
+    }
+}
+
+pub struct TableIter<'m, 'a, F> {
+    entries: RootEntries<'a>,
+    map: &'m F,
+}
+
+impl<'m, 'a, F> Iterator for TableIter<'m, 'a, F>
+where
+    F: Fn(u64) -> Option<&'a [u8]>,
+{
+    type Item = Result<Table<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.entries.next()?;
+        Some(load_table(self.map, address))
+    }
+}
+
+fn load_table<'a, F>(map: &F, address: u64) -> Result<Table<'a>, ParseError>
+where
+    F: Fn(u64) -> Option<&'a [u8]>,
+{
+    if address == 0 {
+        return Err(ParseError::Unmapped(address));
+    }
+    let bytes = map(address).ok_or(ParseError::Unmapped(address))?;
+    Table::parse(address, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const RSDP_ADDRESS: u64 = 0x1000;
+    const XSDT_ADDRESS: u64 = 0x2000;
+    const SSDT_ADDRESS: u64 = 0x3000;
+
+    fn table(signature: u32, body: &[u8]) -> Vec<u8> {
+        let mut header = DescriptionHeader::zeroed();
+        header.signature = signature;
+        header.length = (mem::size_of::<DescriptionHeader>() + body.len()) as u32;
+        header.revision = 1;
+        let mut table = as_bytes(&header).to_vec();
+        table.extend_from_slice(body);
+        table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = checksum(&table);
+        table
+    }
+
+    fn rsdp(xsdt_address: u64) -> Vec<u8> {
+        let mut rsdp = RSDP_SIGNATURE.to_le_bytes().to_vec();
+        rsdp.push(0);
+        rsdp.extend_from_slice(b"BPBOEM");
+        rsdp.push(ACPI_2_RSDP_REVISION);
+        rsdp.extend_from_slice(&0u32.to_le_bytes());
+        rsdp.extend_from_slice(&(mem::size_of::<RootSystemDescriptionPointer3>() as u32).to_le_bytes());
+        rsdp.extend_from_slice(&xsdt_address.to_le_bytes());
+        rsdp.extend_from_slice(&[0; 4]);
+        rsdp[8] = checksum(&rsdp[..ACPI_1_RSDP_LENGTH]);
+        rsdp[32] = checksum(&rsdp);
+        rsdp
+    }
+
+    struct Fixture {
+        rsdp: Vec<u8>,
+        xsdt: Vec<u8>,
+        ssdt: Vec<u8>,
+    }
+
+    impl Fixture {
+        fn new(entries: &[u64]) -> Fixture {
+            let body: Vec<u8> = entries.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+            Fixture {
+                rsdp: rsdp(XSDT_ADDRESS),
+                xsdt: table(XSDT_SIGNATURE, &body),
+                ssdt: table(SSDT_SIGNATURE, &[0x10, 0x05, b'\\', 0, 0, 0]),
+            }
+        }
+
+        fn map(&self, address: u64) -> Option<&[u8]> {
+            match address {
+                RSDP_ADDRESS => Some(&self.rsdp),
+                XSDT_ADDRESS => Some(&self.xsdt),
+                SSDT_ADDRESS => Some(&self.ssdt),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn parses_rsdp_xsdt_and_tables() {
+        let fixture = Fixture::new(&[SSDT_ADDRESS]);
+        let tables = AcpiTables::new(&fixture.rsdp, |address| fixture.map(address)).unwrap();
+        assert_eq!(tables.rsdp.revision, ACPI_2_RSDP_REVISION);
+        assert_eq!(&tables.rsdp.oem_id, b"BPBOEM");
+        assert_eq!(tables.rsdp.xsdt_address, Some(XSDT_ADDRESS));
+        assert_eq!({ tables.root.header.signature }, XSDT_SIGNATURE);
+
+        let listed: Vec<_> = tables.iter().collect();
+        assert_eq!(listed.len(), 1);
+        let ssdt = tables.find(SSDT_SIGNATURE).unwrap();
+        assert_eq!(ssdt.address, SSDT_ADDRESS);
+        assert_eq!(ssdt.bytes, &fixture.ssdt[..]);
+        assert_eq!(ssdt.body(), &[0x10, 0x05, b'\\', 0, 0, 0]);
+        assert!(tables.find(DSDT_SIGNATURE).is_none());
+    }
+
+    #[test]
+    fn reports_unmapped_entries() {
+        let fixture = Fixture::new(&[SSDT_ADDRESS, 0x4000]);
+        let tables = AcpiTables::new(&fixture.rsdp, |address| fixture.map(address)).unwrap();
+        let listed: Vec<_> = tables.iter().collect();
+        assert!(listed[0].is_ok());
+        assert_eq!(listed[1].unwrap_err(), ParseError::Unmapped(0x4000));
+        assert_eq!(tables.dsdt().unwrap_err(), ParseError::NotFound(ACPI_3_FADT_SIGNATURE));
+    }
+
+    #[test]
+    fn rejects_bad_rsdp_checksum() {
+        let mut rsdp = rsdp(XSDT_ADDRESS);
+        rsdp[8] = rsdp[8].wrapping_add(1);
+        assert_eq!(Rsdp::parse(&rsdp).unwrap_err(), ParseError::BadChecksum);
+    }
+
+    #[test]
+    fn rejects_bad_rsdp_extended_checksum() {
+        let mut rsdp = rsdp(XSDT_ADDRESS);
+        rsdp[32] = rsdp[32].wrapping_add(1);
+        assert_eq!(Rsdp::parse(&rsdp).unwrap_err(), ParseError::BadExtendedChecksum);
+    }
+
+    #[test]
+    fn rejects_bad_table_checksum() {
+        let mut fixture = Fixture::new(&[SSDT_ADDRESS]);
+        fixture.xsdt[DESCRIPTION_HEADER_CHECKSUM_OFFSET] ^= 1;
+        let error = AcpiTables::new(&fixture.rsdp, |address| fixture.map(address)).err();
+        assert_eq!(error, Some(ParseError::BadChecksum));
+        assert_eq!(validate_table(&fixture.xsdt), Err(ParseError::BadChecksum));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let rsdp = rsdp(XSDT_ADDRESS);
+        assert_eq!(Rsdp::parse(&rsdp[..ACPI_1_RSDP_LENGTH - 1]).unwrap_err(), ParseError::Truncated);
+        assert_eq!(Rsdp::parse(&rsdp[..rsdp.len() - 1]).unwrap_err(), ParseError::Truncated);
+
+        let fixture = Fixture::new(&[SSDT_ADDRESS]);
+        let ssdt = &fixture.ssdt;
+        assert_eq!(Table::parse(SSDT_ADDRESS, &ssdt[..ssdt.len() - 1]).unwrap_err(), ParseError::Truncated);
+        assert_eq!(Table::parse(SSDT_ADDRESS, &ssdt[..10]).unwrap_err(), ParseError::Truncated);
+        assert_eq!(validate_table(&ssdt[..ssdt.len() - 1]), Err(ParseError::BadLength));
+
+        let error = AcpiTables::new(&fixture.rsdp, |address| {
+            fixture.map(address).map(|bytes| &bytes[..bytes.len() - 1])
+        }).err();
+        assert_eq!(error, Some(ParseError::Truncated));
+    }
+}
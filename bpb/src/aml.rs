@@ -0,0 +1,919 @@
+//! A small ACPI Machine Language encoder, enough to describe
+//! the BPB device in an SSDT without running iasl.
+
+use alloc::vec::Vec;
+
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const NAME_OP: u8 = 0x08;
+const BYTE_PREFIX: u8 = 0x0a;
+const WORD_PREFIX: u8 = 0x0b;
+const DWORD_PREFIX: u8 = 0x0c;
+const STRING_PREFIX: u8 = 0x0d;
+const QWORD_PREFIX: u8 = 0x0e;
+const SCOPE_OP: u8 = 0x10;
+const BUFFER_OP: u8 = 0x11;
+const PACKAGE_OP: u8 = 0x12;
+const METHOD_OP: u8 = 0x14;
+const DUAL_NAME_PREFIX: u8 = 0x2e;
+const MULTI_NAME_PREFIX: u8 = 0x2f;
+const EXT_OP_PREFIX: u8 = 0x5b;
+const ROOT_CHAR: u8 = b'\\';
+const PARENT_PREFIX_CHAR: u8 = b'^';
+const RETURN_OP: u8 = 0xa4;
+const ONES_OP: u8 = 0xff;
+const DEVICE_OP: u8 = 0x82;
+
+const IO_PORT_DESCRIPTOR: u8 = 0x47;
+const END_TAG_DESCRIPTOR: u8 = 0x79;
+const MEMORY32_FIXED_DESCRIPTOR: u8 = 0x86;
+const QWORD_ADDRESS_SPACE_DESCRIPTOR: u8 = 0x8a;
+
+/// Anything that can be encoded as AML.
+pub trait Aml {
+    fn append_aml(&self, bytes: &mut Vec<u8>);
+
+    fn to_aml_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.append_aml(&mut bytes);
+        bytes
+    }
+}
+
+impl<T: Aml + ?Sized> Aml for &T {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        (**self).append_aml(bytes);
+    }
+}
+
+/// Encode a PkgLength for a package whose contents, not counting
+/// the PkgLength itself, are `length` bytes long.
+fn append_pkg_length(bytes: &mut Vec<u8>, length: usize) {
+    // The encoded value includes the PkgLength bytes
+    let (count, total) = if length + 1 < (1 << 6) {
+        (1, length + 1)
+    } else if length + 2 < (1 << 12) {
+        (2, length + 2)
+    } else if length + 3 < (1 << 20) {
+        (3, length + 3)
+    } else {
+        (4, length + 4)
+    };
+    assert!(total < (1 << 28), "AML package is too large");
+
+    if count == 1 {
+        bytes.push(total as u8);
+        return;
+    }
+    bytes.push((((count - 1) << 6) | (total & 0xf)) as u8);
+    for n in 1..count {
+        bytes.push((total >> (4 + 8 * (n - 1))) as u8);
+    }
+}
+
+/// Wrap `contents` into `op`, PkgLength and the contents.
+fn append_package(bytes: &mut Vec<u8>, op: &[u8], contents: &[u8]) {
+    bytes.extend_from_slice(op);
+    append_pkg_length(bytes, contents.len());
+    bytes.extend_from_slice(contents);
+}
+
+fn append_terms(bytes: &mut Vec<u8>, terms: &[&dyn Aml]) {
+    for term in terms {
+        term.append_aml(bytes);
+    }
+}
+
+/// An integer encoded with the smallest constant that holds it.
+impl Aml for u64 {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        match *self {
+            0 => bytes.push(ZERO_OP),
+            1 => bytes.push(ONE_OP),
+            u64::MAX => bytes.push(ONES_OP),
+            value if value <= u8::MAX as u64 => {
+                bytes.push(BYTE_PREFIX);
+                bytes.push(value as u8);
+            },
+            value if value <= u16::MAX as u64 => {
+                bytes.push(WORD_PREFIX);
+                bytes.extend_from_slice(&(value as u16).to_le_bytes());
+            },
+            value if value <= u32::MAX as u64 => {
+                bytes.push(DWORD_PREFIX);
+                bytes.extend_from_slice(&(value as u32).to_le_bytes());
+            },
+            value => {
+                bytes.push(QWORD_PREFIX);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            },
+        }
+    }
+}
+
+impl Aml for u32 {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        (*self as u64).append_aml(bytes);
+    }
+}
+
+impl Aml for u16 {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        (*self as u64).append_aml(bytes);
+    }
+}
+
+impl Aml for u8 {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        (*self as u64).append_aml(bytes);
+    }
+}
+
+/// A null-terminated ASCII string constant.
+impl Aml for str {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        assert!(self.is_ascii() && !self.bytes().any(|c| c == 0));
+        bytes.push(STRING_PREFIX);
+        bytes.extend_from_slice(self.as_bytes());
+        bytes.push(0);
+    }
+}
+
+/// A NameString such as `\_SB.BPB0`, `^FOO` or `_CRS`.
+pub struct Path<'a> {
+    path: &'a str,
+}
+
+impl<'a> Path<'a> {
+    pub fn new(path: &'a str) -> Path<'a> {
+        Path { path }
+    }
+}
+
+impl<'a> Aml for Path<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut path = self.path.as_bytes();
+        while let Some((&prefix, rest)) = path.split_first() {
+            if prefix != ROOT_CHAR && prefix != PARENT_PREFIX_CHAR {
+                break;
+            }
+            bytes.push(prefix);
+            path = rest;
+        }
+
+        let segments = if path.is_empty() {
+            0
+        } else {
+            path.split(|&c| c == b'.').count()
+        };
+        match segments {
+            0 => bytes.push(ZERO_OP),
+            1 => (),
+            2 => bytes.push(DUAL_NAME_PREFIX),
+            n => {
+                assert!(n <= u8::MAX as usize);
+                bytes.push(MULTI_NAME_PREFIX);
+                bytes.push(n as u8);
+            },
+        }
+        if segments == 0 {
+            return;
+        }
+
+        for segment in path.split(|&c| c == b'.') {
+            assert!(!segment.is_empty() && segment.len() <= 4, "bad AML name segment");
+            assert!(segment[0].is_ascii_uppercase() || segment[0] == b'_');
+            assert!(segment.iter().all(|&c| {
+                c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_'
+            }));
+            bytes.extend_from_slice(segment);
+            for _ in segment.len()..4 {
+                bytes.push(b'_');
+            }
+        }
+    }
+}
+
+/// `Scope (path) { terms }`
+pub struct Scope<'a> {
+    path: Path<'a>,
+    terms: Vec<&'a dyn Aml>,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new(path: &'a str, terms: Vec<&'a dyn Aml>) -> Scope<'a> {
+        Scope { path: Path::new(path), terms }
+    }
+}
+
+impl<'a> Aml for Scope<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut contents = self.path.to_aml_bytes();
+        append_terms(&mut contents, &self.terms);
+        append_package(bytes, &[SCOPE_OP], &contents);
+    }
+}
+
+/// `Device (path) { terms }`
+pub struct Device<'a> {
+    path: Path<'a>,
+    terms: Vec<&'a dyn Aml>,
+}
+
+impl<'a> Device<'a> {
+    pub fn new(path: &'a str, terms: Vec<&'a dyn Aml>) -> Device<'a> {
+        Device { path: Path::new(path), terms }
+    }
+}
+
+impl<'a> Aml for Device<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut contents = self.path.to_aml_bytes();
+        append_terms(&mut contents, &self.terms);
+        append_package(bytes, &[EXT_OP_PREFIX, DEVICE_OP], &contents);
+    }
+}
+
+/// `Name (path, value)`
+pub struct Name<'a> {
+    path: Path<'a>,
+    value: &'a dyn Aml,
+}
+
+impl<'a> Name<'a> {
+    pub fn new(path: &'a str, value: &'a dyn Aml) -> Name<'a> {
+        Name { path: Path::new(path), value }
+    }
+}
+
+impl<'a> Aml for Name<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        bytes.push(NAME_OP);
+        self.path.append_aml(bytes);
+        self.value.append_aml(bytes);
+    }
+}
+
+/// `Package () { elements }`
+pub struct Package<'a> {
+    elements: Vec<&'a dyn Aml>,
+}
+
+impl<'a> Package<'a> {
+    pub fn new(elements: Vec<&'a dyn Aml>) -> Package<'a> {
+        assert!(elements.len() <= u8::MAX as usize);
+        Package { elements }
+    }
+}
+
+impl<'a> Aml for Package<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut contents = vec![self.elements.len() as u8];
+        append_terms(&mut contents, &self.elements);
+        append_package(bytes, &[PACKAGE_OP], &contents);
+    }
+}
+
+/// `Buffer () { data }`
+pub struct Buffer<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Buffer<'a> {
+    pub fn new(data: &'a [u8]) -> Buffer<'a> {
+        Buffer { data }
+    }
+}
+
+impl<'a> Aml for Buffer<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut contents = (self.data.len() as u64).to_aml_bytes();
+        contents.extend_from_slice(self.data);
+        append_package(bytes, &[BUFFER_OP], &contents);
+    }
+}
+
+/// `Method (path, args, Serialized|NotSerialized) { terms }`
+pub struct Method<'a> {
+    path: Path<'a>,
+    args: u8,
+    serialized: bool,
+    terms: Vec<&'a dyn Aml>,
+}
+
+impl<'a> Method<'a> {
+    pub fn new(path: &'a str, args: u8, serialized: bool, terms: Vec<&'a dyn Aml>) -> Method<'a> {
+        assert!(args <= 7);
+        Method { path: Path::new(path), args, serialized, terms }
+    }
+}
+
+impl<'a> Aml for Method<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut contents = self.path.to_aml_bytes();
+        contents.push(self.args | (self.serialized as u8) << 3);
+        append_terms(&mut contents, &self.terms);
+        append_package(bytes, &[METHOD_OP], &contents);
+    }
+}
+
+/// `Return (value)`
+pub struct Return<'a> {
+    value: &'a dyn Aml,
+}
+
+impl<'a> Return<'a> {
+    pub fn new(value: &'a dyn Aml) -> Return<'a> {
+        Return { value }
+    }
+}
+
+impl<'a> Aml for Return<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        bytes.push(RETURN_OP);
+        self.value.append_aml(bytes);
+    }
+}
+
+/// `ResourceTemplate () { descriptors }`, a buffer of resource
+/// descriptors closed by an end tag.
+pub struct ResourceTemplate<'a> {
+    descriptors: Vec<&'a dyn Aml>,
+}
+
+impl<'a> ResourceTemplate<'a> {
+    pub fn new(descriptors: Vec<&'a dyn Aml>) -> ResourceTemplate<'a> {
+        ResourceTemplate { descriptors }
+    }
+}
+
+impl<'a> Aml for ResourceTemplate<'a> {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        append_terms(&mut data, &self.descriptors);
+        // A zero checksum means the template is not checksummed
+        data.push(END_TAG_DESCRIPTOR);
+        data.push(0);
+        Buffer::new(&data).append_aml(bytes);
+    }
+}
+
+/// `Memory32Fixed (ReadWrite|ReadOnly, base, length)`
+pub struct Memory32Fixed {
+    read_write: bool,
+    base: u32,
+    length: u32,
+}
+
+impl Memory32Fixed {
+    pub fn new(read_write: bool, base: u32, length: u32) -> Memory32Fixed {
+        Memory32Fixed { read_write, base, length }
+    }
+}
+
+impl Aml for Memory32Fixed {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        bytes.push(MEMORY32_FIXED_DESCRIPTOR);
+        bytes.extend_from_slice(&9u16.to_le_bytes());
+        bytes.push(self.read_write as u8);
+        bytes.extend_from_slice(&self.base.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+    }
+}
+
+/// Memory attributes of an address space descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cacheability {
+    NonCacheable = 0,
+    Cacheable = 1,
+    WriteCombining = 2,
+    Prefetchable = 3,
+}
+
+/// `QWordMemory (ResourceConsumer, PosDecode, MinFixed, MaxFixed,
+/// cacheability, ReadWrite|ReadOnly, 0, base, base + length - 1,
+/// 0, length)`
+pub struct QWordMemory {
+    cacheability: Cacheability,
+    read_write: bool,
+    base: u64,
+    length: u64,
+}
+
+impl QWordMemory {
+    /// `None` for an empty range or one that runs past the end of the
+    /// address space, whose maximum cannot be encoded.
+    pub fn new(cacheability: Cacheability, read_write: bool, base: u64, length: u64) -> Option<QWordMemory> {
+        base.checked_add(length.checked_sub(1)?)?;
+        Some(QWordMemory { cacheability, read_write, base, length })
+    }
+}
+
+impl Aml for QWordMemory {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        // Resource type: memory range
+        const MEMORY_RANGE: u8 = 0;
+        // Consumer, positive decode, fixed minimum and maximum
+        const GENERAL_FLAGS: u8 = 0x1 | 0x4 | 0x8;
+
+        bytes.push(QWORD_ADDRESS_SPACE_DESCRIPTOR);
+        bytes.extend_from_slice(&43u16.to_le_bytes());
+        bytes.push(MEMORY_RANGE);
+        bytes.push(GENERAL_FLAGS);
+        bytes.push((self.cacheability as u8) << 1 | self.read_write as u8);
+        // Granularity
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&self.base.to_le_bytes());
+        // Checked by new
+        let max = self.base + (self.length - 1);
+        bytes.extend_from_slice(&max.to_le_bytes());
+        // Translation offset
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+    }
+}
+
+/// `IO (Decode16|Decode10, min, max, alignment, length)`
+pub struct Io {
+    decode16: bool,
+    min: u16,
+    max: u16,
+    alignment: u8,
+    length: u8,
+}
+
+impl Io {
+    pub fn new(decode16: bool, min: u16, max: u16, alignment: u8, length: u8) -> Io {
+        Io { decode16, min, max, alignment, length }
+    }
+}
+
+impl Aml for Io {
+    fn append_aml(&self, bytes: &mut Vec<u8>) {
+        bytes.push(IO_PORT_DESCRIPTOR);
+        bytes.push(self.decode16 as u8);
+        bytes.extend_from_slice(&self.min.to_le_bytes());
+        bytes.extend_from_slice(&self.max.to_le_bytes());
+        bytes.push(self.alignment);
+        bytes.push(self.length);
+    }
+}
+
+/// Decode a PkgLength. Returns the encoded length, which counts
+/// the PkgLength bytes themselves, and the number of those bytes.
+pub fn parse_pkg_length(bytes: &[u8]) -> Option<(usize, usize)> {
+    let lead = *bytes.first()?;
+    let count = (lead >> 6) as usize;
+    if count == 0 {
+        return Some(((lead & 0x3f) as usize, 1));
+    }
+    let mut length = (lead & 0xf) as usize;
+    for n in 0..count {
+        length |= (*bytes.get(1 + n)? as usize) << (4 + 8 * n);
+    }
+    Some((length, 1 + count))
+}
+
+/// Decode a NameString. Returns its last name segment and the
+/// number of bytes it occupies.
+pub fn parse_name_string(bytes: &[u8]) -> Option<([u8; 4], usize)> {
+    let mut offset = 0;
+    while let Some(&prefix) = bytes.get(offset) {
+        if prefix != ROOT_CHAR && prefix != PARENT_PREFIX_CHAR {
+            break;
+        }
+        offset += 1;
+    }
+    let segments = match *bytes.get(offset)? {
+        ZERO_OP => return Some(([0; 4], offset + 1)),
+        DUAL_NAME_PREFIX => {
+            offset += 1;
+            2
+        },
+        MULTI_NAME_PREFIX => {
+            offset += 2;
+            *bytes.get(offset - 1)? as usize
+        },
+        _ => 1,
+    };
+    if segments == 0 {
+        return None;
+    }
+    let end = offset + 4 * segments;
+    let last = bytes.get(end - 4..end)?;
+    let mut segment = [0; 4];
+    segment.copy_from_slice(last);
+    Some((segment, end))
+}
+
+fn name_segment(name: &str) -> [u8; 4] {
+    let mut segment = [b'_'; 4];
+    for (dst, src) in segment.iter_mut().zip(name.bytes()) {
+        *dst = src;
+    }
+    segment
+}
+
+/// Find `Device (name)` anywhere in `aml` and return the terms
+/// inside it. Only the last segment of the device path is matched.
+pub fn find_device<'a>(aml: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let wanted = name_segment(name);
+    (0..aml.len().saturating_sub(1))
+        .filter(|&offset| aml[offset] == EXT_OP_PREFIX && aml[offset + 1] == DEVICE_OP)
+        .find_map(|offset| {
+            let package = &aml[offset + 2..];
+            let (length, length_size) = parse_pkg_length(package)?;
+            let package = package.get(length_size..length)?;
+            let (segment, name_size) = parse_name_string(package)?;
+            if segment == wanted {
+                Some(&package[name_size..])
+            } else {
+                None
+            }
+        })
+}
+
+/// Find `Name (name, Buffer () { ... })` among `terms` and return
+/// the buffer contents.
+pub fn find_name_buffer<'a>(terms: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let wanted = name_segment(name);
+    (0..terms.len().saturating_sub(5))
+        .filter(|&offset| terms[offset] == NAME_OP && terms[offset + 1..offset + 5] == wanted)
+        .find_map(|offset| parse_buffer(terms.get(offset + 5..)?))
+}
+
+/// Decode the `Buffer () { ... }` at the start of `bytes` and return
+/// its contents.
+pub fn parse_buffer(bytes: &[u8]) -> Option<&[u8]> {
+    if *bytes.first()? != BUFFER_OP {
+        return None;
+    }
+    let (length, length_size) = parse_pkg_length(&bytes[1..])?;
+    let package = bytes.get(1 + length_size..1 + length)?;
+    let (size, size_size) = parse_integer(package)?;
+    package.get(size_size..size_size + size as usize)
+}
+
+/// Decode an integer constant. Returns its value and size.
+pub fn parse_integer(bytes: &[u8]) -> Option<(u64, usize)> {
+    let read = |size: usize| -> Option<(u64, usize)> {
+        let mut value = [0; 8];
+        value[..size].copy_from_slice(bytes.get(1..1 + size)?);
+        Some((u64::from_le_bytes(value), 1 + size))
+    };
+    match *bytes.first()? {
+        ZERO_OP => Some((0, 1)),
+        ONE_OP => Some((1, 1)),
+        ONES_OP => Some((u64::MAX, 1)),
+        BYTE_PREFIX => read(1),
+        WORD_PREFIX => read(2),
+        DWORD_PREFIX => read(4),
+        QWORD_PREFIX => read(8),
+        _ => None,
+    }
+}
+
+/// A decoded resource descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Memory32Fixed { read_write: bool, base: u32, length: u32 },
+    QWordMemory { read_write: bool, min: u64, max: u64, length: u64 },
+    Io { decode16: bool, min: u16, max: u16, alignment: u8, length: u8 },
+    /// A descriptor this decoder does not know about.
+    Other { tag: u8 },
+}
+
+impl Resource {
+    /// Base and length of a memory descriptor.
+    pub fn memory_range(&self) -> Option<(u64, u64)> {
+        match *self {
+            Resource::Memory32Fixed { base, length, .. } => Some((base as u64, length as u64)),
+            Resource::QWordMemory { min, length, .. } => Some((min, length)),
+            _ => None,
+        }
+    }
+}
+
+/// Descriptors in a resource template, up to the end tag.
+#[derive(Clone, Debug)]
+pub struct Resources<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Resources<'a> {
+    pub fn new(template: &'a [u8]) -> Resources<'a> {
+        Resources { bytes: template }
+    }
+}
+
+impl<'a> Iterator for Resources<'a> {
+    type Item = Resource;
+
+    fn next(&mut self) -> Option<Resource> {
+        let tag = *self.bytes.first()?;
+        let (data, size) = if tag & 0x80 == 0 {
+            let length = (tag & 0x7) as usize;
+            (self.bytes.get(1..1 + length)?, 1 + length)
+        } else {
+            let length = u16::from_le_bytes([*self.bytes.get(1)?, *self.bytes.get(2)?]) as usize;
+            (self.bytes.get(3..3 + length)?, 3 + length)
+        };
+        if tag & 0xf8 == END_TAG_DESCRIPTOR & 0xf8 && tag & 0x80 == 0 {
+            self.bytes = &[];
+            return None;
+        }
+        self.bytes = &self.bytes[size..];
+
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let u32_at = |offset: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&data[offset..offset + 4]);
+            u32::from_le_bytes(value)
+        };
+        let u64_at = |offset: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&data[offset..offset + 8]);
+            u64::from_le_bytes(value)
+        };
+
+        let resource = match tag {
+            MEMORY32_FIXED_DESCRIPTOR if data.len() >= 9 => Resource::Memory32Fixed {
+                read_write: data[0] & 1 != 0,
+                base: u32_at(1),
+                length: u32_at(5),
+            },
+            // Resource type 0 is a memory range
+            QWORD_ADDRESS_SPACE_DESCRIPTOR if data.len() >= 43 && data[0] == 0 => Resource::QWordMemory {
+                read_write: data[2] & 1 != 0,
+                min: u64_at(11),
+                max: u64_at(19),
+                length: u64_at(35),
+            },
+            IO_PORT_DESCRIPTOR if data.len() >= 7 => Resource::Io {
+                decode16: data[0] & 1 != 0,
+                min: u16_at(1),
+                max: u16_at(3),
+                alignment: data[5],
+                length: data[6],
+            },
+            tag => Resource::Other { tag },
+        };
+        Some(resource)
+    }
+}
+
+/// Point the first memory descriptor in a resource template at
+/// `base` and `length`, in place. Fails if there is none, or if the
+/// range does not end inside the address space, or below 4 GiB for a
+/// `Memory32Fixed`.
+pub fn set_memory_range(template: &mut [u8], base: u64, length: u64) -> bool {
+    let mut offset = 0;
+    while let Some(&tag) = template.get(offset) {
+        let (data, size) = if tag & 0x80 == 0 {
+            if tag & 0xf8 == END_TAG_DESCRIPTOR & 0xf8 {
+                return false;
+            }
+            (offset + 1, 1 + (tag & 0x7) as usize)
+        } else {
+            let length = match template.get(offset + 1..offset + 3) {
+                Some(length) => u16::from_le_bytes([length[0], length[1]]) as usize,
+                None => return false,
+            };
+            (offset + 3, 3 + length)
+        };
+        if template.len() < offset + size {
+            return false;
+        }
+        let data = &mut template[data..offset + size];
+        let put = |data: &mut [u8], at: usize, value: &[u8]| {
+            data[at..at + value.len()].copy_from_slice(value);
+        };
+        match tag {
+            MEMORY32_FIXED_DESCRIPTOR if data.len() >= 9 => {
+                match base.checked_add(length) {
+                    Some(end) if end <= 1 << 32 => (),
+                    _ => return false,
+                }
+                put(data, 1, &(base as u32).to_le_bytes());
+                put(data, 5, &(length as u32).to_le_bytes());
+                return true;
+            },
+            // Resource type 0 is a memory range
+            QWORD_ADDRESS_SPACE_DESCRIPTOR if data.len() >= 43 && data[0] == 0 => {
+                let max = match base.checked_add(length.max(1)) {
+                    Some(end) => end - 1,
+                    None => return false,
+                };
+                put(data, 11, &base.to_le_bytes());
+                put(data, 19, &max.to_le_bytes());
+                put(data, 35, &length.to_le_bytes());
+                return true;
+            },
+            _ => offset += size,
+        }
+    }
+    false
+}
+
+/// Whether `aml` is one `Scope (...) { ... }` and nothing else,
+/// the way our own SSDT is laid out.
+pub fn is_single_scope(aml: &[u8]) -> bool {
+    match aml.split_first() {
+        Some((&SCOPE_OP, package)) => {
+            parse_pkg_length(package).map_or(false, |(length, _)| length == package.len())
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pkg_length(length: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        append_pkg_length(&mut bytes, length);
+        bytes
+    }
+
+    #[test]
+    fn encodes_pkg_lengths() {
+        assert_eq!(pkg_length(0), [0x01]);
+        assert_eq!(pkg_length(62), [0x3f]);
+        assert_eq!(pkg_length(63), [0x41, 0x04]);
+        assert_eq!(pkg_length(4093), [0x4f, 0xff]);
+        assert_eq!(pkg_length(4094), [0x81, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn encodes_integers_in_the_smallest_constant() {
+        assert_eq!(0u64.to_aml_bytes(), [ZERO_OP]);
+        assert_eq!(1u8.to_aml_bytes(), [ONE_OP]);
+        assert_eq!(u64::MAX.to_aml_bytes(), [ONES_OP]);
+        assert_eq!(0xffu16.to_aml_bytes(), [BYTE_PREFIX, 0xff]);
+        assert_eq!(0x100u32.to_aml_bytes(), [WORD_PREFIX, 0x00, 0x01]);
+        assert_eq!(0x1_0000u64.to_aml_bytes(), [DWORD_PREFIX, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!((1u64 << 32).to_aml_bytes(), [QWORD_PREFIX, 0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!("BPB".to_aml_bytes(), [STRING_PREFIX, b'B', b'P', b'B', 0]);
+    }
+
+    #[test]
+    fn encodes_name_strings() {
+        assert_eq!(Path::new("_CRS").to_aml_bytes(), *b"_CRS");
+        assert_eq!(Path::new("^FOO").to_aml_bytes(), *b"^FOO_");
+        assert_eq!(Path::new("\\").to_aml_bytes(), [ROOT_CHAR, ZERO_OP]);
+        assert_eq!(Path::new("\\_SB.BPB0").to_aml_bytes(), *b"\\\x2e_SB_BPB0");
+        assert_eq!(Path::new("\\_SB.PCI0.BPB0").to_aml_bytes(), *b"\\\x2f\x03_SB_PCI0BPB0");
+    }
+
+    #[test]
+    fn encodes_nested_objects() {
+        let uid = Name::new("_UID", &1u64);
+        let bpb0 = Device::new("BPB0", vec![&uid]);
+        let scope = Scope::new("\\_SB", vec![&bpb0]);
+        assert_eq!(scope.to_aml_bytes(), [
+            SCOPE_OP, 19, b'\\', b'_', b'S', b'B', b'_',
+            EXT_OP_PREFIX, DEVICE_OP, 11, b'B', b'P', b'B', b'0',
+            NAME_OP, b'_', b'U', b'I', b'D', ONE_OP,
+        ]);
+
+        let sta = Return::new(&0x0fu64);
+        let method = Method::new("_STA", 0, false, vec![&sta]);
+        assert_eq!(method.to_aml_bytes(), [
+            METHOD_OP, 9, b'_', b'S', b'T', b'A', 0x00, RETURN_OP, BYTE_PREFIX, 0x0f,
+        ]);
+    }
+
+    #[test]
+    fn encodes_resource_templates() {
+        let memory32 = Memory32Fixed::new(true, 0xfed0_0000, 0x1000);
+        let template = ResourceTemplate::new(vec![&memory32]);
+        assert_eq!(template.to_aml_bytes(), [
+            BUFFER_OP, 17, BYTE_PREFIX, 14,
+            MEMORY32_FIXED_DESCRIPTOR, 0x09, 0x00, 0x01,
+            0x00, 0x00, 0xd0, 0xfe, 0x00, 0x10, 0x00, 0x00,
+            END_TAG_DESCRIPTOR, 0x00,
+        ]);
+
+        let base = 0x1_0000_0000u64;
+        let qword = QWordMemory::new(Cacheability::Cacheable, false, base, 0x3000).unwrap().to_aml_bytes();
+        assert_eq!(qword.len(), 46);
+        assert_eq!(qword[..6], [QWORD_ADDRESS_SPACE_DESCRIPTOR, 43, 0, 0, 0x0d, 0x02]);
+        assert_eq!(qword[14..22], base.to_le_bytes());
+        assert_eq!(qword[22..30], (base + 0x2fff).to_le_bytes());
+        assert_eq!(qword[38..46], 0x3000u64.to_le_bytes());
+        let last_page = u64::MAX - 0xfff;
+        assert!(QWordMemory::new(Cacheability::Cacheable, false, last_page, 0x1000).is_some());
+        assert!(QWordMemory::new(Cacheability::Cacheable, false, last_page, 0x1001).is_none());
+        assert!(QWordMemory::new(Cacheability::Cacheable, false, base, 0).is_none());
+
+        let io = Io::new(true, 0x60, 0x64, 1, 1);
+        assert_eq!(io.to_aml_bytes(), [IO_PORT_DESCRIPTOR, 0x01, 0x60, 0x00, 0x64, 0x00, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn parses_buffers() {
+        let data = [1, 2, 3];
+        let buffer = Buffer::new(&data).to_aml_bytes();
+        assert_eq!(parse_buffer(&buffer), Some(&data[..]));
+        assert_eq!(parse_buffer(&buffer[..buffer.len() - 1]), None);
+        assert_eq!(parse_buffer(&buffer[1..]), None);
+    }
+
+    /// `Scope (\_SB) { Device (BPB0) { Name (_HID, ...) Name (_CRS, template) } }`
+    fn device(descriptor: &dyn Aml) -> Vec<u8> {
+        let template = ResourceTemplate::new(vec![descriptor]);
+        let crs = Name::new("_CRS", &template);
+        let hid = Name::new("_HID", &"BPB0001");
+        let bpb0 = Device::new("BPB0", vec![&hid, &crs]);
+        Scope::new("\\_SB", vec![&bpb0]).to_aml_bytes()
+    }
+
+    fn resources(aml: &[u8]) -> Vec<Resource> {
+        let terms = find_device(aml, "BPB0").unwrap();
+        Resources::new(find_name_buffer(terms, "_CRS").unwrap()).collect()
+    }
+
+    #[test]
+    fn memory32_fixed_round_trip() {
+        let aml = device(&Memory32Fixed::new(true, 0xfed0_0000, 0x1000));
+        assert_eq!(resources(&aml), [
+            Resource::Memory32Fixed { read_write: true, base: 0xfed0_0000, length: 0x1000 },
+        ]);
+        assert_eq!(resources(&aml)[0].memory_range(), Some((0xfed0_0000, 0x1000)));
+    }
+
+    #[test]
+    fn qword_memory_round_trip() {
+        let base = 0x1_0000_0000;
+        let aml = device(&QWordMemory::new(Cacheability::Cacheable, false, base, 0x3000).unwrap());
+        assert_eq!(resources(&aml), [
+            Resource::QWordMemory { read_write: false, min: base, max: base + 0x2fff, length: 0x3000 },
+        ]);
+        assert_eq!(resources(&aml)[0].memory_range(), Some((base, 0x3000)));
+    }
+
+    #[test]
+    fn finds_only_named_objects() {
+        let aml = device(&Io::new(true, 0x60, 0x60, 1, 1));
+        assert!(find_device(&aml, "BPB1").is_none());
+        let terms = find_device(&aml, "BPB0").unwrap();
+        assert!(find_name_buffer(terms, "_PRS").is_none());
+        assert_eq!(resources(&aml), [
+            Resource::Io { decode16: true, min: 0x60, max: 0x60, alignment: 1, length: 1 },
+        ]);
+        assert_eq!(resources(&aml)[0].memory_range(), None);
+    }
+
+    /// The `_CRS` template of `aml`, mutable.
+    fn template(aml: &mut [u8]) -> &mut [u8] {
+        let template = find_name_buffer(find_device(aml, "BPB0").unwrap(), "_CRS").unwrap();
+        let start = template.as_ptr() as usize - aml.as_ptr() as usize;
+        let length = template.len();
+        &mut aml[start..start + length]
+    }
+
+    #[test]
+    fn recognizes_a_single_scope() {
+        let aml = device(&Memory32Fixed::new(true, 0xfed0_0000, 0x1000));
+        assert!(is_single_scope(&aml));
+        assert!(!is_single_scope(&aml[1..]));
+        let mut two = aml.clone();
+        two.extend_from_slice(&aml);
+        assert!(!is_single_scope(&two));
+    }
+
+    #[test]
+    fn sets_memory32_fixed_range() {
+        let mut aml = device(&Memory32Fixed::new(true, 0xfed0_0000, 0x1000));
+        assert!(set_memory_range(template(&mut aml), 0x7ff0_0000, 0x2000));
+        assert_eq!(resources(&aml)[0].memory_range(), Some((0x7ff0_0000, 0x2000)));
+        assert!(!set_memory_range(template(&mut aml), 0xffff_f000, 0x2000));
+        assert!(!set_memory_range(template(&mut aml), u64::MAX, 1));
+        assert_eq!(resources(&aml)[0].memory_range(), Some((0x7ff0_0000, 0x2000)));
+    }
+
+    #[test]
+    fn sets_qword_memory_range() {
+        let mut aml = device(&QWordMemory::new(Cacheability::Cacheable, false, 0x1_0000_0000, 0x3000).unwrap());
+        assert!(set_memory_range(template(&mut aml), 0x2_0000_0000, 0x1000));
+        assert_eq!(resources(&aml), [
+            Resource::QWordMemory {
+                read_write: false,
+                min: 0x2_0000_0000,
+                max: 0x2_0000_0fff,
+                length: 0x1000,
+            },
+        ]);
+        assert!(!set_memory_range(template(&mut aml), u64::MAX - 0xfff, 0x2000));
+        assert_eq!(resources(&aml)[0].memory_range(), Some((0x2_0000_0000, 0x1000)));
+    }
+
+    #[test]
+    fn needs_a_memory_descriptor() {
+        let aml = device(&Io::new(true, 0x60, 0x60, 1, 1));
+        let terms = find_device(&aml, "BPB0").unwrap();
+        let mut template = find_name_buffer(terms, "_CRS").unwrap().to_vec();
+        assert!(!set_memory_range(&mut template, 0x1000, 0x1000));
+    }
+}
@@ -0,0 +1,132 @@
+//! Layouts shared by the EFI writer and the OS-side readers of
+//! the boot parameter block.
+
+#![no_std]
+#[macro_use]
+extern crate alloc;
+
+pub mod acpi;
+pub mod aml;
+pub mod payload;
+
+use core::mem;
+
+use acpi::{DescriptionHeader, Plain};
+use payload::PAYLOAD_MAGIC;
+
+pub const MY_TABLE_SIGNATURE: u32 = 0x5442_5042;              // "BPBT"
+pub const MY_TABLE_REVISION: u8 = 2;
+
+pub const MY_PAYLOAD_MAGIC: u64 = 0xfeeddead;
+
+/// Vendor GUID of the BPB variables, in efivarfs notation.
+pub const MY_VENDOR_GUID_STR: &str = "f08ae394-4e98-46e6-b0b3-1bb940ac663d";
+
+/// `MY_VENDOR_GUID_STR` in `EFI_GUID` memory layout, also the
+/// identifier of our `UEFI` table.
+pub const MY_VENDOR_GUID_BYTES: [u8; 16] = [
+    0x94, 0xe3, 0x8a, 0xf0, 0x98, 0x4e, 0xe6, 0x46,
+    0xb0, 0xb3, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d
+];
+
+pub const UEFI_TABLE_SIGNATURE: u32 = 0x4946_4555;            // "UEFI"
+pub const UEFI_TABLE_REVISION: u8 = 1;
+
+/// Vendor GUID of the system configuration table entry that
+/// points at a `MyPayload`.
+pub const MY_CONFIGURATION_TABLE_GUID_STR: &str = "3b8c2a7e-5d41-4f0a-9c6e-2f1d8b7a4e53";
+
+/// `MY_CONFIGURATION_TABLE_GUID_STR` in `EFI_GUID` memory layout.
+pub const MY_CONFIGURATION_TABLE_GUID_BYTES: [u8; 16] = [
+    0x7e, 0x2a, 0x8c, 0x3b, 0x41, 0x5d, 0x0a, 0x4f,
+    0x9c, 0x6e, 0x2f, 0x1d, 0x8b, 0x7a, 0x4e, 0x53
+];
+
+/// `\_SB.BPB0`, the device in our SSDT.
+pub const BPB_DEVICE_NAME: &str = "BPB0";
+pub const BPB_DEVICE_HID: &str = "BPB0001";
+pub const BPB_DEVICE_UID: u64 = 1;
+
+/// Where the BPB pages are. Carried by the `BPBT` table.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MyPayload {
+    pub magic: u64,
+    pub physical_address: u64,
+    pub length_bytes: u64
+}
+
+#[repr(C, packed)]
+pub struct MyBpbtTable {
+    pub header: DescriptionHeader,
+    pub payload: MyPayload
+}
+
+/// The fixed part of an ACPI `UEFI` table. The data starts
+/// `data_offset` bytes from the start of the table.
+#[repr(C, packed)]
+pub struct UefiTableHeader {
+    pub header: DescriptionHeader,
+    pub identifier: [u8; 16],
+    pub data_offset: u16
+}
+
+/// What our `UEFI` table carries, told apart by its magic.
+#[derive(Clone, Copy, Debug)]
+pub enum UefiTableData<'a> {
+    /// Where the BPB pages are.
+    Pointer(MyPayload),
+    /// A copy of the payload, see `payload::Payload::parse`.
+    Payload(&'a [u8]),
+}
+
+/// The data of a `UEFI` table with our identifier. `None` for any
+/// other table, or if the data is neither a `MyPayload` nor a
+/// payload.
+pub fn parse_uefi_table(table: &[u8]) -> Option<UefiTableData<'_>> {
+    if table.len() < mem::size_of::<UefiTableHeader>() {
+        return None;
+    }
+    // SAFETY: the slice covers a whole UefiTableHeader
+    let header = unsafe { (table.as_ptr() as *const UefiTableHeader).read_unaligned() };
+    if header.header.signature != UEFI_TABLE_SIGNATURE || header.identifier != MY_VENDOR_GUID_BYTES {
+        return None;
+    }
+    let length = (header.header.length as usize).min(table.len());
+    let data = table.get(header.data_offset as usize..length)?;
+
+    if data.get(..4) == Some(&PAYLOAD_MAGIC.to_le_bytes()[..]) {
+        return Some(UefiTableData::Payload(data));
+    }
+    if data.len() >= mem::size_of::<MyPayload>() {
+        // SAFETY: data holds a whole MyPayload
+        let payload = unsafe { (data.as_ptr() as *const MyPayload).read_unaligned() };
+        if payload.magic == MY_PAYLOAD_MAGIC {
+            return Some(UefiTableData::Pointer(payload));
+        }
+    }
+    None
+}
+
+pub const RUNTIME_MAX_DP: usize = 256;
+pub const RUNTIME_MAX_MOD: usize = 256;
+
+/// One image loaded in runtime memory, as `struct runtime_module`
+/// in `linux/lsefi/lsefi.c`. The `RuntimeListHead` variable holds the
+/// physical address of the first record and `next` links the rest;
+/// `seg_text` and `seg_data` are the image code and data memory types.
+#[repr(C, packed)]
+pub struct RuntimeModule {
+    pub next: u64,
+    pub base: u64,
+    pub size: u64,
+    pub seg_text: u32,
+    pub seg_data: u32,
+    pub device_path: [u16; RUNTIME_MAX_DP],
+    pub module: [u16; RUNTIME_MAX_MOD]
+}
+
+unsafe impl Plain for MyPayload {}
+unsafe impl Plain for MyBpbtTable {}
+unsafe impl Plain for UefiTableHeader {}
+unsafe impl Plain for RuntimeModule {}
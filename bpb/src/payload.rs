@@ -0,0 +1,492 @@
+//! The boot parameter block layout: a fixed header followed by
+//! typed records.
+//!
+//! ```text
+//! +--------------------------------------------+
+//! | magic | version | header_size | total_size |
+//! | crc32                                      |
+//! +--------------------------------------------+
+//! | kind | reserved | length | value ... | pad |  record
+//! +--------------------------------------------+
+//! | ...                                        |
+//! +--------------------------------------------+
+//! ```
+//!
+//! All integers are little-endian. Records start on an 8-byte
+//! boundary and `length` does not count the padding. The CRC32
+//! covers `total_size` bytes with the `crc32` field set to zero.
+
+use core::{fmt, mem, str};
+
+/// Also the probe word `bpbpnp` looks for at the start of the page.
+pub const PAYLOAD_MAGIC: u32 = 0xfead_dead;
+pub const PAYLOAD_VERSION: u16 = 1;
+
+/// `key` NUL `value`, both UTF-8.
+pub const RECORD_KEY_VALUE: u16 = 1;
+/// `key` NUL `data`.
+pub const RECORD_BLOB: u16 = 2;
+/// `name` NUL, padding to 8 bytes, then nested records.
+pub const RECORD_SECTION: u16 = 3;
+
+const RECORD_ALIGNMENT: usize = 8;
+const CRC32_OFFSET: usize = 12;
+
+#[repr(C, packed)]
+pub struct PayloadHeader {
+    pub magic: u32,
+    pub version: u16,
+    pub header_size: u16,
+    pub total_size: u32,
+    pub crc32: u32,
+}
+
+#[repr(C, packed)]
+pub struct RecordHeader {
+    pub kind: u16,
+    pub reserved: u16,
+    pub length: u32,
+}
+
+const HEADER_SIZE: usize = mem::size_of::<PayloadHeader>();
+const RECORD_HEADER_SIZE: usize = mem::size_of::<RecordHeader>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    /// The buffer cannot hold what is being written.
+    BufferTooSmall,
+    /// The buffer is longer than the `u32` total size can describe.
+    BufferTooLarge,
+    /// The buffer ends before the payload or a record does.
+    Truncated,
+    BadMagic(u32),
+    UnsupportedVersion(u16),
+    BadHeaderSize(u16),
+    BadCrc { expected: u32, actual: u32 },
+    /// A key or name is empty or holds a NUL.
+    BadKey,
+    /// A key or string value is not UTF-8.
+    BadString,
+}
+
+/// CRC-32 as used by IEEE 802.3 and `gBS->CalculateCrc32()`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    crc
+}
+
+fn align_up(value: usize) -> usize {
+    (value + RECORD_ALIGNMENT - 1) & !(RECORD_ALIGNMENT - 1)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn check_key(key: &str) -> Result<(), PayloadError> {
+    if key.is_empty() || key.bytes().any(|c| c == 0) {
+        return Err(PayloadError::BadKey);
+    }
+    Ok(())
+}
+
+/// Writes a payload into a caller-provided buffer, typically the
+/// BPB pages themselves. Call `finish` to seal the header.
+pub struct PayloadWriter<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> PayloadWriter<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Result<PayloadWriter<'a>, PayloadError> {
+        if buffer.len() < HEADER_SIZE {
+            return Err(PayloadError::BufferTooSmall);
+        }
+        if buffer.len() > u32::MAX as usize {
+            return Err(PayloadError::BufferTooLarge);
+        }
+        for byte in buffer[..HEADER_SIZE].iter_mut() {
+            *byte = 0;
+        }
+        Ok(PayloadWriter {
+            buffer,
+            position: HEADER_SIZE,
+        })
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), PayloadError> {
+        let end = self.position + bytes.len();
+        if end > self.buffer.len() {
+            return Err(PayloadError::BufferTooSmall);
+        }
+        self.buffer[self.position..end].copy_from_slice(bytes);
+        self.position = end;
+        Ok(())
+    }
+
+    fn pad(&mut self) -> Result<(), PayloadError> {
+        let end = align_up(self.position);
+        if end > self.buffer.len() {
+            return Err(PayloadError::BufferTooSmall);
+        }
+        for byte in self.buffer[self.position..end].iter_mut() {
+            *byte = 0;
+        }
+        self.position = end;
+        Ok(())
+    }
+
+    /// Start a record and return the offset of its length field.
+    fn begin(&mut self, kind: u16) -> Result<usize, PayloadError> {
+        self.put(&kind.to_le_bytes())?;
+        self.put(&0u16.to_le_bytes())?;
+        let length_offset = self.position;
+        self.put(&0u32.to_le_bytes())?;
+        Ok(length_offset)
+    }
+
+    fn end(&mut self, length_offset: usize) -> Result<(), PayloadError> {
+        let length = (self.position - length_offset - 4) as u32;
+        self.buffer[length_offset..length_offset + 4].copy_from_slice(&length.to_le_bytes());
+        self.pad()
+    }
+
+    fn keyed(&mut self, kind: u16, key: &str, data: &[u8]) -> Result<(), PayloadError> {
+        check_key(key)?;
+        let length_offset = self.begin(kind)?;
+        self.put(key.as_bytes())?;
+        self.put(&[0])?;
+        self.put(data)?;
+        self.end(length_offset)
+    }
+
+    pub fn key_value(&mut self, key: &str, value: &str) -> Result<(), PayloadError> {
+        self.keyed(RECORD_KEY_VALUE, key, value.as_bytes())
+    }
+
+    pub fn blob(&mut self, key: &str, data: &[u8]) -> Result<(), PayloadError> {
+        self.keyed(RECORD_BLOB, key, data)
+    }
+
+    /// Write a section named `name` whose nested records are
+    /// produced by `records`.
+    pub fn section<F>(&mut self, name: &str, records: F) -> Result<(), PayloadError>
+    where
+        F: FnOnce(&mut PayloadWriter<'a>) -> Result<(), PayloadError>,
+    {
+        check_key(name)?;
+        let length_offset = self.begin(RECORD_SECTION)?;
+        self.put(name.as_bytes())?;
+        self.put(&[0])?;
+        self.pad()?;
+        records(self)?;
+        self.end(length_offset)
+    }
+
+    /// Fill in the header and return the total payload size.
+    pub fn finish(self) -> Result<usize, PayloadError> {
+        let header = PayloadHeader {
+            magic: PAYLOAD_MAGIC,
+            version: PAYLOAD_VERSION,
+            header_size: HEADER_SIZE as u16,
+            total_size: self.position as u32,
+            crc32: 0,
+        };
+        // SAFETY: PayloadHeader is packed and has no padding
+        let header_bytes = unsafe {
+            core::slice::from_raw_parts(&header as *const PayloadHeader as *const u8, HEADER_SIZE)
+        };
+        self.buffer[..HEADER_SIZE].copy_from_slice(header_bytes);
+        let crc = crc32(&self.buffer[..self.position]);
+        self.buffer[CRC32_OFFSET..CRC32_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+        Ok(self.position)
+    }
+}
+
+/// A payload whose header and CRC have been validated.
+#[derive(Clone, Copy)]
+pub struct Payload<'a> {
+    pub version: u16,
+    bytes: &'a [u8],
+    header_size: usize,
+}
+
+impl<'a> Payload<'a> {
+    /// Validate the payload at the start of `bytes`, which may
+    /// extend past it, e.g. to the end of the BPB pages.
+    pub fn parse(bytes: &'a [u8]) -> Result<Payload<'a>, PayloadError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(PayloadError::Truncated);
+        }
+        let magic = read_u32(bytes, 0);
+        if magic != PAYLOAD_MAGIC {
+            return Err(PayloadError::BadMagic(magic));
+        }
+        let version = read_u16(bytes, 4);
+        if version != PAYLOAD_VERSION {
+            return Err(PayloadError::UnsupportedVersion(version));
+        }
+        let header_size = read_u16(bytes, 6);
+        if (header_size as usize) < HEADER_SIZE || header_size as usize & (RECORD_ALIGNMENT - 1) != 0 {
+            return Err(PayloadError::BadHeaderSize(header_size));
+        }
+        let total_size = read_u32(bytes, 8) as usize;
+        if total_size < header_size as usize || total_size > bytes.len() {
+            return Err(PayloadError::Truncated);
+        }
+        let bytes = &bytes[..total_size];
+
+        let expected = read_u32(bytes, CRC32_OFFSET);
+        let crc = crc32_update(!0, &bytes[..CRC32_OFFSET]);
+        let crc = crc32_update(crc, &[0; 4]);
+        let actual = !crc32_update(crc, &bytes[CRC32_OFFSET + 4..]);
+        if expected != actual {
+            return Err(PayloadError::BadCrc { expected, actual });
+        }
+
+        Ok(Payload {
+            version,
+            bytes,
+            header_size: header_size as usize,
+        })
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn crc32(&self) -> u32 {
+        read_u32(self.bytes, CRC32_OFFSET)
+    }
+
+    pub fn records(&self) -> Records<'a> {
+        Records {
+            bytes: &self.bytes[self.header_size..],
+        }
+    }
+
+    /// The data of the first top-level blob record named `key`.
+    pub fn blob(&self, key: &str) -> Option<&'a [u8]> {
+        self.records()
+            .filter_map(|record| record.ok())
+            .find_map(|record| match record {
+                Record::Blob { key: k, data } if k == key => Some(data),
+                _ => None,
+            })
+    }
+
+    /// The value of the first top-level key/value record named `key`.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.records()
+            .filter_map(|record| record.ok())
+            .find_map(|record| match record {
+                Record::KeyValue { key: k, value } if k == key => Some(value),
+                _ => None,
+            })
+    }
+}
+
+impl<'a> fmt::Debug for Payload<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("version", &self.version)
+            .field("total_size", &self.total_size())
+            .field("crc32", &format_args!("{:#010x}", self.crc32()))
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Record<'a> {
+    KeyValue { key: &'a str, value: &'a str },
+    Blob { key: &'a str, data: &'a [u8] },
+    Section { name: &'a str, records: Records<'a> },
+    /// A record kind this reader does not know about.
+    Unknown { kind: u16, data: &'a [u8] },
+}
+
+/// Records at one nesting level. A malformed record ends the
+/// iteration after reporting an error.
+#[derive(Clone, Debug)]
+pub struct Records<'a> {
+    bytes: &'a [u8],
+}
+
+fn split_key(data: &[u8]) -> Result<(&str, &[u8]), PayloadError> {
+    let nul = data.iter()
+        .position(|&c| c == 0)
+        .ok_or(PayloadError::Truncated)?;
+    if nul == 0 {
+        return Err(PayloadError::BadKey);
+    }
+    let key = str::from_utf8(&data[..nul])
+        .map_err(|_| PayloadError::BadString)?;
+    Ok((key, &data[nul + 1..]))
+}
+
+impl<'a> Records<'a> {
+    fn parse_next(&mut self) -> Result<Record<'a>, PayloadError> {
+        if self.bytes.len() < RECORD_HEADER_SIZE {
+            return Err(PayloadError::Truncated);
+        }
+        let kind = read_u16(self.bytes, 0);
+        let length = read_u32(self.bytes, 4) as usize;
+        let end = RECORD_HEADER_SIZE
+            .checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(PayloadError::Truncated)?;
+        let data = &self.bytes[RECORD_HEADER_SIZE..end];
+        self.bytes = &self.bytes[align_up(end).min(self.bytes.len())..];
+
+        match kind {
+            RECORD_KEY_VALUE => {
+                let (key, value) = split_key(data)?;
+                let value = str::from_utf8(value)
+                    .map_err(|_| PayloadError::BadString)?;
+                Ok(Record::KeyValue { key, value })
+            },
+            RECORD_BLOB => {
+                let (key, data) = split_key(data)?;
+                Ok(Record::Blob { key, data })
+            },
+            RECORD_SECTION => {
+                let (name, _) = split_key(data)?;
+                let nested = align_up(name.len() + 1).min(data.len());
+                Ok(Record::Section {
+                    name,
+                    records: Records { bytes: &data[nested..] },
+                })
+            },
+            kind => Ok(Record::Unknown { kind, data }),
+        }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, PayloadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let record = self.parse_next();
+        if record.is_err() {
+            self.bytes = &[];
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn write(buffer: &mut [u8]) -> Result<usize, PayloadError> {
+        let mut writer = PayloadWriter::new(buffer)?;
+        writer.key_value("version", "1.2")?;
+        writer.blob("bpb.address", &0x7ff0_0000u64.to_le_bytes())?;
+        writer.section("boot", |writer| writer.key_value("cmdline", "quiet"))?;
+        writer.finish()
+    }
+
+    #[test]
+    fn crc32_is_ieee() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = [0xaa; 256];
+        let size = write(&mut buffer).unwrap();
+        assert_eq!(size % RECORD_ALIGNMENT, 0);
+
+        let payload = Payload::parse(&buffer).unwrap();
+        assert_eq!(payload.version, PAYLOAD_VERSION);
+        assert_eq!(payload.total_size(), size);
+        let mut zeroed = buffer[..size].to_vec();
+        zeroed[CRC32_OFFSET..CRC32_OFFSET + 4].copy_from_slice(&[0; 4]);
+        assert_eq!(payload.crc32(), crc32(&zeroed));
+        assert_eq!(payload.get("version"), Some("1.2"));
+        assert_eq!(payload.get("cmdline"), None);
+        assert_eq!(payload.blob("bpb.address"), Some(&0x7ff0_0000u64.to_le_bytes()[..]));
+        assert_eq!(payload.blob("version"), None);
+
+        let records: Vec<_> = payload.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 3);
+        match &records[1] {
+            Record::Blob { key, data } => {
+                assert_eq!(key, &"bpb.address");
+                assert_eq!(*data, &0x7ff0_0000u64.to_le_bytes()[..]);
+            },
+            record => panic!("expected a blob, got {:?}", record),
+        }
+        match &records[2] {
+            Record::Section { name, records } => {
+                assert_eq!(name, &"boot");
+                let nested: Vec<_> = records.clone().collect::<Result<_, _>>().unwrap();
+                assert!(matches!(nested[..], [Record::KeyValue { key: "cmdline", value: "quiet" }]));
+            },
+            record => panic!("expected a section, got {:?}", record),
+        }
+    }
+
+    #[test]
+    fn rejects_crc_mismatch() {
+        let mut buffer = [0; 256];
+        let size = write(&mut buffer).unwrap();
+        let expected = read_u32(&buffer, CRC32_OFFSET);
+        buffer[size - 1] ^= 1;
+        match Payload::parse(&buffer) {
+            Err(PayloadError::BadCrc { expected: crc, actual }) => {
+                assert_eq!(crc, expected);
+                assert_ne!(actual, expected);
+            },
+            result => panic!("expected BadCrc, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reports_buffer_too_small() {
+        let mut buffer = [0; 256];
+        let size = write(&mut buffer).unwrap();
+        for length in [0, HEADER_SIZE - 1, HEADER_SIZE + 4, size - 1] {
+            let mut buffer = vec![0; length];
+            assert_eq!(write(&mut buffer), Err(PayloadError::BufferTooSmall), "{} bytes", length);
+        }
+        assert_eq!(Payload::parse(&buffer[..size - 1]).unwrap_err(), PayloadError::Truncated);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn reports_buffer_too_large() {
+        // Zeroed pages are only mapped when touched
+        let mut buffer = vec![0; u32::MAX as usize + 1];
+        assert_eq!(PayloadWriter::new(&mut buffer).err(), Some(PayloadError::BufferTooLarge));
+    }
+
+    #[test]
+    fn rejects_bad_keys() {
+        let mut buffer = [0; 64];
+        let mut writer = PayloadWriter::new(&mut buffer).unwrap();
+        assert_eq!(writer.key_value("", "value"), Err(PayloadError::BadKey));
+        assert_eq!(writer.blob("a\0b", &[]), Err(PayloadError::BadKey));
+    }
+}
@@ -0,0 +1,55 @@
+use std::env;
+
+/// Build-time OEM identity, see `src/oem.rs`.
+const OEM_VARIABLES: [&str; 4] = ["BPB_OEM_ID", "BPB_OEM_TABLE_ID", "BPB_OEM_REVISION", "BPB_OEM_INHERIT"];
+const OEM_TABLES: [&str; 3] = ["bpbt", "ssdt", "uefi"];
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Fails the build on an identity the image would silently
+/// truncate or ignore.
+fn check_oem_variables() {
+    let check = |name: &str, valid: &dyn Fn(&str) -> bool| {
+        if let Ok(value) = env::var(name) {
+            if !valid(&value) {
+                panic!("{}={:?} is not valid", name, value);
+            }
+        }
+    };
+    check("BPB_OEM_ID", &|value| value.is_ascii() && value.len() <= 6);
+    check("BPB_OEM_TABLE_ID", &|value| value.is_ascii() && value.len() <= 8);
+    check("BPB_OEM_REVISION", &|value| {
+        parse_number(value).map_or(false, |revision| revision <= u32::MAX as u64)
+    });
+    check("BPB_OEM_INHERIT", &|value| {
+        value.is_empty() || value == "none" || value.split(',').all(|name| OEM_TABLES.contains(&name.trim()))
+    });
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The loader places EFI_RUNTIME_DRIVER images in runtime memory
+    if env::var_os("CARGO_FEATURE_RUNTIME_DRIVER").is_some() {
+        println!("cargo:rustc-link-arg-bins=/subsystem:efi_runtime_driver");
+    }
+
+    for name in &OEM_VARIABLES {
+        println!("cargo:rerun-if-env-changed={}", name);
+    }
+    check_oem_variables();
+
+    // creator_revision of our tables, 0xMMmmpp
+    let version = |name: &str| {
+        env::var(name).ok().and_then(|value| value.parse::<u32>().ok()).unwrap_or(0)
+    };
+    let creator_revision = version("CARGO_PKG_VERSION_MAJOR") << 16
+        | (version("CARGO_PKG_VERSION_MINOR") & 0xff) << 8
+        | (version("CARGO_PKG_VERSION_PATCH") & 0xff);
+    println!("cargo:rustc-env=BPB_CREATOR_REVISION={}", creator_revision);
+}
@@ -0,0 +1,615 @@
+//! Locate the boot parameter block through every channel the
+//! EFI application publishes it on and dump its contents.
+
+use std::convert::TryInto;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use bpb::acpi::{Table, SSDT_SIGNATURE};
+use bpb::aml::{self, Resources};
+use bpb::payload::{Payload, Record, Records};
+use bpb::{
+    parse_uefi_table, MyPayload, UefiTableData, MY_CONFIGURATION_TABLE_GUID_BYTES, MY_PAYLOAD_MAGIC,
+    MY_TABLE_SIGNATURE, MY_VENDOR_GUID_STR, UEFI_TABLE_SIGNATURE,
+};
+
+const EFIVARS: &str = "/sys/firmware/efi/efivars";
+const EFI_CONFIG_TABLE: &str = "/sys/firmware/efi/config_table";
+const ACPI_TABLES: &str = "/sys/firmware/acpi/tables";
+const DEV_MEM: &str = "/dev/mem";
+const PAGE_SIZE: u64 = 4096;
+
+/// "IBI SYST"
+const EFI_SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249;
+const EFI_SYSTEM_TABLE_NR_TABLES_OFFSET: usize = 104;
+const EFI_SYSTEM_TABLE_CONFIG_TABLE_OFFSET: usize = 112;
+const EFI_SYSTEM_TABLE_LENGTH: u64 = 120;
+const EFI_CONFIGURATION_TABLE_ENTRY_LENGTH: u64 = 24;
+
+const USAGE: &str = "\
+usage: bpbctl [options]
+
+Locate the BPB through the BpbAddress variable, the BPBT table,
+the UEFI table with our identifier, the BPB0 device in the SSDT and
+the EFI configuration table, then dump the payload.
+
+options:
+  --source LIST   comma separated subset of var,bpbt,uefi,ssdt,cfg (default: all)
+  --image FILE    read the payload from FILE instead of physical memory
+  --mem FILE      physical memory device (default: /dev/mem)
+  --json          print JSON instead of text
+  -h, --help      print this message
+";
+
+type Result<T> = std::result::Result<T, String>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    Variable,
+    Bpbt,
+    Uefi,
+    Ssdt,
+    ConfigTable,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Variable => "var",
+            Source::Bpbt => "bpbt",
+            Source::Uefi => "uefi",
+            Source::Ssdt => "ssdt",
+            Source::ConfigTable => "cfg",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Source> {
+        match name {
+            "var" => Ok(Source::Variable),
+            "bpbt" => Ok(Source::Bpbt),
+            "uefi" => Ok(Source::Uefi),
+            "ssdt" => Ok(Source::Ssdt),
+            "cfg" => Ok(Source::ConfigTable),
+            _ => Err(format!("unknown source: {}", name)),
+        }
+    }
+}
+
+struct Options {
+    sources: Vec<Source>,
+    image: Option<PathBuf>,
+    mem: PathBuf,
+    json: bool,
+}
+
+impl Options {
+    fn parse() -> Result<Options> {
+        let mut options = Options {
+            sources: vec![
+                Source::Variable,
+                Source::Bpbt,
+                Source::Uefi,
+                Source::Ssdt,
+                Source::ConfigTable,
+            ],
+            image: None,
+            mem: PathBuf::from(DEV_MEM),
+            json: false,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--source" => {
+                    options.sources = value()?
+                        .split(',')
+                        .map(Source::parse)
+                        .collect::<Result<Vec<_>>>()?;
+                },
+                "--image" => options.image = Some(PathBuf::from(value()?)),
+                "--mem" => options.mem = PathBuf::from(value()?),
+                "--json" => options.json = true,
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                },
+                _ => return Err(format!("unknown option: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Where one source says the BPB is.
+struct Location {
+    source: Source,
+    result: Result<(u64, Option<u64>)>,
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(data.get(offset..offset + 8)?);
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// One of our variables holding a 64-bit address: efivarfs
+/// prefixes the data with the 32-bit attributes.
+fn read_variable_u64(name: &str) -> Result<u64> {
+    let path = Path::new(EFIVARS).join(format!("{}-{}", name, MY_VENDOR_GUID_STR));
+    let data = read_file(&path)?;
+    read_u64(&data, 4).ok_or(format!("{}: too short", path.display()))
+}
+
+/// The `BpbAddress` variable.
+fn locate_variable() -> Result<(u64, Option<u64>)> {
+    Ok((read_variable_u64("BpbAddress")?, None))
+}
+
+fn read_table(path: &Path) -> Result<Vec<u8>> {
+    let data = read_file(path)?;
+    Table::parse(0, &data)
+        .map_err(|error| format!("{}: {:?}", path.display(), error))?;
+    Ok(data)
+}
+
+/// The `MyPayload` after the header of the `BPBT` table at `path`.
+fn read_bpbt(path: &Path) -> Result<(u64, Option<u64>)> {
+    let data = read_table(path)?;
+    let table = Table::parse(0, &data)
+        .map_err(|error| format!("{}: {:?}", path.display(), error))?;
+    let body = table.body();
+    if body.len() < std::mem::size_of::<MyPayload>() {
+        return Err(format!("{}: too short", path.display()));
+    }
+    // SAFETY: the body holds a whole MyPayload
+    let payload = unsafe {
+        (body.as_ptr() as *const MyPayload).read_unaligned()
+    };
+    if payload.magic != MY_PAYLOAD_MAGIC {
+        return Err(format!("{}: bad magic {:#x}", path.display(), payload.magic));
+    }
+    Ok((payload.physical_address, Some(payload.length_bytes)))
+}
+
+/// The first `BPBT` table carrying a `MyPayload` after its header.
+/// The kernel numbers the files when another table shares the
+/// signature.
+fn locate_bpbt() -> Result<(u64, Option<u64>)> {
+    let mut last_error = String::from("no BPBT table");
+    for path in table_paths(MY_TABLE_SIGNATURE)? {
+        match read_bpbt(&path) {
+            Ok(location) => return Ok(location),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+/// Where a table comes among those with the signature `prefix`: the
+/// kernel names a lone table `SSDT` and several `SSDT1`, `SSDT2`, ...
+fn table_index(prefix: &str, name: &str) -> Option<u32> {
+    let suffix = name.strip_prefix(prefix)?;
+    if suffix.is_empty() {
+        return Some(0);
+    }
+    if !suffix.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok()
+}
+
+/// Every table in `dir` with the signature, e.g. `SSDT1`, `SSDT2`,
+/// ..., `SSDT10`, in numeric order.
+fn table_paths_in(dir: &Path, signature: u32) -> Result<Vec<PathBuf>> {
+    let signature = signature.to_le_bytes();
+    let prefix = String::from_utf8_lossy(&signature).into_owned();
+    let mut paths = fs::read_dir(dir)
+        .map_err(|error| format!("{}: {}", dir.display(), error))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let index = table_index(&prefix, entry.file_name().to_str()?)?;
+            Some((index, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths.into_iter().map(|(_, path)| path).collect())
+}
+
+fn table_paths(signature: u32) -> Result<Vec<PathBuf>> {
+    table_paths_in(Path::new(ACPI_TABLES), signature)
+}
+
+/// The `UEFI` table with our identifier. Its data is either a
+/// `MyPayload` or a copy of the payload, whose `bpb.address` blob
+/// points at the BPB.
+fn locate_uefi() -> Result<(u64, Option<u64>)> {
+    for path in table_paths(UEFI_TABLE_SIGNATURE)? {
+        let data = match read_table(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        match parse_uefi_table(&data) {
+            Some(UefiTableData::Pointer(payload)) => {
+                return Ok((payload.physical_address, Some(payload.length_bytes)));
+            },
+            Some(UefiTableData::Payload(copy)) => {
+                let payload = Payload::parse(copy)
+                    .map_err(|error| format!("{}: {:?}", path.display(), error))?;
+                let address = payload.blob("bpb.address")
+                    .and_then(|address| address.try_into().ok())
+                    .ok_or(format!("{}: no bpb.address", path.display()))?;
+                return Ok((u64::from_le_bytes(address), None));
+            },
+            None => continue,
+        }
+    }
+    Err(String::from("no UEFI table with our identifier"))
+}
+
+/// The first memory descriptor of `\_SB.BPB0._CRS` in any SSDT.
+fn locate_ssdt() -> Result<(u64, Option<u64>)> {
+    for path in table_paths(SSDT_SIGNATURE)? {
+        let data = match read_table(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let table = Table::parse(0, &data)
+            .map_err(|error| format!("{}: {:?}", path.display(), error))?;
+        let range = aml::find_device(table.body(), "BPB0")
+            .and_then(|device| aml::find_name_buffer(device, "_CRS"))
+            .and_then(|crs| Resources::new(crs).find_map(|resource| resource.memory_range()));
+        if let Some((address, length)) = range {
+            return Ok((address, Some(length)));
+        }
+    }
+    Err(String::from("no SSDT defines BPB0 with a memory resource"))
+}
+
+/// Our entry in the EFI configuration table, found through the
+/// system table address the application stores in `SystemTable`.
+///
+/// SetVirtualAddressMap converts the system table's pointer to the
+/// configuration table, so prefer the physical address the kernel
+/// saved before the switch. Vendor table pointers under our GUID are
+/// never converted and stay physical.
+fn locate_config_table(mem: &Path) -> Result<(u64, Option<u64>)> {
+    let system_table_address = read_variable_u64("SystemTable")?;
+    let system_table = read_physical(mem, system_table_address, EFI_SYSTEM_TABLE_LENGTH)?;
+    let field = |offset| read_u64(&system_table, offset).unwrap_or_default();
+    if field(0) != EFI_SYSTEM_TABLE_SIGNATURE {
+        return Err(format!("no EFI system table at {:#x}", system_table_address));
+    }
+    let count = field(EFI_SYSTEM_TABLE_NR_TABLES_OFFSET);
+    let config_table_address = match fs::read_to_string(EFI_CONFIG_TABLE) {
+        Ok(text) => {
+            let text = text.trim();
+            u64::from_str_radix(text.trim_start_matches("0x"), 16)
+                .map_err(|error| format!("{}: {}", EFI_CONFIG_TABLE, error))?
+        },
+        Err(_) => field(EFI_SYSTEM_TABLE_CONFIG_TABLE_OFFSET),
+    };
+
+    let entries = read_physical(
+        mem,
+        config_table_address,
+        count * EFI_CONFIGURATION_TABLE_ENTRY_LENGTH
+    )?;
+    let vendor_table = entries
+        .chunks_exact(EFI_CONFIGURATION_TABLE_ENTRY_LENGTH as usize)
+        .find(|entry| entry[..16] == MY_CONFIGURATION_TABLE_GUID_BYTES)
+        .and_then(|entry| read_u64(entry, 16))
+        .ok_or(String::from("no configuration table entry with our GUID"))?;
+
+    let data = read_physical(mem, vendor_table, std::mem::size_of::<MyPayload>() as u64)?;
+    // SAFETY: data holds a whole MyPayload
+    let payload = unsafe {
+        (data.as_ptr() as *const MyPayload).read_unaligned()
+    };
+    if payload.magic != MY_PAYLOAD_MAGIC {
+        return Err(format!("{:#x}: bad magic {:#x}", vendor_table, payload.magic));
+    }
+    Ok((payload.physical_address, Some(payload.length_bytes)))
+}
+
+fn locate(source: Source, mem: &Path) -> Location {
+    let result = match source {
+        Source::Variable => locate_variable(),
+        Source::Bpbt => locate_bpbt(),
+        Source::Uefi => locate_uefi(),
+        Source::Ssdt => locate_ssdt(),
+        Source::ConfigTable => locate_config_table(mem),
+    };
+    Location { source, result }
+}
+
+fn read_physical(mem: &Path, address: u64, length: u64) -> Result<Vec<u8>> {
+    let error = |error: std::io::Error| format!("{} at {:#x}: {}", mem.display(), address, error);
+    let mut file = fs::File::open(mem).map_err(error)?;
+    file.seek(SeekFrom::Start(address)).map_err(error)?;
+    let mut data = vec![0; length as usize];
+    file.read_exact(&mut data).map_err(error)?;
+    Ok(data)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn text_records(out: &mut String, records: Records, depth: usize) {
+    let indent = depth * 2;
+    for record in records {
+        let _ = match record {
+            Ok(Record::KeyValue { key, value }) => {
+                writeln!(out, "{:indent$}{} = {}", "", key, value, indent = indent)
+            },
+            Ok(Record::Blob { key, data }) => {
+                writeln!(out, "{:indent$}{} = [{} bytes] {}", "", key, data.len(), hex(data), indent = indent)
+            },
+            Ok(Record::Section { name, records }) => {
+                let _ = writeln!(out, "{:indent$}[{}]", "", name, indent = indent);
+                text_records(out, records, depth + 1);
+                Ok(())
+            },
+            Ok(Record::Unknown { kind, data }) => {
+                writeln!(out, "{:indent$}<record {}> [{} bytes]", "", kind, data.len(), indent = indent)
+            },
+            Err(error) => {
+                writeln!(out, "{:indent$}<broken record: {:?}>", "", error, indent = indent)
+            },
+        };
+    }
+}
+
+fn json_records(records: Records) -> String {
+    let records = records
+        .map(|record| match record {
+            Ok(Record::KeyValue { key, value }) => format!(
+                "{{\"type\":\"key_value\",\"key\":{},\"value\":{}}}",
+                json_string(key), json_string(value)
+            ),
+            Ok(Record::Blob { key, data }) => format!(
+                "{{\"type\":\"blob\",\"key\":{},\"data\":\"{}\"}}",
+                json_string(key), hex(data)
+            ),
+            Ok(Record::Section { name, records }) => format!(
+                "{{\"type\":\"section\",\"name\":{},\"records\":{}}}",
+                json_string(name), json_records(records)
+            ),
+            Ok(Record::Unknown { kind, data }) => format!(
+                "{{\"type\":\"unknown\",\"kind\":{},\"data\":\"{}\"}}",
+                kind, hex(data)
+            ),
+            Err(error) => format!(
+                "{{\"type\":\"error\",\"error\":{}}}",
+                json_string(&format!("{:?}", error))
+            ),
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", records.join(","))
+}
+
+fn json_location(location: &Location) -> String {
+    match &location.result {
+        Ok((address, length)) => format!(
+            "{{\"source\":\"{}\",\"address\":{},\"length\":{}}}",
+            location.source.name(),
+            address,
+            length.map_or(String::from("null"), |length| length.to_string())
+        ),
+        Err(error) => format!(
+            "{{\"source\":\"{}\",\"error\":{}}}",
+            location.source.name(),
+            json_string(error)
+        ),
+    }
+}
+
+fn run(options: &Options) -> Result<bool> {
+    let locations = options.sources
+        .iter()
+        .map(|&source| locate(source, &options.mem))
+        .collect::<Vec<_>>();
+
+    let found = locations.iter()
+        .filter_map(|location| location.result.as_ref().ok())
+        .copied()
+        .collect::<Vec<_>>();
+    let consistent = found.windows(2).all(|pair| pair[0].0 == pair[1].0);
+
+    // Prefer a source that also knows the length
+    let target = found.iter()
+        .find(|(_, length)| length.is_some())
+        .or_else(|| found.first())
+        .copied();
+    let data = match (&options.image, target) {
+        (Some(image), _) => Some(read_file(image)),
+        (None, Some((address, length))) => {
+            Some(read_physical(&options.mem, address, length.unwrap_or(PAGE_SIZE)))
+        },
+        (None, None) => None,
+    };
+    let payload = data.as_ref().map(|data| {
+        data.as_ref()
+            .map_err(String::clone)
+            .and_then(|data| Payload::parse(data).map_err(|error| format!("{:?}", error)))
+    });
+
+    if options.json {
+        let locations = locations.iter()
+            .map(json_location)
+            .collect::<Vec<_>>()
+            .join(",");
+        let payload = match &payload {
+            Some(Ok(payload)) => format!(
+                "{{\"version\":{},\"total_size\":{},\"crc32\":{},\"records\":{}}}",
+                payload.version, payload.total_size(), payload.crc32(),
+                json_records(payload.records())
+            ),
+            Some(Err(error)) => format!("{{\"error\":{}}}", json_string(error)),
+            None => String::from("null"),
+        };
+        println!(
+            "{{\"locations\":[{}],\"consistent\":{},\"payload\":{}}}",
+            locations, consistent, payload
+        );
+    } else {
+        for location in &locations {
+            match &location.result {
+                Ok((address, Some(length))) => {
+                    println!("{:<5} {:#x} ({} bytes)", location.source.name(), address, length);
+                },
+                Ok((address, None)) => {
+                    println!("{:<5} {:#x}", location.source.name(), address);
+                },
+                Err(error) => {
+                    println!("{:<5} not found: {}", location.source.name(), error);
+                },
+            }
+        }
+        if !consistent {
+            println!("warning: sources disagree about the BPB address");
+        }
+        match &payload {
+            Some(Ok(payload)) => {
+                println!(
+                    "payload: version {}, {} bytes, crc32 {:#010x}",
+                    payload.version, payload.total_size(), payload.crc32()
+                );
+                let mut records = String::new();
+                text_records(&mut records, payload.records(), 1);
+                print!("{}", records);
+            },
+            Some(Err(error)) => println!("payload: {}", error),
+            None => println!("payload: no source located the BPB"),
+        }
+    }
+
+    Ok(consistent && matches!(payload, Some(Ok(_))))
+}
+
+fn main() {
+    let options = match Options::parse() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("bpbctl: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        },
+    };
+    match run(&options) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            eprintln!("bpbctl: {}", error);
+            process::exit(1);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bpb::payload::PayloadWriter;
+
+    fn payload(buffer: &mut [u8]) -> Payload<'_> {
+        let mut writer = PayloadWriter::new(buffer).unwrap();
+        writer.key_value("version", "1.2").unwrap();
+        writer.blob("bpb.address", &[0x00, 0x10, 0xff]).unwrap();
+        writer.section("boot", |writer| writer.key_value("cmdline", "quiet \"x\"")).unwrap();
+        let size = writer.finish().unwrap();
+        Payload::parse(&buffer[..size]).unwrap()
+    }
+
+    #[test]
+    fn indexes_tables_by_numeric_suffix() {
+        assert_eq!(table_index("SSDT", "SSDT"), Some(0));
+        assert_eq!(table_index("SSDT", "SSDT2"), Some(2));
+        assert_eq!(table_index("SSDT", "SSDT10"), Some(10));
+        assert_eq!(table_index("SSDT", "SSDTX"), None);
+        assert_eq!(table_index("SSDT", "SSDT+1"), None);
+        assert_eq!(table_index("SSDT", "DSDT"), None);
+    }
+
+    #[test]
+    fn lists_tables_in_numeric_order() {
+        let dir = std::env::temp_dir().join(format!("bpbctl-tables-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["SSDT10", "SSDT2", "SSDT1", "SSDTX", "DSDT", "BPBT"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let paths = table_paths_in(&dir, SSDT_SIGNATURE);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names = paths.unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["SSDT1", "SSDT2", "SSDT10"]);
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+        assert_eq!(hex(&[0x00, 0xab]), "00ab");
+    }
+
+    #[test]
+    fn dumps_records_as_text() {
+        let mut buffer = [0; 256];
+        let mut out = String::new();
+        text_records(&mut out, payload(&mut buffer).records(), 1);
+        assert_eq!(out, concat!(
+            "  version = 1.2\n",
+            "  bpb.address = [3 bytes] 0010ff\n",
+            "  [boot]\n",
+            "    cmdline = quiet \"x\"\n",
+        ));
+    }
+
+    #[test]
+    fn dumps_records_as_json() {
+        let mut buffer = [0; 256];
+        assert_eq!(
+            json_records(payload(&mut buffer).records()),
+            concat!(
+                "[{\"type\":\"key_value\",\"key\":\"version\",\"value\":\"1.2\"},",
+                "{\"type\":\"blob\",\"key\":\"bpb.address\",\"data\":\"0010ff\"},",
+                "{\"type\":\"section\",\"name\":\"boot\",\"records\":",
+                "[{\"type\":\"key_value\",\"key\":\"cmdline\",\"value\":\"quiet \\\"x\\\"\"}]}]",
+            )
+        );
+    }
+
+    #[test]
+    fn reports_locations_as_json() {
+        let found = Location { source: Source::Bpbt, result: Ok((0x1000, Some(4096))) };
+        assert_eq!(json_location(&found), "{\"source\":\"bpbt\",\"address\":4096,\"length\":4096}");
+        let missing = Location { source: Source::Variable, result: Err(String::from("gone")) };
+        assert_eq!(json_location(&missing), "{\"source\":\"var\",\"error\":\"gone\"}");
+    }
+}
@@ -5,7 +5,7 @@
     L"RuntimeListHead"
 
 #define MY_VENDOR_GUID \
-    EFI_GUID(0xf08ae394, 0x4e98, 0x46e6, 0xb3, 0xb0, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d)
+    EFI_GUID(0xf08ae394, 0x4e98, 0x46e6, 0xb0, 0xb3, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d)
 
 static int get_list_address(u64 *addr)
 {
@@ -27,7 +27,7 @@ static int get_list_address(u64 *addr)
 
         /* get variable contents into buffer */
         status = efi.get_variable(name, &guid, &attr, &data_size, data);
-        if (status != EFI_SUCCESS) {
+        if (status == EFI_SUCCESS) {
             if (data_size != sizeof(u64)) {
                 goto error;
             }
@@ -35,7 +35,7 @@ typedef struct {
 #define RUNTIME_MAX_MOD 256
 
 #define MY_VAR_PATH                                                     \
-    "/sys/firmware/efi/efivars/RuntimeListHead-f08ae394-4e98-46e6-b3b0-1bb940ac663d"
+    "/sys/firmware/efi/efivars/RuntimeListHead-f08ae394-4e98-46e6-b0b3-1bb940ac663d"
 
 #pragma pack(push, 1)
 
@@ -4,6 +4,7 @@ set -e
 
 ./build.sh
 cp ./target/x86_64-unknown-uefi/debug/bpb-test.efi qemu-hda
+cp bpb.cfg qemu-hda
 
 qemu-system-x86_64 \
     -machine q35 \
@@ -1,586 +1,318 @@
-pub const ACPI_1_FADT_SIGNATURE: u32 = 0x50434146;
-pub const ACPI_1_FADT_REVISION: u8 = 0x01;
+use core::{mem, slice};
+use core::ffi::c_void;
+use alloc::vec::Vec;
+use uefi::prelude::*;
+use uefi::table::boot::MemoryType;
+use uefi::table::cfg::{ACPI_GUID, ACPI2_GUID};
+use uefi::proto::acpi_table::AcpiTable;
 
-pub const ACPI_3_FADT_SIGNATURE: u32 = 0x50434146;
-pub const ACPI_3_FADT_REVISION: u8 = 0x04;
+pub use bpb::acpi::*;
 
-/// RSD_PTR Revision
-pub const ACPI_1_RSDP_REVISION: u8 = 0x01;
-pub const ACPI_2_RSDP_REVISION: u8 = 0x02;
+use crate::acpi_sdt::{Sdt, SdtTable};
+use crate::error::{check, Context, Error, Result};
 
-/// The common ACPI description table header. This
-/// structure prefaces most ACPI tables.
-#[repr(C, packed)]
-pub struct DescriptionHeader {
-    pub signature: u32,
-    pub length: u32,
-    pub revision: u8,
-    pub checksum: u8,
-    pub oem_id: [u8; 6],
-    pub oem_table_id: u64,
-    pub oem_revision: u32,
-    pub creator_id: u32,
-    pub creator_revision: u32
+pub fn parse_status(error: ParseError) -> Status {
+    match error {
+        ParseError::Truncated | ParseError::BadLength => Status::BAD_BUFFER_SIZE,
+        ParseError::BadSignature => Status::UNSUPPORTED,
+        ParseError::BadChecksum | ParseError::BadExtendedChecksum => Status::CRC_ERROR,
+        ParseError::Unmapped(_) | ParseError::NotFound(_) => Status::NOT_FOUND,
+    }
+}
+
+/// Identity-mapped physical memory holding a description table.
+fn physical_table(address: u64) -> Option<&'static [u8]> {
+    // SAFETY: firmware identity-maps memory during boot services
+    // and tables found through the RSDP are expected to be valid
+    unsafe {
+        let length = (address as *const u8)
+            .add(4)
+            .cast::<u32>()
+            .read_unaligned() as usize;
+        if length < mem::size_of::<DescriptionHeader>() {
+            return None;
+        }
+        Some(slice::from_raw_parts(address as *const u8, length))
+    }
+}
+
+pub type PlatformTables = AcpiTables<'static, fn(u64) -> Option<&'static [u8]>>;
+
+/// Locate the RSDP in the system configuration table and parse
+/// the description tables behind it.
+pub fn platform_tables() -> Result<PlatformTables> {
+    let st = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+    };
+    let rsdp_ptr = st.config_table()
+        .iter()
+        .find(|entry| entry.guid == ACPI2_GUID)
+        .or_else(|| {
+            st.config_table()
+                .iter()
+                .find(|entry| entry.guid == ACPI_GUID)
+        });
+    let rsdp_ptr = match rsdp_ptr {
+        Some(rsdp_ptr) => rsdp_ptr.address as *const u8,
+        None => return Err(Error::RsdpNotFound),
+    };
+
+    // SAFETY: the RSDP is at least ACPI_1_RSDP_LENGTH long and only
+    // revision 2 and up carry a length field
+    let rsdp = unsafe {
+        let length = if *rsdp_ptr.add(15) >= ACPI_2_RSDP_REVISION {
+            rsdp_ptr.add(20)
+                .cast::<u32>()
+                .read_unaligned() as usize
+        } else {
+            ACPI_1_RSDP_LENGTH
+        };
+        slice::from_raw_parts(rsdp_ptr, length)
+    };
+
+    let map: fn(u64) -> Option<&'static [u8]> = physical_table;
+    AcpiTables::new(rsdp, map)
+        .map_err(Error::BadRootTables)
+}
+
+/// `creator_id` of every table we build.
+pub const CREATOR_ID: u32 = 0x2042_5042;                  // "BPB "
+
+/// `creator_revision` of every table we build: the bpb-test version
+/// as `0xMMmmpp`, see `build.rs`.
+pub fn creator_revision() -> u32 {
+    env!("BPB_CREATOR_REVISION").parse().unwrap_or(0)
+}
+
+/// Where the OEM fields of an installed table come from.
+#[derive(Clone, Copy, Debug)]
+pub enum OemIdentity {
+    /// Copy `oem_id`, `oem_table_id` and `oem_revision` from
+    /// the platform's root system description table.
+    Inherit,
+    /// Use the given values as is.
+    Explicit {
+        oem_id: [u8; 6],
+        oem_table_id: u64,
+        oem_revision: u32,
+    },
 }
 
-/// Root System Description Pointer Structure
-#[repr(C, packed)]
-pub struct RootSystemDescriptionPointer3 {
-    pub signature: u64,
-    pub checksum: u8,
-    pub oem_id: [u8; 6],
-    pub revision: u8,
-    pub rsdt_address: u32,
-    pub length: u32,
-    pub xsdt_address: u64,
-    pub extended_checksum: u8,
-    pub reserved: [u8; 3]
+/// A table installed through `EFI_ACPI_TABLE_PROTOCOL`.
+#[derive(Clone, Copy, Debug)]
+pub struct InstalledTable {
+    pub signature: u32,
+    pub length: usize,
+    pub key: usize,
 }
 
-/// ACPI 3.0 Generic Address Space Address IDs
-pub const ACPI_3_SYSTEM_MEMORY: u8 = 0x0;
-pub const ACPI_3_SYSTEM_IO: u8 = 0x1;
-pub const ACPI_3_PCI_CONFIGURATION_SPACE: u8 = 0x2;
-pub const ACPI_3_EMBEDDED_CONTROLLER: u8 = 0x3;
-pub const ACPI_3_SMBUS: u8 = 0x4;
-pub const ACPI_3_FUNCTIONAL_FIXED_HARDWARE: u8 = 0x7f;
-
-/// ACPI 3.0 Generic Address Space definition
-#[repr(C, packed)]
-pub struct GenericAddressSpace {
-    pub address_space_id: u8,
-    pub register_bit_width: u8,
-    pub register_bit_offset: u8,
-    pub access_size: u8,
-    pub address: u64,
+/// Builds a description table from a signature, a revision,
+/// an OEM identity and a body, then checksums, validates and
+/// installs it.
+pub struct TableBuilder<'a> {
+    signature: u32,
+    revision: u8,
+    oem: OemIdentity,
+    body: &'a [u8],
 }
 
-impl GenericAddressSpace {
-    pub fn new() -> GenericAddressSpace {
-        GenericAddressSpace {
-            address_space_id: 0,
-            register_bit_width: 0,
-            register_bit_offset: 0,
-            access_size: 0,
-            address: 0,
+impl<'a> TableBuilder<'a> {
+    pub fn new(signature: u32, revision: u8) -> TableBuilder<'a> {
+        TableBuilder {
+            signature,
+            revision,
+            oem: OemIdentity::Inherit,
+            body: &[],
+        }
+    }
+
+    pub fn oem(mut self, oem: OemIdentity) -> Self {
+        self.oem = oem;
+        self
+    }
+
+    /// Bytes that follow the description header.
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// A fixed structure that follows the description header.
+    pub fn body_struct<T: Plain>(self, body: &'a T) -> Self {
+        self.body(as_bytes(body))
+    }
+
+    /// A fixed structure that starts with its own description
+    /// header. The header is replaced by the one built here.
+    pub fn table<T: Plain>(self, table: &'a T) -> Self {
+        let bytes = as_bytes(table);
+        assert!(bytes.len() >= mem::size_of::<DescriptionHeader>());
+        self.body(&bytes[mem::size_of::<DescriptionHeader>()..])
+    }
+
+    /// Assemble the table with its checksum filled in.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let table_size = mem::size_of::<DescriptionHeader>() + self.body.len();
+        if table_size > u32::MAX as usize {
+            return Err(Error::TableTooLarge { signature: self.signature, size: table_size });
         }
+
+        let (oem_id, oem_table_id, oem_revision) = match self.oem {
+            OemIdentity::Inherit => {
+                let sdt = root_sdt_header()?;
+                (sdt.oem_id, sdt.oem_table_id, sdt.oem_revision)
+            },
+            OemIdentity::Explicit { oem_id, oem_table_id, oem_revision } => {
+                (oem_id, oem_table_id, oem_revision)
+            },
+        };
+
+        let header = DescriptionHeader {
+            signature: self.signature,
+            length: table_size as u32,
+            revision: self.revision,
+            checksum: 0,
+            oem_id,
+            oem_table_id,
+            oem_revision,
+            creator_id: CREATOR_ID,
+            creator_revision: creator_revision()
+        };
+
+        let mut table = Vec::with_capacity(table_size);
+        table.extend_from_slice(as_bytes(&header));
+        table.extend_from_slice(self.body);
+        table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = checksum(&table);
+
+        // Entire table must sum to zero
+        validate_table(&table)
+            .map_err(|error| Error::table(self.signature, error))?;
+
+        Ok(table)
+    }
+
+    pub fn install(&self) -> Result<InstalledTable> {
+        install_table(&self.build()?)
+    }
+}
+
+/// Installs a complete, checksummed table through
+/// `EFI_ACPI_TABLE_PROTOCOL`.
+pub fn install_table(table_data: &[u8]) -> Result<InstalledTable> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    let acpi = bs
+        .locate_protocol::<AcpiTable>()
+        .ignore_warning()
+        .map_err(|error| Error::AcpiProtocolMissing(error.status()))?;
+    let acpi = unsafe { &mut *acpi.get() };
+
+    let signature = u32::from_le_bytes([table_data[0], table_data[1], table_data[2], table_data[3]]);
+    let acpi_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
+    let acpi_table_size = table_data.len();
+    let acpi_table = bs.allocate_pool(acpi_table_pool, acpi_table_size)
+        .ignore_warning()
+        .map_err(|error| Error::PoolAllocationFailed {
+            memory_type: acpi_table_pool,
+            size: acpi_table_size,
+            status: error.status(),
+        })?;
+    info!("acpi_table: {:?}", acpi_table);
+
+    // SAFETY: acpi_table is a fresh pool of acpi_table_size bytes
+    unsafe {
+        bs.memmove(
+            acpi_table,
+            table_data.as_ptr(),
+            acpi_table_size
+        );
     }
+
+    let table_key = unsafe {
+        acpi.install_acpi_table(acpi_table as *const c_void, acpi_table_size)
+            .ignore_warning()
+            .map_err(|error| Error::TableInstallFailed {
+                signature,
+                status: error.status(),
+            })
+    };
+    // The protocol keeps its own copy of the table either way
+    bs.free_pool(acpi_table)
+        .or_warn("free_pool (acpi_table)");
+    let table_key = table_key?;
+
+    Ok(InstalledTable {
+        signature,
+        length: acpi_table_size,
+        key: table_key,
+    })
+}
+
+/// A table that took the place of an installed one, and the
+/// original to put back on rollback.
+pub struct ReplacedTable {
+    pub table: InstalledTable,
+    pub original: Vec<u8>,
+}
+
+/// Uninstalls the table under `key` and installs `replacement`. If
+/// that fails, `original` is installed again.
+pub fn replace_table(key: usize, original: Vec<u8>, replacement: &[u8]) -> Result<ReplacedTable> {
+    uninstall_table(key)?;
+    match install_table(replacement) {
+        Ok(table) => Ok(ReplacedTable { table, original }),
+        Err(error) => {
+            if let Err(error) = install_table(&original) {
+                error!("reinstalling the original table: {}", error);
+            }
+            Err(error)
+        },
+    }
+}
+
+/// `EFI_ACPI_TABLE_PROTOCOL` with UninstallAcpiTable, which uefi-rs
+/// does not expose.
+#[repr(C)]
+struct RawAcpiTable {
+    install_acpi_table: usize,
+    uninstall_acpi_table: extern "efiapi" fn(this: *mut RawAcpiTable, table_key: usize) -> Status,
+}
+
+/// Removes a table installed through `EFI_ACPI_TABLE_PROTOCOL`.
+pub fn uninstall_table(key: usize) -> Result {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let acpi = bs
+        .locate_protocol::<AcpiTable>()
+        .ignore_warning()
+        .map_err(|error| Error::AcpiProtocolMissing(error.status()))?;
+    let acpi = acpi.get() as *mut RawAcpiTable;
+    let status = unsafe { ((*acpi).uninstall_acpi_table)(acpi, key) };
+    check(status, "UninstallAcpiTable")
 }
 
-/// Fixed ACPI Description Table Structure (FADT).
-#[repr(C, packed)]
-pub struct FixedDescriptionTable3 {
-    pub header: DescriptionHeader,
-    // Physical memory address of the FACS, where OSPM and
-    // Firmware exchange control information. See Section
-    // 5.2.6, “Root System Description Table,” for a
-    // description of the FACS. If the X_FIRMWARE_CTRL field
-    // contains a non zero value then this field must be
-    // zero. A zero value indicates that no FACS is
-    // specified by this field
-    pub firmware_ctrl: u32,
-    // Physical memory address (0-4 GB) of the DSDT.
-    pub dsdt: u32,
-    // ACPI 1.0 defined this offset as a field named
-    // INT_MODEL, which was eliminated in ACPI
-    // 2.0. Platforms should set this field to zero but
-    // field values of one are also allowed to maintain
-    // compatibility with ACPI 1.0.
-    pub reserved0: u8,
-    // This field is set by the OEM to convey the preferred
-    // power management profile to OSPM. OSPM can use this
-    // field to set default power management policy
-    // parameters during OS installation.
-    pub preferred_pm_profile: u8,
-    // System vector the SCI interrupt is wired to in 8259
-    // mode. On systems that do not contain the 8259, this
-    // field contains the Global System interrupt number of
-    // the SCI interrupt. OSPM is required to treat the ACPI
-    // SCI interrupt as a sharable, level, active low
-    // interrupt.
-    pub sci_int: u16,
-    // System port address of the SMI Command Port. During
-    // ACPI OS initialization, OSPM can determine that the
-    // ACPI hardware registers are owned by SMI (by way of
-    // the SCI_EN bit), in which case the ACPI OS issues the
-    // ACPI_ENABLE command to the SMI_CMD port. The SCI_EN
-    // bit effectively tracks the ownership of the ACPI
-    // hardware registers. OSPM issues commands to the
-    // SMI_CMD port synchronously from the boot
-    // processor. This field is reserved and must be zero on
-    // system that does not support System Management mode
-    pub smi_cmd: u32,
-    // The value to write to SMI_CMD to disable SMI
-    // ownership of the ACPI hardware registers. The last
-    // action SMI does to relinquish ownership is to set the
-    // SCI_EN bit. During the OS initialization process,
-    // OSPM will synchronously wait for the transfer of SMI
-    // ownership to complete, so the ACPI system releases
-    // SMI ownership as quickly as possible. This field is
-    // reserved and must be zero on systems that do not
-    // support Legacy Mode.
-    pub acpi_enable: u8,
-    // The value to write to SMI_CMD to re-enable SMI
-    // ownership of the ACPI hardware registers. This can
-    // only be done when ownership was originally acquired
-    // from SMI by OSPM using ACPI_ENABLE. An OS can hand
-    // ownership back to SMI by relinquishing use to the
-    // ACPI hardware registers, masking off all SCI
-    // interrupts, clearing the SCI_EN bit and then writing
-    // ACPI_DISABLE to the SMI_CMD port from the boot
-    // processor. This field is reserved and must be zero on
-    // systems that do not support Legacy Mode
-    pub acpi_disable: u8,
-    // The value to write to SMI_CMD to enter the S4BIOS
-    // state.  The S4BIOS state provides an alternate way to
-    // enter the S4 state where the firmware saves and
-    // restores the memory context. A value of zero in
-    // S4BIOS_F indicates S4BIOS_REQ is not supported. (See
-    // Table 5-38)
-    pub s4_bios_req: u8,
-    // If non-zero, this field contains the value OSPM
-    // writes to the SMI_CMD register to assume processor
-    // performance state control responsibility.
-    pub pstate_cnt: u8,
-    // System port address of the PM1a Event Register
-    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
-    // a hardware description layout of this register
-    // block. This is a required field. This field is
-    // superseded by the X_PM1a_EVT_BLK field.
-    pub pm1a_evt_blk: u32,
-    // System port address of the PM1b Event Register
-    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
-    // a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM1b_EVT_BLK field.
-    pub pm1b_evt_blk: u32,
-    // System port address of the PM1a Control Register
-    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
-    // for a hardware description layout of this register
-    // block. This is a required field. This field is
-    // superseded by the X_PM1a_CNT_BLK field.
-    pub pm1a_cnt_blk: u32,
-    // System port address of the PM1b Control Register
-    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
-    // for a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM1b_CNT_BLK field.
-    pub pm1b_cnt_blk: u32,
-    // System port address of the PM2 Control Register
-    // Block. See Section 4.8.3.4, “PM2 Control (PM2_CNT),”
-    // for a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM2_CNT_BLK field.
-    pub pm2_cnt_blk: u32,
-    // System port address of the Power Management Timer
-    // Control Register Block. See Section 4.8.3.3, “Power
-    // Management Timer (PM_TMR),” for a hardware
-    // description layout of this register block. This is an
-    // optional field; if this register block is not
-    // supported, this field contains zero. This field is
-    // superseded by the X_PM_TMR_BLK field.
-    pub pm_tmr_blk: u32,
-    // System port address of General-Purpose Event 0
-    // Register Block. See Section 4.8.4.1, “General-Purpose
-    // Event Register Blocks,” for a hardware description of
-    // this register block. This is an optional field; if
-    // this register block is not supported, this field
-    // contains zero. This field is superseded by the
-    // X_GPE0_BLK field.
-    pub gpe0_blk: u32,
-    // System port address of General-Purpose Event 1
-    // Register Block. See Section 4.8.4.1, “General-Purpose
-    // Event Register Blocks,” for a hardware description of
-    // this register block. This is an optional field; if
-    // this register block is not supported, this field
-    // contains zero. This field is superseded by the
-    // X_GPE1_BLK field.
-    pub gpe1_blk: u32,
-    // Number of bytes decoded by PM1a_EVT_BLK and, if
-    // supported, PM1b_EVT_BLK. This value is >= 4.
-    pub pm1_evt_len: u8,
-    // Number of bytes decoded by PM1a_CNT_BLK and, if
-    // supported, PM1b_CNT_BLK. This value is >= 2.
-    pub pm1_cnt_len: u8,
-    // Number of bytes decoded by PM2_CNT_BLK. Support for
-    // the PM2 register block is optional. If supported,
-    // this value is >= 1. If not supported, this field
-    // contains zero.
-    pub pm2_cnt_len: u8,
-    // Number of bytes decoded by PM_TMR_BLK. If the PM
-    // Timer is supported, this field’s value must be 4. If
-    // not supported, this field contains zero.
-    pub pm_tm_len: u8,
-    // Number of bytes decoded by GPE0_BLK. The value is a
-    // non- negative multiple of 2.
-    pub gpe0_blk_len: u8,
-    // Number of bytes decoded by GPE1_BLK. The value is a
-    // non- negative multiple of 2.
-    pub gpe1_blk_len: u8,
-    // Offset within the ACPI general-purpose event model
-    // where GPE1 based events start.
-    pub gpe1_base: u8,
-    // If non-zero, this field contains the value OSPM
-    // writes to the SMI_CMD register to indicate OS support
-    // for the _CST object and C States Changed
-    // notification.
-    pub cst_cnt: u8,
-    // The worst-case hardware latency, in microseconds, to
-    // enter and exit a C2 state. A value > 100 indicates
-    // the system does not support a C2 state.
-    pub p_lvl2_lat: u16,
-    // The worst-case hardware latency, in microseconds, to
-    // enter and exit a C3 state. A value > 1000 indicates
-    // the system does not support a C3 state.
-    pub p_lvl3_lat: u16,
-    // If WBINVD=0, the value of this field is the number of
-    // flush strides that need to be read (using cacheable
-    // addresses) to completely flush dirty lines from any
-    // processor’s memory caches. Notice that the value in
-    // FLUSH_STRIDE is typically the smallest cache line
-    // width on any of the processor’s caches (for more
-    // information, see the FLUSH_STRIDE field
-    // definition). If the system does not support a method
-    // for flushing the processor’s caches, then FLUSH_SIZE
-    // and WBINVD are set to zero. Notice that this method
-    // of flushing the processor caches has limitations, and
-    // WBINVD=1 is the preferred way to flush the processors
-    // caches. This value is typically at least 2 times the
-    // cache size. The maximum allowed value for FLUSH_SIZE
-    // multiplied by FLUSH_STRIDE is 2 MB for a typical
-    // maximum supported cache size of 1 MB. Larger cache
-    // sizes are supported using WBINVD=1.  This value is
-    // ignored if WBINVD=1.  This field is maintained for
-    // ACPI 1.0 processor compatibility on existing
-    // systems. Processors in new ACPI-compatible systems
-    // are required to support the WBINVD function and
-    // indicate this to OSPM by setting the WBINVD field =
-    // 1.
-    pub flush_size: u16,
-    // If WBINVD=0, the value of this field is the cache
-    // line width, in bytes, of the processor’s memory
-    // caches. This value is typically the smallest cache
-    // line width on any of the processor’s caches. For more
-    // information, see the description of the FLUSH_SIZE
-    // field.  This value is ignored if WBINVD=1.  This
-    // field is maintained for ACPI 1.0 processor
-    // compatibility on existing systems. Processors in new
-    // ACPI-compatible systems are required to support the
-    // WBINVD function and indicate this to OSPM by setting
-    // the WBINVD field = 1.
-    pub flush_stride: u16,
-    // The zero-based index of where the processor’s duty
-    // cycle setting is within the processor’s P_CNT
-    // register.
-    pub duty_offset: u8,
-    // The bit width of the processor’s duty cycle setting
-    // value in the P_CNT register. Each processor’s duty
-    // cycle setting allows the software to select a nominal
-    // processor frequency below its absolute frequency as
-    // defined by: THTL_EN = 1 BF * DC/(2 DUTY_WIDTH )
-    // Where: BF–Base frequency DC–Duty cycle setting When
-    // THTL_EN is 0, the processor runs at its absolute
-    // BF. A DUTY_WIDTH value of 0 indicates that processor
-    // duty cycle is not supported and the processor
-    // continuously runs at its base frequency.
-    pub duty_width: u8,
-    // The RTC CMOS RAM index to the day-of-month alarm
-    // value.  If this field contains a zero, then the RTC
-    // day of the month alarm feature is not supported. If
-    // this field has a non-zero value, then this field
-    // contains an index into RTC RAM space that OSPM can
-    // use to program the day of the month alarm.  See
-    // Section 4.8.2.4 “Real Time Clock Alarm,” for a
-    // description of how the hardware works.
-    pub day_alrm: u8,
-    // The RTC CMOS RAM index to the month of year alarm
-    // value.  If this field contains a zero, then the RTC
-    // month of the year alarm feature is not supported. If
-    // this field has a non-zero value, then this field
-    // contains an index into RTC RAM space that OSPM can
-    // use to program the month of the year alarm. If this
-    // feature is supported, then the DAY_ALRM feature must
-    // be supported also.
-    pub mon_alrm: u8,
-    // The RTC CMOS RAM index to the century of data value
-    // (hundred and thousand year decimals). If this field
-    // contains a zero, then the RTC centenary feature is
-    // not supported. If this field has a non-zero value,
-    // then this field contains an index into RTC RAM space
-    // that OSPM can use to program the centenary field.
-    pub century: u8,
-    // IA-PC Boot Architecture Flags. See Table 5-36 for a
-    // description of this field.
-    pub iapc_boot_arch: u16,
-    // Must be 0.
-    pub reserved1: u8,
-    // Fixed feature flags. See Table 5-35 for a description
-    // of this field.
-    pub flags: u32,
-    pub reset_reg: GenericAddressSpace,
-    pub reset_value: u8,
-    pub reserved2: u8,
-    pub reserved3: u8,
-    pub reserved4: u8,
-    pub x_firmware_ctrl: u64,
-    pub x_dsdt: u64,
-    pub x_pm1a_evt_blk: GenericAddressSpace,
-    pub x_pm1b_evt_blk: GenericAddressSpace,
-    pub x_pm1a_cnt_blk: GenericAddressSpace,
-    pub x_pm1b_cnt_blk: GenericAddressSpace,
-    pub x_pm2_cnt_blk: GenericAddressSpace,
-    pub x_pm_tmr_blk: GenericAddressSpace,
-    pub x_gpe0_blk: GenericAddressSpace,
-    pub x_gpe1_blk: GenericAddressSpace,
+/// Keys of the installed tables `matches` picks, found through
+/// `EFI_ACPI_SDT_PROTOCOL`.
+pub fn find_installed(matches: impl Fn(&[u8]) -> bool) -> Result<Vec<InstalledTable>> {
+    Ok(Sdt::locate()?
+        .tables()?
+        .iter()
+        .filter(|table| matches(table.bytes()))
+        .map(SdtTable::installed)
+        .collect())
 }
 
-/// Fixed ACPI Description Table Structure (FADT).
-#[repr(C, packed)]
-pub struct FixedDescriptionTable1 {
-    pub header: DescriptionHeader,
-    // Physical memory address of the FACS, where OSPM and
-    // Firmware exchange control information. See Section
-    // 5.2.6, “Root System Description Table,” for a
-    // description of the FACS. If the X_FIRMWARE_CTRL field
-    // contains a non zero value then this field must be
-    // zero. A zero value indicates that no FACS is
-    // specified by this field
-    pub firmware_ctrl: u32,
-    // Physical memory address (0-4 GB) of the DSDT.
-    pub dsdt: u32,
-    // ACPI 1.0 defined this offset as a field named
-    // INT_MODEL, which was eliminated in ACPI
-    // 2.0. Platforms should set this field to zero but
-    // field values of one are also allowed to maintain
-    // compatibility with ACPI 1.0.
-    pub int_model: u8,
-    // Must be zero.
-    pub reserved0: u8,
-    // System vector the SCI interrupt is wired to in 8259
-    // mode. On systems that do not contain the 8259, this
-    // field contains the Global System interrupt number of
-    // the SCI interrupt. OSPM is required to treat the ACPI
-    // SCI interrupt as a sharable, level, active low
-    // interrupt.
-    pub sci_int: u16,
-    // System port address of the SMI Command Port. During
-    // ACPI OS initialization, OSPM can determine that the
-    // ACPI hardware registers are owned by SMI (by way of
-    // the SCI_EN bit), in which case the ACPI OS issues the
-    // ACPI_ENABLE command to the SMI_CMD port. The SCI_EN
-    // bit effectively tracks the ownership of the ACPI
-    // hardware registers. OSPM issues commands to the
-    // SMI_CMD port synchronously from the boot
-    // processor. This field is reserved and must be zero on
-    // system that does not support System Management mode
-    pub smi_cmd: u32,
-    // The value to write to SMI_CMD to disable SMI
-    // ownership of the ACPI hardware registers. The last
-    // action SMI does to relinquish ownership is to set the
-    // SCI_EN bit. During the OS initialization process,
-    // OSPM will synchronously wait for the transfer of SMI
-    // ownership to complete, so the ACPI system releases
-    // SMI ownership as quickly as possible. This field is
-    // reserved and must be zero on systems that do not
-    // support Legacy Mode.
-    pub acpi_enable: u8,
-    // The value to write to SMI_CMD to re-enable SMI
-    // ownership of the ACPI hardware registers. This can
-    // only be done when ownership was originally acquired
-    // from SMI by OSPM using ACPI_ENABLE. An OS can hand
-    // ownership back to SMI by relinquishing use to the
-    // ACPI hardware registers, masking off all SCI
-    // interrupts, clearing the SCI_EN bit and then writing
-    // ACPI_DISABLE to the SMI_CMD port from the boot
-    // processor. This field is reserved and must be zero on
-    // systems that do not support Legacy Mode
-    pub acpi_disable: u8,
-    // The value to write to SMI_CMD to enter the S4BIOS
-    // state.  The S4BIOS state provides an alternate way to
-    // enter the S4 state where the firmware saves and
-    // restores the memory context. A value of zero in
-    // S4BIOS_F indicates S4BIOS_REQ is not supported. (See
-    // Table 5-38)
-    pub s4_bios_req: u8,
-    // Must be zero.
-    pub reserved1: u8,
-    // System port address of the PM1a Event Register
-    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
-    // a hardware description layout of this register
-    // block. This is a required field. This field is
-    // superseded by the X_PM1a_EVT_BLK field.
-    pub pm1a_evt_blk: u32,
-    // System port address of the PM1b Event Register
-    // Block. See Section 4.8.3.1, “PM1 Event Grouping,” for
-    // a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM1b_EVT_BLK field.
-    pub pm1b_evt_blk: u32,
-    // System port address of the PM1a Control Register
-    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
-    // for a hardware description layout of this register
-    // block. This is a required field. This field is
-    // superseded by the X_PM1a_CNT_BLK field.
-    pub pm1a_cnt_blk: u32,
-    // System port address of the PM1b Control Register
-    // Block. See Section 4.8.3.2, “PM1 Control Grouping,”
-    // for a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM1b_CNT_BLK field.
-    pub pm1b_cnt_blk: u32,
-    // System port address of the PM2 Control Register
-    // Block. See Section 4.8.3.4, “PM2 Control (PM2_CNT),”
-    // for a hardware description layout of this register
-    // block. This field is optional; if this register block
-    // is not supported, this field contains zero.  This
-    // field is superseded by the X_PM2_CNT_BLK field.
-    pub pm2_cnt_blk: u32,
-    // System port address of the Power Management Timer
-    // Control Register Block. See Section 4.8.3.3, “Power
-    // Management Timer (PM_TMR),” for a hardware
-    // description layout of this register block. This is an
-    // optional field; if this register block is not
-    // supported, this field contains zero. This field is
-    // superseded by the X_PM_TMR_BLK field.
-    pub pm_tmr_blk: u32,
-    // System port address of General-Purpose Event 0
-    // Register Block. See Section 4.8.4.1, “General-Purpose
-    // Event Register Blocks,” for a hardware description of
-    // this register block. This is an optional field; if
-    // this register block is not supported, this field
-    // contains zero. This field is superseded by the
-    // X_GPE0_BLK field.
-    pub gpe0_blk: u32,
-    // System port address of General-Purpose Event 1
-    // Register Block. See Section 4.8.4.1, “General-Purpose
-    // Event Register Blocks,” for a hardware description of
-    // this register block. This is an optional field; if
-    // this register block is not supported, this field
-    // contains zero. This field is superseded by the
-    // X_GPE1_BLK field.
-    pub gpe1_blk: u32,
-    // Number of bytes decoded by PM1a_EVT_BLK and, if
-    // supported, PM1b_EVT_BLK. This value is >= 4.
-    pub pm1_evt_len: u8,
-    // Number of bytes decoded by PM1a_CNT_BLK and, if
-    // supported, PM1b_CNT_BLK. This value is >= 2.
-    pub pm1_cnt_len: u8,
-    // Number of bytes decoded by PM2_CNT_BLK. Support for
-    // the PM2 register block is optional. If supported,
-    // this value is >= 1. If not supported, this field
-    // contains zero.
-    pub pm2_cnt_len: u8,
-    // Number of bytes decoded by PM_TMR_BLK. If the PM
-    // Timer is supported, this field’s value must be 4. If
-    // not supported, this field contains zero.
-    pub pm_tm_len: u8,
-    // Number of bytes decoded by GPE0_BLK. The value is a
-    // non- negative multiple of 2.
-    pub gpe0_blk_len: u8,
-    // Number of bytes decoded by GPE1_BLK. The value is a
-    // non- negative multiple of 2.
-    pub gpe1_blk_len: u8,
-    // Offset within the ACPI general-purpose event model
-    // where GPE1 based events start.
-    pub gpe1_base: u8,
-    // Must be 0.
-    pub reserved2: u8,
-    // The worst-case hardware latency, in microseconds, to
-    // enter and exit a C2 state. A value > 100 indicates
-    // the system does not support a C2 state.
-    pub p_lvl2_lat: u16,
-    // The worst-case hardware latency, in microseconds, to
-    // enter and exit a C3 state. A value > 1000 indicates
-    // the system does not support a C3 state.
-    pub p_lvl3_lat: u16,
-    // If WBINVD=0, the value of this field is the number of
-    // flush strides that need to be read (using cacheable
-    // addresses) to completely flush dirty lines from any
-    // processor’s memory caches. Notice that the value in
-    // FLUSH_STRIDE is typically the smallest cache line
-    // width on any of the processor’s caches (for more
-    // information, see the FLUSH_STRIDE field
-    // definition). If the system does not support a method
-    // for flushing the processor’s caches, then FLUSH_SIZE
-    // and WBINVD are set to zero. Notice that this method
-    // of flushing the processor caches has limitations, and
-    // WBINVD=1 is the preferred way to flush the processors
-    // caches. This value is typically at least 2 times the
-    // cache size. The maximum allowed value for FLUSH_SIZE
-    // multiplied by FLUSH_STRIDE is 2 MB for a typical
-    // maximum supported cache size of 1 MB. Larger cache
-    // sizes are supported using WBINVD=1.  This value is
-    // ignored if WBINVD=1.  This field is maintained for
-    // ACPI 1.0 processor compatibility on existing
-    // systems. Processors in new ACPI-compatible systems
-    // are required to support the WBINVD function and
-    // indicate this to OSPM by setting the WBINVD field =
-    // 1.
-    pub flush_size: u16,
-    // If WBINVD=0, the value of this field is the cache
-    // line width, in bytes, of the processor’s memory
-    // caches. This value is typically the smallest cache
-    // line width on any of the processor’s caches. For more
-    // information, see the description of the FLUSH_SIZE
-    // field.  This value is ignored if WBINVD=1.  This
-    // field is maintained for ACPI 1.0 processor
-    // compatibility on existing systems. Processors in new
-    // ACPI-compatible systems are required to support the
-    // WBINVD function and indicate this to OSPM by setting
-    // the WBINVD field = 1.
-    pub flush_stride: u16,
-    // The zero-based index of where the processor’s duty
-    // cycle setting is within the processor’s P_CNT
-    // register.
-    pub duty_offset: u8,
-    // The bit width of the processor’s duty cycle setting
-    // value in the P_CNT register. Each processor’s duty
-    // cycle setting allows the software to select a nominal
-    // processor frequency below its absolute frequency as
-    // defined by: THTL_EN = 1 BF * DC/(2 DUTY_WIDTH )
-    // Where: BF–Base frequency DC–Duty cycle setting When
-    // THTL_EN is 0, the processor runs at its absolute
-    // BF. A DUTY_WIDTH value of 0 indicates that processor
-    // duty cycle is not supported and the processor
-    // continuously runs at its base frequency.
-    pub duty_width: u8,
-    // The RTC CMOS RAM index to the day-of-month alarm
-    // value.  If this field contains a zero, then the RTC
-    // day of the month alarm feature is not supported. If
-    // this field has a non-zero value, then this field
-    // contains an index into RTC RAM space that OSPM can
-    // use to program the day of the month alarm.  See
-    // Section 4.8.2.4 “Real Time Clock Alarm,” for a
-    // description of how the hardware works.
-    pub day_alrm: u8,
-    // The RTC CMOS RAM index to the month of year alarm
-    // value.  If this field contains a zero, then the RTC
-    // month of the year alarm feature is not supported. If
-    // this field has a non-zero value, then this field
-    // contains an index into RTC RAM space that OSPM can
-    // use to program the month of the year alarm. If this
-    // feature is supported, then the DAY_ALRM feature must
-    // be supported also.
-    pub mon_alrm: u8,
-    // The RTC CMOS RAM index to the century of data value
-    // (hundred and thousand year decimals). If this field
-    // contains a zero, then the RTC centenary feature is
-    // not supported. If this field has a non-zero value,
-    // then this field contains an index into RTC RAM space
-    // that OSPM can use to program the centenary field.
-    pub century: u8,
-    // Must be 0.
-    pub reserved3: u8,
-    // Must be 0.
-    pub reserved4: u8,
-    // Must be 0.
-    pub reserved5: u8,
-    // Fixed feature flags. See Table 5-35 for a description
-    // of this field.
-    pub flags: u32,
+/// Read the header of the XSDT, or of the RSDT when the RSDP
+/// does not provide an XSDT.
+pub fn root_sdt_header() -> Result<DescriptionHeader> {
+    let tables = platform_tables()?;
+    Ok(tables.root.header)
 }
@@ -0,0 +1,309 @@
+//! `EFI_ACPI_SDT_PROTOCOL`, which uefi-rs does not bind: walking
+//! the installed tables and the AML handle API for looking at and
+//! changing objects inside them in place.
+//!
+//! AML handles are closed when dropped. Closing a handle to a
+//! table the handle API changed recomputes the table's checksum.
+
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::{ptr, slice};
+use uefi::prelude::*;
+use uefi::proto::Protocol;
+use uefi::unsafe_guid;
+
+use crate::acpi::{DescriptionHeader, InstalledTable};
+use crate::error::{check, Error, Result};
+
+/// `EFI_ACPI_TABLE_VERSION` bits.
+pub const ACPI_TABLE_VERSION_NONE: u32 = 1 << 0;
+pub const ACPI_TABLE_VERSION_1_0B: u32 = 1 << 1;
+pub const ACPI_TABLE_VERSION_2_0: u32 = 1 << 2;
+pub const ACPI_TABLE_VERSION_3_0: u32 = 1 << 3;
+pub const ACPI_TABLE_VERSION_4_0: u32 = 1 << 4;
+pub const ACPI_TABLE_VERSION_5_0: u32 = 1 << 5;
+
+/// `EFI_ACPI_DATA_TYPE`
+const ACPI_DATA_TYPE_NONE: u32 = 0;
+const ACPI_DATA_TYPE_OPCODE: u32 = 1;
+const ACPI_DATA_TYPE_NAME_STRING: u32 = 2;
+const ACPI_DATA_TYPE_OP: u32 = 3;
+const ACPI_DATA_TYPE_UINT: u32 = 4;
+const ACPI_DATA_TYPE_STRING: u32 = 5;
+const ACPI_DATA_TYPE_CHILD: u32 = 6;
+
+/// Called for every table installed after it is registered.
+pub type NotificationFn = extern "efiapi" fn(
+    table: *const DescriptionHeader,
+    version: u32,
+    table_key: usize
+) -> Status;
+
+type AcpiHandle = *mut c_void;
+
+#[unsafe_guid("eb97088e-cfdf-49c6-be4b-d906a5b20e86")]
+#[derive(Protocol)]
+#[repr(C)]
+pub struct AcpiSdt {
+    acpi_version: u32,
+    get_acpi_table: extern "efiapi" fn(
+        index: usize,
+        table: *mut *const DescriptionHeader,
+        version: *mut u32,
+        table_key: *mut usize
+    ) -> Status,
+    register_notify: extern "efiapi" fn(register: bool, notification: NotificationFn) -> Status,
+    open: extern "efiapi" fn(buffer: *mut c_void, handle: *mut AcpiHandle) -> Status,
+    open_sdt: extern "efiapi" fn(table_key: usize, handle: *mut AcpiHandle) -> Status,
+    close: extern "efiapi" fn(handle: AcpiHandle) -> Status,
+    get_child: extern "efiapi" fn(parent: AcpiHandle, handle: *mut AcpiHandle) -> Status,
+    get_option: extern "efiapi" fn(
+        handle: AcpiHandle,
+        index: usize,
+        data_type: *mut u32,
+        data: *mut *const c_void,
+        data_size: *mut usize
+    ) -> Status,
+    set_option: extern "efiapi" fn(
+        handle: AcpiHandle,
+        index: usize,
+        data: *const c_void,
+        data_size: usize
+    ) -> Status,
+    find_path: extern "efiapi" fn(
+        handle_in: AcpiHandle,
+        acpi_path: *const c_void,
+        handle_out: *mut AcpiHandle
+    ) -> Status,
+}
+
+/// An installed table as the protocol hands it out.
+#[derive(Clone, Copy, Debug)]
+pub struct SdtTable {
+    pub address: *const u8,
+    pub header: DescriptionHeader,
+    /// `ACPI_TABLE_VERSION_*` bits of the root tables listing it.
+    pub version: u32,
+    pub key: usize,
+}
+
+impl SdtTable {
+    pub fn bytes(&self) -> &'static [u8] {
+        // SAFETY: the protocol hands out installed, valid tables
+        unsafe { slice::from_raw_parts(self.address, self.header.length as usize) }
+    }
+
+    pub fn installed(&self) -> InstalledTable {
+        InstalledTable {
+            signature: self.header.signature,
+            length: self.header.length as usize,
+            key: self.key,
+        }
+    }
+}
+
+/// An option of an AML object, borrowed from the table.
+#[derive(Clone, Copy, Debug)]
+pub enum AmlOption<'a> {
+    /// Past the last option.
+    None,
+    /// The opcode, one byte or two for extended opcodes.
+    Opcode(&'a [u8]),
+    NameString(&'a [u8]),
+    /// An opcode with no child object, e.g. `ZeroOp`.
+    Op(&'a [u8]),
+    /// An integer of 1, 2, 4 or 8 bytes.
+    Uint(&'a [u8]),
+    String(&'a [u8]),
+    /// A nested object, see `AmlHandle::child`.
+    Child,
+}
+
+impl<'a> AmlOption<'a> {
+    /// The value of a `Uint`.
+    pub fn uint(&self) -> Option<u64> {
+        match self {
+            AmlOption::Uint(bytes) if bytes.len() <= 8 => {
+                let mut value = [0u8; 8];
+                value[..bytes.len()].copy_from_slice(bytes);
+                Some(u64::from_le_bytes(value))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Safe access to `EFI_ACPI_SDT_PROTOCOL`.
+#[derive(Clone, Copy)]
+pub struct Sdt {
+    protocol: &'static AcpiSdt,
+}
+
+impl Sdt {
+    pub fn locate() -> Result<Sdt> {
+        let bs = unsafe {
+            uefi_services::system_table()
+                .as_ref()
+                .boot_services()
+        };
+        let sdt = bs
+            .locate_protocol::<AcpiSdt>()
+            .ignore_warning()
+            .map_err(|error| Error::ProtocolMissing {
+                protocol: "EFI_ACPI_SDT_PROTOCOL",
+                status: error.status(),
+            })?;
+        Ok(Sdt { protocol: unsafe { &*sdt.get() } })
+    }
+
+    /// `ACPI_TABLE_VERSION_*` bits the protocol supports.
+    pub fn acpi_version(&self) -> u32 {
+        self.protocol.acpi_version
+    }
+
+    /// The installed table at `index`, `None` past the last one.
+    pub fn table(&self, index: usize) -> Result<Option<SdtTable>> {
+        let mut table = ptr::null();
+        let mut version = 0;
+        let mut key = 0;
+        let status = (self.protocol.get_acpi_table)(index, &mut table, &mut version, &mut key);
+        if status == Status::NOT_FOUND {
+            return Ok(None);
+        }
+        check(status, "GetAcpiTable")?;
+        if table.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: the protocol hands out installed, valid tables
+        let header = unsafe { table.read_unaligned() };
+        Ok(Some(SdtTable { address: table as *const u8, header, version, key }))
+    }
+
+    /// Every installed table, in installation order.
+    pub fn tables(&self) -> Result<Vec<SdtTable>> {
+        let mut tables = Vec::new();
+        while let Some(table) = self.table(tables.len())? {
+            tables.push(table);
+        }
+        Ok(tables)
+    }
+
+    /// Installed tables with the signature.
+    pub fn find(&self, signature: u32) -> Result<Vec<SdtTable>> {
+        Ok(self.tables()?
+            .into_iter()
+            .filter(|table| table.header.signature == signature)
+            .collect())
+    }
+
+    /// Calls `notification` for every table installed from now on.
+    pub fn register_notify(&self, notification: NotificationFn) -> Result {
+        check((self.protocol.register_notify)(true, notification), "RegisterNotify")
+    }
+
+    pub fn unregister_notify(&self, notification: NotificationFn) -> Result {
+        check((self.protocol.register_notify)(false, notification), "RegisterNotify (unregister)")
+    }
+
+    /// The top-level object of an installed DSDT or SSDT.
+    pub fn open_sdt(&self, table_key: usize) -> Result<AmlHandle> {
+        let mut handle = ptr::null_mut();
+        check((self.protocol.open_sdt)(table_key, &mut handle), "OpenSdt")?;
+        Ok(AmlHandle { sdt: *self, handle })
+    }
+
+    /// The AML object starting at `aml`.
+    ///
+    /// # Safety
+    ///
+    /// `aml` must point at a valid AML object that outlives the
+    /// handle.
+    pub unsafe fn open(&self, aml: *mut u8) -> Result<AmlHandle> {
+        let mut handle = ptr::null_mut();
+        check((self.protocol.open)(aml as *mut c_void, &mut handle), "Open")?;
+        Ok(AmlHandle { sdt: *self, handle })
+    }
+}
+
+/// An open `EFI_ACPI_HANDLE`.
+pub struct AmlHandle {
+    sdt: Sdt,
+    handle: AcpiHandle,
+}
+
+impl AmlHandle {
+    /// The object at the ASL path, e.g. `\_SB.PCI0` or, relative
+    /// to this object, `BPB0._CRS`. `None` if there is none.
+    pub fn find_path(&self, path: &str) -> Result<Option<AmlHandle>> {
+        let mut asl_path = Vec::with_capacity(path.len() + 1);
+        asl_path.extend_from_slice(path.as_bytes());
+        asl_path.push(0);
+        let mut handle = ptr::null_mut();
+        let status = (self.sdt.protocol.find_path)(
+            self.handle,
+            asl_path.as_ptr() as *const c_void,
+            &mut handle
+        );
+        if status == Status::NOT_FOUND {
+            return Ok(None);
+        }
+        check(status, "FindPath")?;
+        Ok(match handle.is_null() {
+            true => None,
+            false => Some(AmlHandle { sdt: self.sdt, handle }),
+        })
+    }
+
+    /// The child object after `previous`, or the first one.
+    pub fn child(&self, previous: Option<&AmlHandle>) -> Result<Option<AmlHandle>> {
+        let mut handle = previous.map_or(ptr::null_mut(), |previous| previous.handle);
+        check((self.sdt.protocol.get_child)(self.handle, &mut handle), "GetChild")?;
+        Ok(match handle.is_null() {
+            true => None,
+            false => Some(AmlHandle { sdt: self.sdt, handle }),
+        })
+    }
+
+    /// Option `index` of the object; option 0 is its opcode.
+    pub fn option(&self, index: usize) -> Result<AmlOption<'_>> {
+        let mut data_type = ACPI_DATA_TYPE_NONE;
+        let mut data = ptr::null();
+        let mut data_size = 0;
+        check(
+            (self.sdt.protocol.get_option)(self.handle, index, &mut data_type, &mut data, &mut data_size),
+            "GetOption"
+        )?;
+        let bytes = match data.is_null() {
+            true => &[][..],
+            // SAFETY: the data lies inside the table the handle is open on
+            false => unsafe { slice::from_raw_parts(data as *const u8, data_size) },
+        };
+        Ok(match data_type {
+            ACPI_DATA_TYPE_OPCODE => AmlOption::Opcode(bytes),
+            ACPI_DATA_TYPE_NAME_STRING => AmlOption::NameString(bytes),
+            ACPI_DATA_TYPE_OP => AmlOption::Op(bytes),
+            ACPI_DATA_TYPE_UINT => AmlOption::Uint(bytes),
+            ACPI_DATA_TYPE_STRING => AmlOption::String(bytes),
+            ACPI_DATA_TYPE_CHILD => AmlOption::Child,
+            _ => AmlOption::None,
+        })
+    }
+
+    /// Replaces option `index` with `data` of the same size, in
+    /// the installed table.
+    pub fn set_option(&self, index: usize, data: &[u8]) -> Result {
+        check(
+            (self.sdt.protocol.set_option)(self.handle, index, data.as_ptr() as *const c_void, data.len()),
+            "SetOption"
+        )
+    }
+}
+
+impl Drop for AmlHandle {
+    fn drop(&mut self) {
+        let status = (self.sdt.protocol.close)(self.handle);
+        if status.is_error() {
+            warn!("Close returned {:?}", status);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! `Boot####` and `Driver####` entries that make the firmware run
+//! bpb-test on every boot.
+//!
+//! Runtime driver builds go into `DriverOrder`, applications first
+//! into `BootOrder`, from where they can chainload the real loader.
+//! Our entries are told apart from others by their description.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::prelude::*;
+use uefi::table::runtime::VariableAttributes;
+
+use crate::image::{device_path_nodes, RawDevicePath, RawLoadedImage, END_DEVICE_PATH_TYPE, END_ENTIRE_DEVICE_PATH_SUBTYPE};
+use crate::error::{Context, Error, Result};
+use crate::variable;
+
+pub const EFI_GLOBAL_VARIABLE: uefi::Guid = uefi::Guid::from_values(
+    0x8be4df61,
+    0x93ca,
+    0x11d2,
+    0xaa0d,
+    [0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c]
+);
+
+pub const LOAD_OPTION_ACTIVE: u32 = 0x0000_0001;
+
+pub const BOOT_OPTION_DESCRIPTION: &str = "Boot Parameter Block";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Boot,
+    Driver,
+}
+
+impl OptionKind {
+    /// The kind that fits this build.
+    pub fn current() -> OptionKind {
+        if cfg!(feature = "runtime-driver") {
+            OptionKind::Driver
+        } else {
+            OptionKind::Boot
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            OptionKind::Boot => "Boot",
+            OptionKind::Driver => "Driver",
+        }
+    }
+
+    fn order_name(self) -> String {
+        format!("{}Order", self.prefix())
+    }
+
+    fn option_name(self, number: u16) -> String {
+        format!("{}{:04X}", self.prefix(), number)
+    }
+}
+
+fn attributes() -> VariableAttributes {
+    VariableAttributes::NON_VOLATILE
+        | VariableAttributes::BOOTSERVICE_ACCESS
+        | VariableAttributes::RUNTIME_ACCESS
+}
+
+fn read_variable(name: &str) -> Result<Option<Vec<u8>>> {
+    variable::read_variable(name, &EFI_GLOBAL_VARIABLE)
+}
+
+/// Writes `data` to the global variable `name`.
+fn write_variable(name: &str, data: &[u8]) -> Result {
+    variable::write_variable(name, &EFI_GLOBAL_VARIABLE, attributes(), data)
+}
+
+fn read_order(kind: OptionKind) -> Result<Vec<u16>> {
+    let data = read_variable(&kind.order_name())?
+        .unwrap_or_default();
+    let order = data.chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(order)
+}
+
+fn write_order(kind: OptionKind, order: &[u16]) -> Result {
+    let data = order.iter()
+        .flat_map(|number| number.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    write_variable(&kind.order_name(), &data)
+}
+
+/// The description of an `EFI_LOAD_OPTION`.
+fn load_option_description(data: &[u8]) -> Option<String> {
+    let description = data.get(6..)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    Some(String::from_utf16_lossy(&description))
+}
+
+/// `EFI_LOAD_OPTION`: attributes, device path length, description,
+/// device path and optional data.
+fn load_option(device_path: &[u8], optional_data: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&LOAD_OPTION_ACTIVE.to_le_bytes());
+    data.extend_from_slice(&(device_path.len() as u16).to_le_bytes());
+    for c in BOOT_OPTION_DESCRIPTION.encode_utf16().chain(core::iter::once(0)) {
+        data.extend_from_slice(&c.to_le_bytes());
+    }
+    data.extend_from_slice(device_path);
+    data.extend_from_slice(optional_data);
+    data
+}
+
+/// The full device path of the running image: the device it was
+/// loaded from followed by its file path.
+fn image_device_path(image: &RawLoadedImage) -> Result<Vec<u8>> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    if image.device_handle.is_null() || image.file_path.is_null() {
+        return Err(Error::NotLoadedFromDevice);
+    }
+    // SAFETY: a non-null device handle from LoadedImage is a handle
+    let device = unsafe { core::mem::transmute::<_, Handle>(image.device_handle) };
+    let device_path = bs.handle_protocol::<RawDevicePath>(device)
+        .context("handle_protocol (DevicePath)")?;
+    // SAFETY: the firmware provides well-formed device paths
+    let mut bytes = unsafe { device_path_nodes(device_path.get() as *const u8) }.to_vec();
+    bytes.extend_from_slice(unsafe { device_path_nodes(image.file_path as *const u8) });
+    bytes.extend_from_slice(&[END_DEVICE_PATH_TYPE, END_ENTIRE_DEVICE_PATH_SUBTYPE, 4, 0]);
+    Ok(bytes)
+}
+
+/// The numbers of our entries of `kind`, in order.
+fn find_ours(kind: OptionKind, order: &[u16]) -> Result<Vec<u16>> {
+    let mut ours = Vec::new();
+    for &number in order {
+        let data = read_variable(&kind.option_name(number))?;
+        let description = data.as_deref().and_then(load_option_description);
+        if description.as_deref() == Some(BOOT_OPTION_DESCRIPTION) {
+            ours.push(number);
+        }
+    }
+    Ok(ours)
+}
+
+/// The lowest number without an entry of `kind`.
+fn free_number(kind: OptionKind) -> Result<u16> {
+    for number in 0..=0xffff {
+        if read_variable(&kind.option_name(number))?.is_none() {
+            return Ok(number);
+        }
+    }
+    Err(Error::NoFreeLoadOption)
+}
+
+/// Writes our entry of `kind` with `load_options` as its optional
+/// data and puts it first in the order, replacing an earlier one.
+pub fn install(kind: OptionKind, image: &RawLoadedImage, load_options: &str) -> Result<u16> {
+    let device_path = image_device_path(image)?;
+    let optional_data = load_options.encode_utf16()
+        .chain(core::iter::once(0))
+        .flat_map(|c| c.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+
+    let mut order = read_order(kind)?;
+    let number = match find_ours(kind, &order)?.first() {
+        Some(&number) => number,
+        None => free_number(kind)?,
+    };
+
+    write_variable(&kind.option_name(number), &load_option(&device_path, &optional_data))?;
+    order.retain(|&other| other != number);
+    order.insert(0, number);
+    write_order(kind, &order)?;
+
+    info!("installed {}", kind.option_name(number));
+    Ok(number)
+}
+
+/// Deletes all our entries of `kind` and drops them from the order.
+pub fn uninstall(kind: OptionKind) -> Result<usize> {
+    let mut order = read_order(kind)?;
+    let ours = find_ours(kind, &order)?;
+    for &number in &ours {
+        variable::delete_variable(&kind.option_name(number), &EFI_GLOBAL_VARIABLE)?;
+        info!("uninstalled {}", kind.option_name(number));
+    }
+    order.retain(|number| !ours.contains(number));
+    write_order(kind, &order)?;
+    Ok(ours.len())
+}
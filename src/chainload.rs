@@ -0,0 +1,126 @@
+//! Starting the next OS loader once the BPB is published, so that
+//! bpb-test can sit in front of it as a Boot#### entry.
+
+use alloc::vec::Vec;
+use core::ptr;
+use uefi::prelude::*;
+use uefi::proto::loaded_image::LoadedImage;
+
+use crate::image::{
+    device_path_nodes, RawDevicePath, RawLoadedImage,
+    END_DEVICE_PATH_TYPE, END_ENTIRE_DEVICE_PATH_SUBTYPE, MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP,
+};
+use crate::error::{check, Context, Result};
+
+/// The image services part of `EFI_BOOT_SERVICES`, which uefi-rs
+/// does not expose with a device path.
+#[repr(C)]
+struct RawBootServices {
+    header: uefi::table::Header,
+    /// RaiseTPL through InstallConfigurationTable.
+    _before: [usize; 22],
+    load_image: extern "efiapi" fn(
+        boot_policy: bool,
+        parent_image_handle: Handle,
+        device_path: *const u8,
+        source_buffer: *const u8,
+        source_size: usize,
+        image_handle: *mut Option<Handle>
+    ) -> Status,
+    start_image: extern "efiapi" fn(
+        image_handle: Handle,
+        exit_data_size: *mut usize,
+        exit_data: *mut *mut u16
+    ) -> Status,
+    exit: usize,
+    unload_image: extern "efiapi" fn(image_handle: Handle) -> Status,
+}
+
+fn to_ucs2(text: &str) -> Vec<u16> {
+    text.encode_utf16()
+        .chain(core::iter::once(0))
+        .collect()
+}
+
+/// The device path of the volume `image` was loaded from with a
+/// file path node for `path` appended.
+fn file_device_path(image: Handle, path: &str) -> Result<Vec<u8>> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    let loaded_image = bs.handle_protocol::<LoadedImage>(image)
+        .context("handle_protocol (LoadedImage)")?;
+    let device = unsafe { &*loaded_image.get() }.device();
+    let device_path = bs.handle_protocol::<RawDevicePath>(device)
+        .context("handle_protocol (DevicePath)")?;
+
+    // SAFETY: the firmware installed a well-formed device path
+    let mut bytes = unsafe { device_path_nodes(device_path.get() as *const u8) }.to_vec();
+    let name = to_ucs2(path);
+    let length = (4 + name.len() * 2) as u16;
+    bytes.extend_from_slice(&[MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP]);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    for c in name {
+        bytes.extend_from_slice(&c.to_le_bytes());
+    }
+    bytes.extend_from_slice(&[END_DEVICE_PATH_TYPE, END_ENTIRE_DEVICE_PATH_SUBTYPE, 4, 0]);
+    Ok(bytes)
+}
+
+/// Loads `path` from our own volume and starts it with `options` as
+/// its LoadOptions. Returns only if the loader exits or fails.
+pub fn chainload(
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+    path: &str,
+    options: Option<&str>
+) -> Result {
+    let bs = unsafe {
+        &*(system_table.boot_services() as *const _ as *const RawBootServices)
+    };
+
+    let device_path = file_device_path(image, path)?;
+    info!("chainload: {}", path);
+
+    let mut child = None;
+    let status = (bs.load_image)(false, image, device_path.as_ptr(), ptr::null(), 0, &mut child);
+    check(status, "LoadImage")?;
+    let child = match child {
+        Some(child) => child,
+        None => return check(Status::NOT_FOUND, "LoadImage"),
+    };
+
+    // Stays alive for as long as the child can look at it, since a
+    // loader that boots an OS never comes back
+    let load_options = options.map(to_ucs2);
+    if let Some(load_options) = &load_options {
+        let ubs = system_table.boot_services();
+        let child_image = ubs.handle_protocol::<RawLoadedImage>(child)
+            .context("handle_protocol (child LoadedImage)");
+        match child_image {
+            Ok(child_image) => unsafe {
+                let child_image = &mut *child_image.get();
+                child_image.load_options = load_options.as_ptr() as *const u8;
+                child_image.load_options_size = (load_options.len() * 2) as u32;
+            },
+            Err(error) => {
+                (bs.unload_image)(child);
+                return Err(error);
+            },
+        }
+    }
+
+    let mut exit_data_size = 0;
+    let mut exit_data = ptr::null_mut();
+    let status = (bs.start_image)(child, &mut exit_data_size, &mut exit_data);
+    if !exit_data.is_null() {
+        system_table.boot_services()
+            .free_pool(exit_data as *mut u8)
+            .or_warn("free_pool (exit data)");
+    }
+    warn!("{} exited with {:?}", path, status);
+    check(status, "StartImage")
+}
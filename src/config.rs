@@ -0,0 +1,271 @@
+//! `bpb.cfg`, read from the directory the image was loaded from.
+//!
+//! An INI subset: `[section]` headers, `key = value` lines and `#` or
+//! `;` comments.
+//!
+//!     [options]
+//!     transport = acpi,var
+//!     pages = 4
+//!     below-4g = true
+//!
+//!     [parameters]
+//!     console = ttyS0
+//!
+//!     [ssdt]
+//!     oem-id = OEM
+//!     oem-table-id = BPBT0
+//!     oem-revision = 4
+//!
+//!     [bpbt]
+//!     oem = inherit
+//!
+//! `[options]` takes the long command line options without the
+//! dashes; flags take `true` or `false`. The command line is applied
+//! after the file, so it wins. `[parameters]` become key/value records
+//! of the payload. `[bpbt]`, `[ssdt]` and `[uefi]` set the OEM
+//! identity of those tables: `oem = inherit` copies the platform's,
+//! any `oem-*` key makes it explicit with the build's default for the
+//! keys left out. A table without a section keeps its default, see
+//! `src/oem.rs`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+use uefi::prelude::*;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::{File, FileAttribute, FileMode, FileType};
+use uefi::proto::media::fs::SimpleFileSystem;
+
+use crate::acpi::OemIdentity;
+use crate::oem::{default_fields, oem_field, OemTable};
+use crate::image::{RawLoadedImage, END_DEVICE_PATH_TYPE, MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP};
+use crate::error::{self, Context, Error};
+use crate::options::parse_number;
+
+pub const CONFIG_FILE_NAME: &str = "bpb.cfg";
+
+/// Options that take no value on the command line.
+const FLAGS: [&str; 7] = [
+    "below-4g", "fallback", "mmio", "runtime-list", "dump-mmap", "dump-acpi", "selftest",
+];
+
+/// Options that act on the boot entries or print the usage instead
+/// of publishing, which a file read on every run must not set.
+const COMMAND_LINE_ONLY: [&str; 3] = ["install", "uninstall", "help"];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Neither a section header nor `key = value`.
+    Syntax(usize),
+    UnknownSection(usize, String),
+    UnknownKey(usize, String),
+    BadValue(usize, String),
+    /// An `[options]` key only the command line may give.
+    CommandLineOnly(usize, String),
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// `[options]` as command line arguments.
+    pub args: Vec<String>,
+    pub parameters: Vec<(String, String)>,
+    /// By `OemTable::index`.
+    pub oem: [Option<OemIdentity>; 3],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    None,
+    Options,
+    Parameters,
+    Oem(OemTable),
+}
+
+/// `value` if it fits an OEM id field of `length` bytes. Longer or
+/// non-ASCII ids are refused rather than truncated, as build.rs does.
+fn oem_id_value(value: &str, length: usize) -> Option<&str> {
+    Some(value).filter(|value| value.is_ascii() && value.len() <= length)
+}
+
+fn set_oem(oem: &mut Option<OemIdentity>, line: usize, key: &str, value: &str) -> Result<(), ConfigError> {
+    let bad_value = || ConfigError::BadValue(line, String::from(key));
+    let (mut oem_id, mut oem_table_id, mut oem_revision) = match *oem {
+        Some(OemIdentity::Explicit { oem_id, oem_table_id, oem_revision }) => {
+            (oem_id, oem_table_id, oem_revision)
+        },
+        _ => default_fields(),
+    };
+    match key {
+        "oem" => match value {
+            "inherit" => {
+                *oem = Some(OemIdentity::Inherit);
+                return Ok(());
+            },
+            "explicit" => (),
+            _ => return Err(bad_value()),
+        },
+        "oem-id" => oem_id = oem_field(oem_id_value(value, 6).ok_or_else(bad_value)?),
+        "oem-table-id" => {
+            oem_table_id = u64::from_le_bytes(oem_field(oem_id_value(value, 8).ok_or_else(bad_value)?));
+        },
+        "oem-revision" => {
+            oem_revision = parse_number(value)
+                .filter(|&revision| revision <= u32::MAX as u64)
+                .ok_or_else(bad_value)? as u32;
+        },
+        _ => return Err(ConfigError::UnknownKey(line, String::from(key))),
+    }
+    *oem = Some(OemIdentity::Explicit { oem_id, oem_table_id, oem_revision });
+    Ok(())
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut section = Section::None;
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = match name.trim() {
+                    "options" => Section::Options,
+                    "parameters" => Section::Parameters,
+                    name => match OemTable::parse(name) {
+                        Some(table) => Section::Oem(table),
+                        None => return Err(ConfigError::UnknownSection(number, String::from(name))),
+                    },
+                };
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(at) => (line[..at].trim(), line[at + 1..].trim()),
+                None => return Err(ConfigError::Syntax(number)),
+            };
+            if key.is_empty() {
+                return Err(ConfigError::Syntax(number));
+            }
+
+            match section {
+                Section::None => return Err(ConfigError::UnknownKey(number, String::from(key))),
+                Section::Options if COMMAND_LINE_ONLY.contains(&key) => {
+                    return Err(ConfigError::CommandLineOnly(number, String::from(key)));
+                },
+                Section::Options if FLAGS.contains(&key) => match value {
+                    "true" => config.args.push(format!("--{}", key)),
+                    "false" => (),
+                    _ => return Err(ConfigError::BadValue(number, String::from(key))),
+                },
+                Section::Options => {
+                    config.args.push(format!("--{}", key));
+                    config.args.push(String::from(value));
+                },
+                Section::Parameters => {
+                    config.parameters.push((String::from(key), String::from(value)));
+                },
+                Section::Oem(table) => set_oem(&mut config.oem[table.index()], number, key, value)?,
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// The directory part of the image's file path, e.g. `\EFI\BOOT\`,
+/// from the media file path nodes of `LoadedImage.FilePath`.
+fn image_directory(image: &RawLoadedImage) -> String {
+    let mut path = String::new();
+    let mut node = image.file_path as *const u8;
+    // SAFETY: the firmware hands us a well-formed device path
+    unsafe {
+        while !node.is_null() && *node != END_DEVICE_PATH_TYPE {
+            let length = u16::from_le_bytes([*node.add(2), *node.add(3)]) as usize;
+            if length < 4 {
+                break;
+            }
+            if *node == MEDIA_DEVICE_PATH && *node.add(1) == MEDIA_FILEPATH_DP {
+                let name = core::slice::from_raw_parts(node.add(4), length - 4)
+                    .chunks_exact(mem::size_of::<u16>())
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|&c| c != 0)
+                    .collect::<Vec<_>>();
+                if !path.is_empty() && !path.ends_with('\\') {
+                    path.push('\\');
+                }
+                path.push_str(&String::from_utf16_lossy(&name));
+            }
+            node = node.add(length);
+        }
+    }
+    match path.rfind('\\') {
+        Some(at) => String::from(&path[..=at]),
+        None => String::from("\\"),
+    }
+}
+
+fn read_file(handle: Handle, path: &str) -> error::Result<Option<Vec<u8>>> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    let loaded_image = bs.handle_protocol::<LoadedImage>(handle)
+        .context("handle_protocol (LoadedImage)")?;
+    let device = unsafe { &*loaded_image.get() }.device();
+    // Images loaded from a firmware volume or memory have no volume
+    let sfs = match bs.handle_protocol::<SimpleFileSystem>(device).ignore_warning() {
+        Ok(sfs) => unsafe { &mut *sfs.get() },
+        Err(_) => return Ok(None),
+    };
+
+    let mut root = sfs.open_volume()
+        .context("open_volume")?;
+    let file = match root.open(path, FileMode::Read, FileAttribute::empty()).ignore_warning() {
+        Ok(file) => file,
+        Err(error) if error.status() == Status::NOT_FOUND => return Ok(None),
+        Err(error) => return Err(Error::Firmware { call: "open", status: error.status() }),
+    };
+    let mut file = match file.into_type().context("into_type")? {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => return Err(Error::Firmware { call: "open", status: Status::INVALID_PARAMETER }),
+    };
+
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 512];
+    loop {
+        let count = file.read(&mut chunk)
+            .map_err(|error| uefi::Error::from(error.status()))
+            .context("read")?;
+        if count == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..count]);
+    }
+    Ok(Some(data))
+}
+
+/// Reads and parses `bpb.cfg` next to the image; `None` if there
+/// is no such file.
+pub fn load(handle: Handle, image: &RawLoadedImage) -> error::Result<Option<Config>> {
+    let path = format!("{}{}", image_directory(image), CONFIG_FILE_NAME);
+    info!("config: {}", path);
+
+    let data = match read_file(handle, &path)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let text = match core::str::from_utf8(&data) {
+        Ok(text) => text,
+        Err(_) => return Err(Error::ConfigNotUtf8(path)),
+    };
+    match Config::parse(text) {
+        Ok(config) => Ok(Some(config)),
+        Err(error) => Err(Error::Config { path, error }),
+    }
+}
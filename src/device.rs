@@ -0,0 +1,169 @@
+//! Putting the `BPB0` device into a DSDT or SSDT the platform
+//! already has, for platforms that reject an extra SSDT or where
+//! the device must live under a particular scope.
+//!
+//! Tables are found through `EFI_ACPI_SDT_PROTOCOL`. Patching finds
+//! an existing `BPB0` with its AML handle API and points it at the
+//! BPB in place. Injecting appends
+//! `Scope (scope) { Device (BPB0) ... }` to a copy of the table that
+//! defines the scope and installs the copy instead: a later Scope
+//! term adds to a scope defined earlier in the table, so none of
+//! the platform's package lengths change.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::acpi::{
+    self, checksum, signature_str, validate_table, ReplacedTable,
+    DESCRIPTION_HEADER_CHECKSUM_OFFSET, DSDT_SIGNATURE, SSDT_SIGNATURE,
+};
+use crate::acpi_sdt::{AmlHandle, AmlOption, Sdt, SdtTable};
+use crate::error::{Error, Result};
+use crate::options::DevicePlacement;
+use crate::{bpb_device_aml, BpbDevice};
+use bpb::{aml, BPB_DEVICE_NAME};
+
+/// Scopes the ACPI namespace predefines, which belong to the DSDT
+/// even though no table defines them.
+const PREDEFINED_SCOPES: &[&str] = &["\\", "\\_SB", "\\_GPE", "\\_PR", "\\_SI", "\\_TZ"];
+
+/// A table changed in place and its bytes from before.
+pub struct PatchedTable {
+    pub key: usize,
+    pub address: u64,
+    pub original: Vec<u8>,
+}
+
+pub enum Placed {
+    Patched(PatchedTable),
+    Injected(ReplacedTable),
+}
+
+/// `{scope}.BPB0`
+fn device_path(scope: &str) -> String {
+    match scope {
+        "\\" => format!("\\{}", BPB_DEVICE_NAME),
+        scope => format!("{}.{}", scope, BPB_DEVICE_NAME),
+    }
+}
+
+fn is_definition_block(table: &SdtTable) -> bool {
+    table.header.signature == DSDT_SIGNATURE || table.header.signature == SSDT_SIGNATURE
+}
+
+/// The installed DSDT or SSDT that defines `path`.
+fn defining_table(sdt: &Sdt, path: &str) -> Result<Option<SdtTable>> {
+    let tables = sdt.tables()?;
+    if PREDEFINED_SCOPES.contains(&path) {
+        return Ok(tables.into_iter().find(|table| table.header.signature == DSDT_SIGNATURE));
+    }
+    for table in tables.iter().filter(|table| is_definition_block(table)) {
+        if sdt.open_sdt(table.key)?.find_path(path)?.is_some() {
+            return Ok(Some(*table));
+        }
+    }
+    Ok(None)
+}
+
+fn update_checksum(table: &mut [u8]) {
+    table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = 0;
+    table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = checksum(table);
+}
+
+/// Where the object behind `handle` starts in `table`.
+fn object_offset(table: &SdtTable, handle: &AmlHandle) -> Option<usize> {
+    let opcode = match handle.option(0).ok()? {
+        AmlOption::Opcode(opcode) => opcode.as_ptr() as usize,
+        _ => return None,
+    };
+    opcode.checked_sub(table.address as usize)
+        .filter(|&offset| offset < table.bytes().len())
+}
+
+/// Points the `_CRS` of an existing `{scope}.BPB0` at the device's
+/// range, in the installed table. `None` if no table defines it.
+pub fn patch(device: &BpbDevice) -> Result<Option<PatchedTable>> {
+    let sdt = Sdt::locate()?;
+    let path = device_path(device.scope);
+    let table = match defining_table(&sdt, &path)? {
+        Some(table) => table,
+        None => return Ok(None),
+    };
+    let bad_device = || Error::BadBpbDevice(path.clone());
+
+    let root = sdt.open_sdt(table.key)?;
+    let bpb0 = root.find_path(&path)?.ok_or_else(bad_device)?;
+    let crs = bpb0.find_path("_CRS")?.ok_or_else(bad_device)?;
+    // The resource template is the raw contents of the Buffer the
+    // name holds, which the handle API has no option for
+    let buffer = crs.child(None)?.ok_or_else(bad_device)?;
+    let offset = object_offset(&table, &buffer).ok_or_else(bad_device)?;
+    let original = table.bytes().to_vec();
+    let template = aml::parse_buffer(&original[offset..]).ok_or_else(bad_device)?;
+    let start = template.as_ptr() as usize - original.as_ptr() as usize;
+    let mut patched = template.to_vec();
+    if !aml::set_memory_range(&mut patched, device.address, device.length) {
+        return Err(bad_device());
+    }
+    let name = match crs.option(1)? {
+        AmlOption::NameString(name) => name.to_vec(),
+        _ => return Err(bad_device()),
+    };
+
+    // SAFETY: the template lies inside the installed table, and
+    // tables are writable until ExitBootServices
+    unsafe {
+        ptr::copy_nonoverlapping(patched.as_ptr(), (table.address as *mut u8).add(start), patched.len());
+    }
+    // Setting the name to itself marks the handle changed, so that
+    // closing it recomputes the table's checksum
+    if let Err(error) = crs.set_option(1, &name) {
+        // SAFETY: as above
+        unsafe {
+            ptr::copy_nonoverlapping(original.as_ptr(), table.address as *mut u8, original.len());
+        }
+        return Err(error);
+    }
+    drop((buffer, crs, bpb0, root));
+    info!("patched {} in {} table {}", path, signature_str(&table.header.signature), table.key);
+
+    Ok(Some(PatchedTable { key: table.key, address: table.address as u64, original }))
+}
+
+/// Adds `BPB0` under `device.scope` to the installed DSDT or SSDT
+/// that defines the scope.
+pub fn inject(device: &BpbDevice) -> Result<ReplacedTable> {
+    let sdt = Sdt::locate()?;
+    let table = defining_table(&sdt, device.scope)?
+        .ok_or_else(|| Error::AmlPathNotFound(String::from(device.scope)))?;
+    let signature = table.header.signature;
+
+    let original = table.bytes().to_vec();
+    let mut injected = original.clone();
+    injected.extend_from_slice(&bpb_device_aml(device)?);
+    if injected.len() > u32::MAX as usize {
+        return Err(Error::TableTooLarge { signature, size: injected.len() });
+    }
+    let length = injected.len() as u32;
+    injected[4..8].copy_from_slice(&length.to_le_bytes());
+    update_checksum(&mut injected);
+    validate_table(&injected)
+        .map_err(|error| Error::table(signature, error))?;
+
+    info!("injecting {} into {} table {}", BPB_DEVICE_NAME, signature_str(&signature), table.key);
+    acpi::replace_table(table.key, original, &injected)
+}
+
+/// Puts `BPB0` where `placement` says, other than into our own
+/// SSDT. Injecting patches a `BPB0` an earlier run injected instead
+/// of adding a second one.
+pub fn place(placement: DevicePlacement, device: &BpbDevice) -> Result<Placed> {
+    if let Some(patched) = patch(device)? {
+        return Ok(Placed::Patched(patched));
+    }
+    match placement {
+        DevicePlacement::Inject => Ok(Placed::Injected(inject(device)?)),
+        _ => Err(Error::AmlPathNotFound(device_path(device.scope))),
+    }
+}
@@ -0,0 +1,270 @@
+//! What can go wrong while publishing the BPB, with enough context
+//! for a field log to say which transport broke and why.
+
+use alloc::string::String;
+use core::fmt;
+use uefi::prelude::*;
+use uefi::table::boot::MemoryType;
+
+use crate::acpi::{signature_str, ParseError};
+use crate::config::ConfigError;
+use crate::fadt::FadtPatchError;
+use crate::options::OptionsError;
+use bpb::payload::PayloadError;
+
+pub type Result<T = ()> = core::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A boot or runtime service failed.
+    Firmware { call: &'static str, status: Status },
+    /// A protocol we need is not installed.
+    ProtocolMissing { protocol: &'static str, status: Status },
+    /// `EFI_ACPI_TABLE_PROTOCOL` is not installed.
+    AcpiProtocolMissing(Status),
+    /// No RSDP in the system configuration table.
+    RsdpNotFound,
+    /// A table does not sum to zero.
+    ChecksumMismatch { signature: u32 },
+    /// A table that is malformed in any other way.
+    BadTable { signature: u32, error: ParseError },
+    /// The RSDP or the XSDT/RSDT behind it is malformed.
+    BadRootTables(ParseError),
+    TableTooLarge { signature: u32, size: usize },
+    TableInstallFailed { signature: u32, status: Status },
+    /// No installed table with the signature to replace.
+    TableNotInstalled { signature: u32 },
+    /// No installed DSDT or SSDT defines the ASL path.
+    AmlPathNotFound(String),
+    /// The `BPB0` at the path has no `_CRS` memory descriptor that
+    /// can describe the BPB.
+    BadBpbDevice(String),
+    /// A `--fadt-patch` the platform's FADT or the protocol does
+    /// not take.
+    BadFadtPatch { field: &'static str, error: FadtPatchError },
+    AllocationFailed { memory_type: MemoryType, pages: usize, status: Status },
+    PoolAllocationFailed { memory_type: MemoryType, size: usize, status: Status },
+    /// The placement policy cannot be satisfied as given.
+    BadPlacement(&'static str),
+    /// A configuration table entry we need is missing.
+    ConfigurationTableMissing(&'static str),
+    /// Longer than our name buffer or not UCS-2.
+    BadVariableName(String),
+    VariableReadFailed { name: String, status: Status },
+    VariableWriteFailed { name: String, status: Status },
+    Payload(PayloadError),
+    /// The hooked variable has room for `capacity` bytes.
+    BpbTooLarge { size: usize, capacity: usize },
+    TooManyRuntimePointers,
+    /// Images loaded from memory have no device path to point at.
+    NotLoadedFromDevice,
+    /// Every `Boot####` or `Driver####` number is taken.
+    NoFreeLoadOption,
+    /// No memory map descriptor covers the address.
+    NotInMemoryMap(u64),
+    /// No unclaimed address range for an MMIO reservation.
+    NoMmioRange { length: u64 },
+    /// `failed` of the `checked` consumer paths do not lead to the BPB.
+    SelfTestFailed { failed: usize, checked: usize },
+    Options(OptionsError),
+    Config { path: String, error: ConfigError },
+    ConfigNotUtf8(String),
+}
+
+impl Error {
+    /// A table that failed validation.
+    pub fn table(signature: u32, error: ParseError) -> Error {
+        match error {
+            ParseError::BadChecksum | ParseError::BadExtendedChecksum => {
+                Error::ChecksumMismatch { signature }
+            },
+            error => Error::BadTable { signature, error },
+        }
+    }
+
+    /// The EFI status efi_main exits with.
+    pub fn status(&self) -> Status {
+        match *self {
+            Error::Firmware { status, .. }
+            | Error::ProtocolMissing { status, .. }
+            | Error::AcpiProtocolMissing(status)
+            | Error::TableInstallFailed { status, .. }
+            | Error::AllocationFailed { status, .. }
+            | Error::PoolAllocationFailed { status, .. }
+            | Error::VariableReadFailed { status, .. }
+            | Error::VariableWriteFailed { status, .. } => status,
+            Error::RsdpNotFound
+            | Error::ConfigurationTableMissing(_)
+            | Error::NotInMemoryMap(_)
+            | Error::TableNotInstalled { .. }
+            | Error::AmlPathNotFound(_)
+            | Error::NoMmioRange { .. } => Status::NOT_FOUND,
+            Error::ChecksumMismatch { .. } => Status::CRC_ERROR,
+            Error::BadTable { error, .. } | Error::BadRootTables(error) => {
+                crate::acpi::parse_status(error)
+            },
+            Error::TableTooLarge { .. } | Error::BpbTooLarge { .. } => Status::BAD_BUFFER_SIZE,
+            Error::Payload(PayloadError::BufferTooSmall) => Status::BUFFER_TOO_SMALL,
+            Error::Payload(PayloadError::BufferTooLarge) => Status::BAD_BUFFER_SIZE,
+            Error::Payload(_) => Status::INVALID_PARAMETER,
+            Error::BadPlacement(_)
+            | Error::BadFadtPatch { .. }
+            | Error::BadVariableName(_)
+            | Error::Options(_)
+            | Error::Config { .. }
+            | Error::ConfigNotUtf8(_) => Status::INVALID_PARAMETER,
+            Error::TooManyRuntimePointers | Error::NoFreeLoadOption => Status::OUT_OF_RESOURCES,
+            Error::NotLoadedFromDevice | Error::BadBpbDevice(_) => Status::UNSUPPORTED,
+            Error::SelfTestFailed { .. } => Status::ABORTED,
+        }
+    }
+
+    /// Logs the error and the status it maps to.
+    pub fn report(&self) {
+        error!("bpb-test failed: {}", self);
+        error!("exit status: {:?}", self.status());
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Firmware { call, status } => write!(f, "{} returned {:?}", call, status),
+            Error::ProtocolMissing { protocol, status } => {
+                write!(f, "no {} ({:?})", protocol, status)
+            },
+            Error::AcpiProtocolMissing(status) => {
+                write!(f, "no EFI_ACPI_TABLE_PROTOCOL ({:?}), ACPI tables cannot be installed", status)
+            },
+            Error::RsdpNotFound => write!(f, "no ACPI RSDP in the system configuration table"),
+            Error::ChecksumMismatch { signature } => {
+                write!(f, "{} table checksum does not sum to zero", signature_str(signature))
+            },
+            Error::BadTable { signature, error } => {
+                write!(f, "{} table is malformed: {:?}", signature_str(signature), error)
+            },
+            Error::BadRootTables(error) => {
+                write!(f, "the RSDP or root system description table is malformed: {:?}", error)
+            },
+            Error::TableTooLarge { signature, size } => {
+                write!(f, "{} table is too large: {} bytes", signature_str(signature), size)
+            },
+            Error::TableInstallFailed { signature, status } => {
+                write!(f, "installing the {} table returned {:?}", signature_str(signature), status)
+            },
+            Error::TableNotInstalled { signature } => {
+                write!(f, "no {} table is installed", signature_str(signature))
+            },
+            Error::AmlPathNotFound(path) => write!(f, "no installed DSDT or SSDT defines {}", path),
+            Error::BadBpbDevice(path) => {
+                write!(f, "{} has no _CRS memory descriptor that can hold the BPB", path)
+            },
+            Error::BadFadtPatch { field, error: FadtPatchError::TooShort { length } } => {
+                write!(f, "the platform FADT is {} bytes and has no {} field", length, field)
+            },
+            Error::BadFadtPatch { field, error: FadtPatchError::NotKept { patched, installed } } => {
+                write!(f, "FADT.{} is {:#x} once installed, not {:#x}", field, installed, patched)
+            },
+            Error::BadFadtPatch { field, error: FadtPatchError::NotADsdt { address } } => {
+                write!(f, "{} {:#x} does not point at a valid DSDT", field, address)
+            },
+            Error::AllocationFailed { memory_type, pages, status } => {
+                write!(f, "allocating {} pages of {:?} returned {:?}", pages, memory_type, status)
+            },
+            Error::PoolAllocationFailed { memory_type, size, status } => {
+                write!(f, "allocating {} bytes of {:?} pool returned {:?}", size, memory_type, status)
+            },
+            Error::BadPlacement(reason) => write!(f, "bad placement policy: {}", reason),
+            Error::ConfigurationTableMissing(name) => {
+                write!(f, "no {} in the system configuration table", name)
+            },
+            Error::BadVariableName(name) => write!(f, "bad variable name {:?}", name),
+            Error::VariableReadFailed { name, status } => {
+                write!(f, "reading variable {} returned {:?}", name, status)
+            },
+            Error::VariableWriteFailed { name, status } => {
+                write!(f, "writing variable {} returned {:?}", name, status)
+            },
+            Error::Payload(error) => write!(f, "cannot write the payload: {:?}", error),
+            Error::BpbTooLarge { size, capacity } => {
+                write!(f, "bpb is {} bytes, at most {} fit", size, capacity)
+            },
+            Error::TooManyRuntimePointers => write!(f, "too many pointers to convert at runtime"),
+            Error::NotLoadedFromDevice => write!(f, "image was not loaded from a device"),
+            Error::NoFreeLoadOption => write!(f, "no free load option number"),
+            Error::NotInMemoryMap(address) => write!(f, "{:#x} is not in the memory map", address),
+            Error::NoMmioRange { length } => {
+                write!(f, "no unclaimed range of {:#x} bytes below 4 GiB", length)
+            },
+            Error::SelfTestFailed { failed, checked } => {
+                write!(f, "selftest: {} of {} transports failed", failed, checked)
+            },
+            Error::Options(error) => write!(f, "bad options: {:?}", error),
+            Error::Config { path, error } => write!(f, "{}: {:?}", path, error),
+            Error::ConfigNotUtf8(path) => write!(f, "{} is not UTF-8", path),
+        }
+    }
+}
+
+impl From<PayloadError> for Error {
+    fn from(error: PayloadError) -> Self {
+        Error::Payload(error)
+    }
+}
+
+impl From<OptionsError> for Error {
+    fn from(error: OptionsError) -> Self {
+        Error::Options(error)
+    }
+}
+
+/// Turns the `uefi::Result` of a boot or runtime service into ours.
+pub trait Context<T> {
+    /// Drops warnings and names the call that failed.
+    fn context(self, call: &'static str) -> Result<T>;
+
+    /// For calls whose failure changes nothing, such as freeing on
+    /// an error path: logs a warning and carries on.
+    fn or_warn(self, call: &'static str) -> Option<T>;
+}
+
+impl<T> Context<T> for uefi::Result<T> {
+    fn context(self, call: &'static str) -> Result<T> {
+        self.ignore_warning()
+            .map_err(|error| Error::Firmware { call, status: error.status() })
+    }
+
+    fn or_warn(self, call: &'static str) -> Option<T> {
+        match self.ignore_warning() {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!("{} returned {:?}", call, error.status());
+                None
+            },
+        }
+    }
+}
+
+/// The status of a raw service, e.g. of the DXE services table.
+impl Context<()> for Status {
+    fn context(self, call: &'static str) -> Result {
+        check(self, call)
+    }
+
+    fn or_warn(self, call: &'static str) -> Option<()> {
+        if self.is_error() {
+            warn!("{} returned {:?}", call, self);
+            return None;
+        }
+        Some(())
+    }
+}
+
+/// A raw service's status as a `Result`.
+pub fn check(status: Status, call: &'static str) -> Result {
+    if status.is_error() {
+        Err(Error::Firmware { call, status })
+    } else {
+        Ok(())
+    }
+}
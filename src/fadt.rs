@@ -0,0 +1,230 @@
+//! Replacing the platform's FADT with a patched copy.
+//!
+//! The FADT is copied byte for byte whatever its revision, only the
+//! fields named on the command line are changed, and the checksum
+//! is recomputed. The original is uninstalled through
+//! `EFI_ACPI_TABLE_PROTOCOL` first, since the protocol refuses a
+//! second FADT, and put back if the copy cannot be installed or
+//! does not keep a patched field.
+//!
+//! The protocol points `dsdt` and `x_dsdt` at the DSDT it has
+//! installed, so patching them installs the DSDT found at the new
+//! address in place of the platform's instead.
+
+use alloc::vec::Vec;
+use core::slice;
+
+use crate::acpi::{
+    self, checksum, platform_tables, validate_table, DescriptionHeader, ReplacedTable,
+    ACPI_3_FADT_SIGNATURE, DESCRIPTION_HEADER_CHECKSUM_OFFSET, DSDT_SIGNATURE,
+};
+use crate::acpi_sdt::Sdt;
+use crate::error::{Error, Result};
+use crate::options::parse_number;
+
+/// A FADT field by its byte offset, valid in every revision long
+/// enough to hold it.
+#[derive(Debug)]
+pub struct FadtField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub width: usize,
+}
+
+/// The fields `--fadt-patch` knows, see `FixedDescriptionTable3`.
+pub const FADT_FIELDS: &[FadtField] = &[
+    FadtField { name: "firmware_ctrl", offset: 36, width: 4 },
+    FadtField { name: "dsdt", offset: 40, width: 4 },
+    FadtField { name: "preferred_pm_profile", offset: 45, width: 1 },
+    FadtField { name: "sci_int", offset: 46, width: 2 },
+    FadtField { name: "smi_cmd", offset: 48, width: 4 },
+    FadtField { name: "acpi_enable", offset: 52, width: 1 },
+    FadtField { name: "acpi_disable", offset: 53, width: 1 },
+    FadtField { name: "century", offset: 108, width: 1 },
+    FadtField { name: "iapc_boot_arch", offset: 109, width: 2 },
+    FadtField { name: "flags", offset: 112, width: 4 },
+    FadtField { name: "reset_value", offset: 128, width: 1 },
+    FadtField { name: "arm_boot_arch", offset: 129, width: 2 },
+    FadtField { name: "minor_version", offset: 131, width: 1 },
+    FadtField { name: "x_firmware_ctrl", offset: 132, width: 8 },
+    FadtField { name: "x_dsdt", offset: 140, width: 8 },
+    FadtField { name: "hypervisor_vendor_id", offset: 268, width: 8 },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchOp {
+    /// `FIELD=VALUE`
+    Set,
+    /// `FIELD|=BITS`
+    Or,
+    /// `FIELD&=MASK`
+    And,
+}
+
+/// Why a `--fadt-patch` cannot be applied.
+#[derive(Clone, Copy, Debug)]
+pub enum FadtPatchError {
+    /// The platform's FADT revision ends before the field.
+    TooShort { length: usize },
+    /// The installed copy has `installed` where we wrote `patched`.
+    NotKept { patched: u64, installed: u64 },
+    /// `dsdt` or `x_dsdt` does not point at a valid DSDT.
+    NotADsdt { address: u64 },
+}
+
+impl PatchOp {
+    fn apply(self, old: u64, value: u64) -> u64 {
+        match self {
+            PatchOp::Set => value,
+            PatchOp::Or => old | value,
+            PatchOp::And => old & value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FadtPatch {
+    pub field: &'static FadtField,
+    pub op: PatchOp,
+    pub value: u64,
+}
+
+impl FadtPatch {
+    /// `FIELD=VALUE`, `FIELD|=BITS` or `FIELD&=MASK`, e.g.
+    /// `x_dsdt=0x7ff0000` or `flags|=0x100000`. A DSDT address can
+    /// only be set.
+    pub fn parse(text: &str) -> Option<FadtPatch> {
+        let (name, op, value) = if let Some(index) = text.find("|=") {
+            (&text[..index], PatchOp::Or, &text[index + 2..])
+        } else if let Some(index) = text.find("&=") {
+            (&text[..index], PatchOp::And, &text[index + 2..])
+        } else {
+            let index = text.find('=')?;
+            (&text[..index], PatchOp::Set, &text[index + 1..])
+        };
+        let field = FADT_FIELDS.iter().find(|field| field.name == name)?;
+        let value = parse_number(value)?;
+        if field.width < 8 && value >> (8 * field.width) != 0 {
+            return None;
+        }
+        let patch = FadtPatch { field, op, value };
+        if patch.is_dsdt() && op != PatchOp::Set {
+            return None;
+        }
+        Some(patch)
+    }
+
+    /// Whether the patch names a new DSDT, which is installed
+    /// rather than written into the FADT.
+    pub fn is_dsdt(&self) -> bool {
+        self.field.name == "dsdt" || self.field.name == "x_dsdt"
+    }
+}
+
+/// The DSDT address the last `dsdt` or `x_dsdt` patch sets.
+pub fn dsdt_address(patches: &[FadtPatch]) -> Option<u64> {
+    patches.iter().rev().find(|patch| patch.is_dsdt()).map(|patch| patch.value)
+}
+
+fn field_value(table: &[u8], field: &FadtField) -> Option<u64> {
+    let bytes = table.get(field.offset..field.offset + field.width)?;
+    let mut value = [0u8; 8];
+    value[..field.width].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(value))
+}
+
+/// A copy of `original` with `patches` other than a new DSDT
+/// applied and the checksum recomputed.
+pub fn patch(original: &[u8], patches: &[FadtPatch]) -> Result<Vec<u8>> {
+    let mut table = original.to_vec();
+    for patch in patches.iter().filter(|patch| !patch.is_dsdt()) {
+        let FadtField { name, offset, width } = *patch.field;
+        let old = field_value(&table, patch.field).ok_or(Error::BadFadtPatch {
+            field: name,
+            error: FadtPatchError::TooShort { length: original.len() },
+        })?;
+        let new = patch.op.apply(old, patch.value);
+        table[offset..offset + width].copy_from_slice(&new.to_le_bytes()[..width]);
+        info!("FADT.{}: {:#x} -> {:#x}", name, old, new);
+    }
+
+    table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = 0;
+    table[DESCRIPTION_HEADER_CHECKSUM_OFFSET] = checksum(&table);
+    validate_table(&table)
+        .map_err(|error| Error::table(ACPI_3_FADT_SIGNATURE, error))?;
+    Ok(table)
+}
+
+/// Fails unless the installed FADT kept every field `patch` wrote
+/// into `patched`.
+fn verify(key: usize, patched: &[u8], patches: &[FadtPatch]) -> Result {
+    let installed = Sdt::locate()?
+        .find(ACPI_3_FADT_SIGNATURE)?
+        .into_iter()
+        .find(|table| table.key == key)
+        .ok_or(Error::TableNotInstalled { signature: ACPI_3_FADT_SIGNATURE })?;
+    for patch in patches.iter().filter(|patch| !patch.is_dsdt()) {
+        let patched = field_value(patched, patch.field);
+        let installed = field_value(installed.bytes(), patch.field);
+        if let (Some(patched), Some(installed)) = (patched, installed) {
+            if patched != installed {
+                let error = FadtPatchError::NotKept { patched, installed };
+                return Err(Error::BadFadtPatch { field: patch.field.name, error });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the platform's FADT with a copy that has `patches`
+/// applied, and reads it back.
+pub fn replace(patches: &[FadtPatch]) -> Result<ReplacedTable> {
+    let tables = platform_tables()?;
+    let fadt = tables.find(ACPI_3_FADT_SIGNATURE)
+        .ok_or(Error::TableNotInstalled { signature: ACPI_3_FADT_SIGNATURE })?;
+    info!("platform FADT: revision {}, {} bytes", fadt.header.revision, fadt.bytes.len());
+    let original = fadt.bytes.to_vec();
+    let patched = patch(&original, patches)?;
+
+    let key = acpi::find_installed(|table| table[..4] == ACPI_3_FADT_SIGNATURE.to_le_bytes())?
+        .first()
+        .map(|table| table.key)
+        .ok_or(Error::TableNotInstalled { signature: ACPI_3_FADT_SIGNATURE })?;
+    // Never leaves the OS without a FADT
+    let replaced = acpi::replace_table(key, original, &patched)?;
+    if let Err(error) = verify(replaced.table.key, &patched, patches) {
+        if let Err(error) = acpi::replace_table(replaced.table.key, patched, &replaced.original) {
+            error!("putting the platform FADT back: {}", error);
+        }
+        return Err(error);
+    }
+    Ok(replaced)
+}
+
+/// Replaces the platform's DSDT with a copy of the one at
+/// `address`; the protocol points the FADT at the copy.
+pub fn replace_dsdt(address: u64) -> Result<ReplacedTable> {
+    let not_a_dsdt = Error::BadFadtPatch {
+        field: "x_dsdt",
+        error: FadtPatchError::NotADsdt { address },
+    };
+    if address == 0 {
+        return Err(not_a_dsdt);
+    }
+    // SAFETY: `--fadt-patch x_dsdt=` names a table in memory
+    let header = unsafe { (address as *const DescriptionHeader).read_unaligned() };
+    if header.signature != DSDT_SIGNATURE {
+        return Err(not_a_dsdt);
+    }
+    // SAFETY: as above, header.length bytes long
+    let dsdt = unsafe { slice::from_raw_parts(address as *const u8, header.length as usize) };
+    validate_table(dsdt).map_err(|_| not_a_dsdt)?;
+
+    let platform = Sdt::locate()?
+        .find(DSDT_SIGNATURE)?
+        .into_iter()
+        .next()
+        .ok_or(Error::TableNotInstalled { signature: DSDT_SIGNATURE })?;
+    info!("replacing DSDT {} with the one at {:#x}, {} bytes", platform.key, address, dsdt.len());
+    acpi::replace_table(platform.key, platform.bytes().to_vec(), dsdt)
+}
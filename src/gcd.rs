@@ -0,0 +1,200 @@
+//! Reserving an MMIO range through the DXE Services GCD memory
+//! space map.
+//!
+//! A range the GCD knows as non-existent is claimed by no device and
+//! no memory, so we can add it as MemoryMappedIo, allocate it to our
+//! image and mark it `EFI_MEMORY_RUNTIME`. The firmware then reports
+//! it in the UEFI memory map as an EfiMemoryMappedIO runtime
+//! descriptor which the OS maps but never uses as RAM.
+
+use core::ffi::c_void;
+use core::{ptr, slice};
+use uefi::prelude::*;
+use uefi::table::Header;
+
+use crate::placement::PAGE_SIZE;
+use crate::error::{check, Context, Error, Result};
+use crate::find_configuration_table;
+
+pub const DXE_SERVICES_GUID: uefi::Guid = uefi::Guid::from_values(
+    0x05ad34ba,
+    0x6f02,
+    0x4214,
+    0x952e,
+    [0x4d, 0xa0, 0x39, 0x8e, 0x2b, 0xb9]
+);
+
+pub const EFI_MEMORY_UC: u64 = 0x1;
+pub const EFI_MEMORY_RUNTIME: u64 = 0x8000_0000_0000_0000;
+
+/// Stay clear of the real-mode IVT, BDA and legacy video/BIOS area.
+const LOW_MEMORY_LIMIT: u64 = 0x10_0000;
+/// 32-bit consumers must be able to reach the range.
+const MMIO_LIMIT: u64 = 0x1_0000_0000;
+/// AllocateMemorySpace takes the alignment as a bit count.
+const PAGE_SHIFT: usize = 12;
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcdMemoryType {
+    NonExistent = 0,
+    Reserved = 1,
+    SystemMemory = 2,
+    MemoryMappedIo = 3,
+    Persistent = 4,
+    MoreReliable = 5,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum GcdAllocateType {
+    AnySearchBottomUp = 0,
+    MaxAddressSearchBottomUp = 1,
+    Address = 2,
+    AnySearchTopDown = 3,
+    MaxAddressSearchTopDown = 4,
+}
+
+/// `EFI_GCD_MEMORY_SPACE_DESCRIPTOR`. The memory type is read as a
+/// plain `u32` since firmware may report values we do not know.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GcdMemorySpaceDescriptor {
+    pub base_address: u64,
+    pub length: u64,
+    pub capabilities: u64,
+    pub attributes: u64,
+    pub gcd_memory_type: u32,
+    pub image_handle: *mut c_void,
+    pub device_handle: *mut c_void,
+}
+
+/// The memory space part of `EFI_DXE_SERVICES`.
+#[repr(C)]
+pub struct DxeServices {
+    pub header: Header,
+    pub add_memory_space: extern "efiapi" fn(
+        gcd_memory_type: GcdMemoryType,
+        base_address: u64,
+        length: u64,
+        capabilities: u64
+    ) -> Status,
+    pub allocate_memory_space: extern "efiapi" fn(
+        gcd_allocate_type: GcdAllocateType,
+        gcd_memory_type: GcdMemoryType,
+        alignment: usize,
+        length: u64,
+        base_address: *mut u64,
+        image_handle: Handle,
+        device_handle: *mut c_void
+    ) -> Status,
+    pub free_memory_space: extern "efiapi" fn(base_address: u64, length: u64) -> Status,
+    pub remove_memory_space: extern "efiapi" fn(base_address: u64, length: u64) -> Status,
+    pub get_memory_space_descriptor: extern "efiapi" fn(
+        base_address: u64,
+        descriptor: *mut GcdMemorySpaceDescriptor
+    ) -> Status,
+    pub set_memory_space_attributes: extern "efiapi" fn(
+        base_address: u64,
+        length: u64,
+        attributes: u64
+    ) -> Status,
+    pub get_memory_space_map: extern "efiapi" fn(
+        number_of_descriptors: *mut usize,
+        memory_space_map: *mut *mut GcdMemorySpaceDescriptor
+    ) -> Status,
+}
+
+fn dxe_services() -> Result<&'static DxeServices> {
+    let entry = find_configuration_table(&DXE_SERVICES_GUID)
+        .ok_or(Error::ConfigurationTableMissing("DXE services table"))?;
+    Ok(unsafe { &*(entry.address as *const DxeServices) })
+}
+
+/// The lowest `alignment` aligned base of `length` bytes inside an
+/// unclaimed non-existent range between 1 MiB and 4 GiB.
+fn find_unclaimed(map: &[GcdMemorySpaceDescriptor], length: u64, alignment: u64) -> Option<u64> {
+    map.iter()
+        .filter(|descriptor| {
+            descriptor.gcd_memory_type == GcdMemoryType::NonExistent as u32
+                && descriptor.image_handle.is_null()
+        })
+        .find_map(|descriptor| {
+            let start = descriptor.base_address.max(LOW_MEMORY_LIMIT);
+            let end = descriptor.base_address
+                .saturating_add(descriptor.length)
+                .min(MMIO_LIMIT);
+            let base = start.checked_add(alignment - 1)? & !(alignment - 1);
+            if base.checked_add(length)? <= end {
+                Some(base)
+            } else {
+                None
+            }
+        })
+}
+
+/// Gives back a range `reserve_mmio` took.
+pub fn release_mmio(base: u64, length: u64) -> Result {
+    let ds = dxe_services()?;
+    check((ds.free_memory_space)(base, length), "FreeMemorySpace")?;
+    check((ds.remove_memory_space)(base, length), "RemoveMemorySpace")
+}
+
+/// Adds, allocates and marks as runtime an MMIO range of `length`
+/// bytes owned by `image` and returns its base address.
+pub fn reserve_mmio(image: Handle, length: u64) -> Result<u64> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let ds = dxe_services()?;
+
+    let mut count = 0;
+    let mut map_ptr = ptr::null_mut();
+    check((ds.get_memory_space_map)(&mut count, &mut map_ptr), "GetMemorySpaceMap")?;
+    // SAFETY: the firmware returned count descriptors from pool
+    let map = unsafe { slice::from_raw_parts(map_ptr, count) };
+    let base = find_unclaimed(map, length, PAGE_SIZE);
+    bs.free_pool(map_ptr as *mut u8)
+        .or_warn("free_pool (memory space map)");
+    let base = base.ok_or(Error::NoMmioRange { length })?;
+    info!("reserve_mmio: {:#x} ({:#x} bytes)", base, length);
+
+    let attributes = EFI_MEMORY_UC | EFI_MEMORY_RUNTIME;
+    check(
+        (ds.add_memory_space)(GcdMemoryType::MemoryMappedIo, base, length, attributes),
+        "AddMemorySpace"
+    )?;
+
+    let mut address = base;
+    let result = check(
+        (ds.allocate_memory_space)(
+            GcdAllocateType::Address,
+            GcdMemoryType::MemoryMappedIo,
+            PAGE_SHIFT,
+            length,
+            &mut address,
+            image,
+            ptr::null_mut()
+        ),
+        "AllocateMemorySpace"
+    ).and_then(|_| {
+        check(
+            (ds.set_memory_space_attributes)(base, length, attributes),
+            "SetMemorySpaceAttributes"
+        ).map_err(|error| {
+            // Only an allocated range can be freed
+            (ds.free_memory_space)(base, length)
+                .or_warn("FreeMemorySpace (mmio)");
+            error
+        })
+    });
+    if let Err(error) = result {
+        (ds.remove_memory_space)(base, length)
+            .or_warn("RemoveMemorySpace (mmio)");
+        return Err(error);
+    }
+
+    Ok(base)
+}
@@ -0,0 +1,113 @@
+//! Raw `EFI_LOADED_IMAGE_PROTOCOL`, for the fields uefi-rs keeps
+//! to itself.
+
+use core::ffi::c_void;
+use core::slice;
+use uefi::prelude::*;
+use uefi::proto::Protocol;
+use uefi::table::boot::MemoryType;
+use uefi::unsafe_guid;
+
+use crate::error::{Context, Result};
+
+#[repr(C)]
+#[unsafe_guid("5b1b31a1-9562-11d2-8e3f-00a0c969723b")]
+#[derive(Protocol)]
+pub struct RawLoadedImage {
+    pub revision: u32,
+    pub parent_handle: *mut c_void,
+    pub system_table: *mut c_void,
+    pub device_handle: *mut c_void,
+    pub file_path: *const c_void,
+    pub reserved: *mut c_void,
+    pub load_options_size: u32,
+    pub load_options: *const u8,
+    pub image_base: u64,
+    pub image_size: u64,
+    pub image_code_type: MemoryType,
+    pub image_data_type: MemoryType,
+    pub unload: usize,
+}
+
+impl RawLoadedImage {
+    /// LoadOptions as UCS-2 up to the terminating null, the way the
+    /// shell and Boot#### entries pass a command line. Anything else,
+    /// e.g. the binary GUID in the OptionalData of boot options the
+    /// firmware creates on its own, is ignored with a warning.
+    pub fn load_options(&self) -> &[u16] {
+        let size = self.load_options_size as usize;
+        if self.load_options.is_null() || size == 0 {
+            return &[];
+        }
+        if self.load_options as usize % 2 != 0 || size % 2 != 0 {
+            warn!("ignoring {} bytes of load options that are not UCS-2", size);
+            return &[];
+        }
+        // SAFETY: the firmware owns load_options_size bytes there
+        let options = unsafe {
+            slice::from_raw_parts(self.load_options as *const u16, size / 2)
+        };
+        let command_line = options.iter()
+            .position(|&c| c == 0)
+            .map(|length| &options[..length]);
+        match command_line {
+            Some(command_line) if command_line.iter().all(|&c| is_ucs2_text(c)) => command_line,
+            _ => {
+                warn!("ignoring {} bytes of load options that are not a command line", size);
+                &[]
+            },
+        }
+    }
+}
+
+/// A printable UCS-2 character or whitespace; surrogates are not
+/// UCS-2.
+fn is_ucs2_text(c: u16) -> bool {
+    match char::from_u32(c as u32) {
+        Some(c) => !c.is_control() || c.is_whitespace(),
+        None => false,
+    }
+}
+
+pub fn loaded_image(handle: Handle) -> Result<&'static RawLoadedImage> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let image = bs.handle_protocol::<RawLoadedImage>(handle)
+        .context("handle_protocol (LoadedImage)")?;
+    Ok(unsafe { &*image.get() })
+}
+
+pub const END_DEVICE_PATH_TYPE: u8 = 0x7f;
+pub const END_ENTIRE_DEVICE_PATH_SUBTYPE: u8 = 0xff;
+pub const MEDIA_DEVICE_PATH: u8 = 0x04;
+pub const MEDIA_FILEPATH_DP: u8 = 0x04;
+
+/// `EFI_DEVICE_PATH_PROTOCOL`: the interface is the first node.
+#[repr(C)]
+#[unsafe_guid("09576e91-6d3f-11d2-8e39-00a0c969723b")]
+#[derive(Protocol)]
+pub struct RawDevicePath {
+    pub node_type: u8,
+    pub sub_type: u8,
+    pub length: [u8; 2],
+}
+
+/// The nodes of the device path at `path`, without the end node.
+///
+/// # Safety
+///
+/// `path` must point at a well-formed device path.
+pub unsafe fn device_path_nodes<'a>(path: *const u8) -> &'a [u8] {
+    let mut node = path;
+    while *node != END_DEVICE_PATH_TYPE {
+        let length = u16::from_le_bytes([*node.add(2), *node.add(3)]) as usize;
+        if length < 4 {
+            break;
+        }
+        node = node.add(length);
+    }
+    slice::from_raw_parts(path, node as usize - path as usize)
+}
@@ -12,36 +12,58 @@ extern crate uefi_services;
 extern crate alloc;
 
 use uefi::prelude::*;
-use uefi::table::boot::{AllocateType, MemoryDescriptor, MemoryType};
-use uefi::table::cfg::{ConfigTableEntry, ACPI_GUID, ACPI2_GUID};
+use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use uefi::table::cfg::ConfigTableEntry;
+use alloc::string::String;
 use alloc::vec::*;
 use core::mem;
-use core::fmt;
+use core::slice;
 use core::ffi::c_void;
 use uefi::proto::acpi_table::AcpiTable;
 
-use uefi::{CStr16};
 use uefi::table::runtime::VariableAttributes;
 
 mod acpi;
 use acpi::*;
-
-const PHYS_ADDR: usize = 0x1000000;
-const PAGE_COUNT: usize = 1;
-
+mod acpi_sdt;
+mod boot_option;
+mod chainload;
+mod config;
+mod device;
+mod error;
+use error::{Context, Error, Result};
+mod fadt;
+mod gcd;
+mod image;
+mod oem;
+use oem::{OemPolicy, OemTable};
+mod options;
+use options::*;
+mod placement;
+use placement::*;
+mod previous;
+mod runtime;
+mod runtime_list;
+mod selftest;
+mod transaction;
+use transaction::{Step, Transaction, Undo};
+mod variable;
+mod variable_hook;
+use bpb::*;
+use bpb::aml;
+use bpb::payload::*;
+
+
+/// See `MY_CONFIGURATION_TABLE_GUID_STR`.
 const MY_CONFIGURATION_TABLE_GUID: uefi::Guid = uefi::Guid::from_values(
-    0x8868e871, 0xe4f1, 0x11d3, 0x22bc, [0x0, 0x80, 0xc7, 0x3c, 0x88, 0x81]
+    0x3b8c2a7e, 0x5d41, 0x4f0a, 0x9c6e, [0x2f, 0x1d, 0x8b, 0x7a, 0x4e, 0x53]
 );
 
-const SSDT_SIGNATURE: u32 = 0x5444_5353;                 // "SSDT"
-const SSDT_REVISION: u8 = 0x2;
-
-const OEM_VENDOR_ID: [u8; 6] = [0x4f, 0x45, 0x4d, 0x0, 0x0, 0x0]; // "OEM"
-const OEM_REVISION: u32 = 0x3;
-const OEM_TABLE_ID: u64 = 0x00000030_54425042;         // "BPBT0"
 
-const MY_TABLE_SIGNATURE: u32 = 0x5442_5042;              // "BPBT"
-const MY_TABLE_REVISION: u8 = 2;
+/// Variables under the vendor GUID that point OS readers at the
+/// BPB and at the EFI system table.
+const BPB_ADDRESS_VARIABLE: &str = "BpbAddress";
+const SYSTEM_TABLE_VARIABLE: &str = "SystemTable";
 
 const MY_VENDOR_GUID: uefi::Guid = uefi::Guid::from_values(
     0xf08ae394,
@@ -51,53 +73,47 @@ const MY_VENDOR_GUID: uefi::Guid = uefi::Guid::from_values(
     [0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d]
 );
 
-// ssdt_bpb.aml
-const MY_AML_CODE_SIZE: usize = 99 - mem::size_of::<DescriptionHeader>();
-const MY_AML_CODE: &[u8; MY_AML_CODE_SIZE] = &[
-    // 0x53, 0x53, 0x44, 0x54, 0x63, 0x00, 0x00, 0x00, 0x02, 0xe5, 0x4f, 0x45,
-    // 0x4d, 0x00, 0x00, 0x00, 0x42, 0x50, 0x42, 0x54, 0x30, 0x00, 0x00, 0x00,
-    // 0x04, 0x00, 0x00, 0x00, 0x49, 0x4e, 0x54, 0x4c, 0x05, 0x01, 0x18, 0x20,
-    0x10, 0x3e, 0x5c, 0x5f, 0x53, 0x42, 0x5f, 0x5b, 0x82, 0x36, 0x42, 0x50,
-    0x42, 0x30, 0x08, 0x5f, 0x41, 0x44, 0x52, 0x00, 0x08, 0x5f, 0x55, 0x49,
-    0x44, 0x01, 0x08, 0x5f, 0x48, 0x49, 0x44, 0x0d, 0x42, 0x50, 0x42, 0x30,
-    0x30, 0x30, 0x31, 0x00, 0x08, 0x5f, 0x43, 0x52, 0x53, 0x11, 0x11, 0x0a,
-    0x0e, 0x86, 0x09, 0x00, 0x01, 0x44, 0x33, 0x22, 0x11, 0x00, 0x10, 0x00,
-    0x00, 0x79, 0x00
-];
-
-#[repr(C, packed)]
-struct MySsdtTable {
-    header: DescriptionHeader,
-    aml_code: [u8; MY_AML_CODE_SIZE]
-}
-
-#[repr(C, packed)]
-struct MyBpbtTable {
-    header: DescriptionHeader,
-    payload: MyPayload
-}
-
-#[repr(C)]
-struct MyConfTable {
-    guid: uefi::Guid,
-    payload: *const MyPayload,
+/// The `\_SB.BPB0` device through which the OS finds the BPB
+/// range, see `win32/bpbpnp/acpi/ssdt_bpb.dsl`.
+struct BpbDevice<'a> {
+    /// The ASL scope the device is declared in.
+    scope: &'a str,
+    hid: &'a str,
+    uid: u64,
+    address: u64,
+    length: u64,
 }
 
-#[repr(C)]
-struct MyPayload {
-    magic: u64,
-    physical_address: u64,
-    length_bytes: u64
-}
 
-fn inspect<'a, E: fmt::Debug + 'a>(name: &'a str) -> impl FnOnce(E) -> E + 'a {
-    move |errdata| {
-        error!("{} returned {:?}", name, errdata);
-        errdata
+fn write_payload(
+    buffer: &mut [u8],
+    phys_addr: u64,
+    parameters: &[(String, String)]
+) -> Result<usize> {
+    let st = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+    };
+    let firmware_vendor = char::decode_utf16(st.firmware_vendor().to_u16_slice().iter().copied())
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect::<String>();
+    let firmware_revision = format!("{:?}", st.firmware_revision());
+
+    let mut writer = PayloadWriter::new(buffer)?;
+    writer.key_value("loader", "bpb-test")?;
+    writer.key_value("loader.version", env!("CARGO_PKG_VERSION"))?;
+    writer.blob("bpb.address", &phys_addr.to_le_bytes())?;
+    for (key, value) in parameters {
+        writer.key_value(key, value)?;
     }
+    writer.section("firmware", |writer| {
+        writer.key_value("vendor", &firmware_vendor)?;
+        writer.key_value("revision", &firmware_revision)
+    })?;
+    Ok(writer.finish()?)
 }
 
-fn dump_mmap() -> uefi::Result {
+fn dump_mmap() -> Result {
     let bs = unsafe {
         uefi_services::system_table()
             .as_ref()
@@ -106,16 +122,15 @@ fn dump_mmap() -> uefi::Result {
     let mmap_size = bs.memory_map_size() + 8 * mem::size_of::<MemoryDescriptor>();
     let mut mmap_buffer = vec![0; mmap_size].into_boxed_slice();
     let (_key, mmap_iter) = bs.memory_map(&mut *mmap_buffer)
-        .ignore_warning()
-        .map_err(inspect("memory_map"))?;
+        .context("memory_map")?;
     let mmap_list = mmap_iter
         .copied()
         .collect::<Vec<_>>();
     info!("memory map: {:#?}", mmap_list);
-    Ok(().into())
+    Ok(())
 }
 
-fn find_region(addr: u64) -> uefi::Result<MemoryDescriptor> {
+fn find_region(addr: u64) -> Result<MemoryDescriptor> {
     let bs = unsafe {
         uefi_services::system_table()
             .as_ref()
@@ -124,94 +139,91 @@ fn find_region(addr: u64) -> uefi::Result<MemoryDescriptor> {
     let mmap_size = bs.memory_map_size() + 8 * mem::size_of::<MemoryDescriptor>();
     let mut mmap_buffer = vec![0; mmap_size].into_boxed_slice();
     let (_key, mmap_iter) = bs.memory_map(&mut *mmap_buffer)
-        .ignore_warning()
-        .map_err(inspect("memory_map"))?;
-    let descriptor = mmap_iter
+        .context("memory_map")?;
+    mmap_iter
         .copied()
         .find(|region| {
             addr >= region.phys_start &&
                 addr < region.phys_start + 4096 * region.page_count
-        });
-    match descriptor {
-        Some(descriptor) => Ok(descriptor.into()),
-        None => Err(uefi::Status::NOT_FOUND.into()),
-    }
+        })
+        .ok_or(Error::NotInMemoryMap(addr))
 }
 
-fn enum_acpi_table_protocols() -> uefi::Result {
+fn enum_acpi_table_protocols() -> Result {
     let bs = unsafe {
         uefi_services::system_table()
             .as_ref()
             .boot_services()
     };
     let acpi_handles = bs.find_handles::<AcpiTable>()
-        .ignore_warning()?;
+        .ignore_warning()
+        .map_err(|error| Error::AcpiProtocolMissing(error.status()))?;
     for acpi_handle in acpi_handles.into_iter() {
         let acpi = bs
             .handle_protocol::<AcpiTable>(acpi_handle)
-            .ignore_warning()?;
+            .context("handle_protocol (AcpiTable)")?;
         let acpi = unsafe { &mut *acpi.get() };
         info!("Got {:?} acpi protocol", &*acpi as *const AcpiTable);
     }
-    Ok(().into())
+    Ok(())
+}
+
+fn dump_acpi_tables() -> Result {
+    let tables = platform_tables()?;
+    info!("rsdp: {:?}", tables.rsdp);
+    info!("root: {:?}", tables.root);
+    for table in tables.iter() {
+        match table {
+            Ok(table) => info!("table: {:?}", table),
+            Err(error) => warn!("broken table: {:?}", error),
+        }
+    }
+    match tables.dsdt() {
+        Ok(dsdt) => info!("dsdt: {:?}", dsdt),
+        Err(error) => warn!("dsdt: {:?}", error),
+    }
+    Ok(())
 }
 
-fn find_configuration_table(guid: &uefi::Guid) -> uefi::Result<&ConfigTableEntry> {
+fn find_configuration_table(guid: &uefi::Guid) -> Option<&'static ConfigTableEntry> {
     let st = unsafe {
         uefi_services::system_table()
             .as_ref()
     };
-    let conf_table = st.config_table()
+    st.config_table()
         .iter()
-        .find(|entry| entry.guid == *guid);
-    match conf_table {
-        Some(conf_table) => Ok(conf_table.into()),
-        None => Err(uefi::Status::NOT_FOUND.into()),
-    }
+        .find(|entry| entry.guid == *guid)
 }
 
-fn install_configuration_table(phys_addr: u64) -> uefi::Result {
+/// Returns the pool holding the vendor table, which must stay
+/// allocated for as long as the entry is installed.
+fn install_configuration_table(phys_addr: u64, length_bytes: u64) -> Result<*mut u8> {
     let bs = unsafe {
         uefi_services::system_table()
             .as_ref()
             .boot_services()
     };
 
-    let cfg_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let cfg_table_size = mem::size_of::<MyConfTable>();
-    let cfg_table = bs.allocate_pool(cfg_table_pool, cfg_table_size)
-        .map_err(inspect("allocate_pool (cfg_table)"))
-        .ignore_warning()?;
-    info!("cfg_table: {:?}", cfg_table);
-
+    // The vendor table must be in RUNTIME_SERVICES_DATA so that it
+    // stays mapped and at the same physical address in the OS
     let payload_pool = MemoryType::RUNTIME_SERVICES_DATA;
     let payload_size = mem::size_of::<MyPayload>();
     let payload = bs.allocate_pool(payload_pool, payload_size)
-        .map_err(inspect("allocate_pool (payload)"))
-        .ignore_warning()?;
+        .ignore_warning()
+        .map_err(|error| Error::PoolAllocationFailed {
+            memory_type: payload_pool,
+            size: payload_size,
+            status: error.status(),
+        })?;
     info!("payload: {:?}", payload);
 
-    let cfg_table_data = MyConfTable {
-        guid: ACPI2_GUID,
-        payload: payload.cast()
-    };
-
     let payload_data = MyPayload {
-        magic: 0xfeeddead,
+        magic: MY_PAYLOAD_MAGIC,
         physical_address: phys_addr,
-        length_bytes: PAGE_COUNT as u64 * 4096,
+        length_bytes,
     };
 
-    // SAFETY: not safe at all
-    unsafe {
-        bs.memmove(
-            cfg_table,
-            &cfg_table_data as *const MyConfTable as *const u8,
-            cfg_table_size
-        );
-    }
-
-    // SAFETY: damn this is hot
+    // SAFETY: payload is a fresh pool of payload_size bytes
     unsafe {
         bs.memmove(
             payload,
@@ -220,535 +232,438 @@ fn install_configuration_table(phys_addr: u64) -> uefi::Result {
         );
     }
 
-    // WTF: is it my responsibility to convert pointer to configuration table?
-    // WTF: is it my responsibility to parse conf tables and append my guid
-    // WTF: is it my responsibility to convert pointer to payload table if guid is my own?
-    // WTF: is it my responsibility to convert pointer to payload table if guid is by a spec?
-    // WTF: is memory preserved at fixed physical address when allocated as RUNTIME_SERVICES_DATA?
-
-    // SAFETY: oof
-    unsafe {
-        let guid = &ACPI2_GUID;
-        bs.install_configuration_table(guid, cfg_table as *mut c_void)
-            .map_err(inspect("InstallConfigurationTable"))?;
+    // The firmware appends or replaces our entry in the system
+    // table's ConfigurationTable. SetVirtualAddressMap converts the
+    // ConfigurationTable pointer itself but never a VendorTable
+    // pointer under a GUID it does not know, and MyPayload only
+    // carries physical addresses, so nothing needs converting here.
+    let result = unsafe {
+        bs.install_configuration_table(&MY_CONFIGURATION_TABLE_GUID, payload as *mut c_void)
+            .context("InstallConfigurationTable")
+    };
+    if let Err(error) = result {
+        bs.free_pool(payload)
+            .or_warn("free_pool (payload)");
+        return Err(error);
     }
 
-    Ok(().into())
+    Ok(payload)
 }
 
-unsafe fn checksum8<T: Sized>(value: &T) -> u8 {
-    use core::slice;
-    use core::num::Wrapping;
-    let buffer = slice::from_raw_parts(
-        value as *const T as *const Wrapping<u8>,
-        mem::size_of::<T>(),
+fn bpb_device_aml(device: &BpbDevice) -> Result<Vec<u8>> {
+    use aml::*;
+
+    let memory32 = Memory32Fixed::new(
+        true,
+        device.address as u32,
+        device.length as u32
     );
-    ((0x100 - u32::from(buffer.iter().copied().sum::<Wrapping<u8>>().0)) & 0xff) as u8
+    let memory64 = QWordMemory::new(
+        Cacheability::Cacheable,
+        true,
+        device.address,
+        device.length
+    ).ok_or(Error::BadPlacement("BPB range runs past the end of the address space"))?;
+    // Memory32Fixed can only describe a range that ends below 4 GiB,
+    // anything else would be silently truncated
+    let fits_memory32 = device.address
+        .checked_add(device.length)
+        .map_or(false, |end| end <= 1 << 32);
+    let resource: &dyn Aml = if fits_memory32 {
+        &memory32
+    } else {
+        &memory64
+    };
+    let crs = ResourceTemplate::new(vec![resource]);
+
+    let adr = Name::new("_ADR", &0u64);             // _ADR: Required but not used
+    let uid = Name::new("_UID", &device.uid);
+    let hid = Name::new("_HID", &device.hid);      // _HID: Vendor-defined device
+    let crs = Name::new("_CRS", &crs);             // _CRS: Current Resource Settings
+    let bpb0 = Device::new(BPB_DEVICE_NAME, vec![&adr, &uid, &hid, &crs]);
+    Ok(Scope::new(device.scope, vec![&bpb0])
+        .to_aml_bytes())
 }
 
-fn install_fadt3(phys_addr: u64) -> uefi::Result<usize> {
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
+fn install_my_ssdt_table(device: &BpbDevice, oem: OemIdentity) -> Result<InstalledTable> {
+    let aml_code = bpb_device_aml(device)?;
 
-    let acpi = bs
-        .locate_protocol::<AcpiTable>()
-        .ignore_warning()?;
-    let acpi = unsafe { &mut *acpi.get() };
-
-    let acpi_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let acpi_table_size = mem::size_of::<FixedDescriptionTable3>();
-    let acpi_table = bs.allocate_pool(acpi_table_pool, acpi_table_size)
-        .map_err(inspect("allocate_pool (acpi_table)"))
-        .ignore_warning()?;
-    info!("acpi_table: {:?}", acpi_table);
-
-    let rsdp_ptr = find_configuration_table(&ACPI2_GUID)
-        .map_err(inspect("find_configuration_table(ACPI2)"))
-        .or_else(|_| find_configuration_table(&ACPI_GUID))
-        .map_err(inspect("find_configuration_table(ACPI1)"))
-        .ignore_warning()?;
-
-    let rsdp = unsafe {
-        (rsdp_ptr.address as *const RootSystemDescriptionPointer3)
-            .read_unaligned()
-    };
+    TableBuilder::new(SSDT_SIGNATURE, SSDT_REVISION)
+        .oem(oem)
+        .body(&aml_code)
+        .install()
+}
 
-    let sdt_ptr = {
-        if rsdp.revision >= ACPI_2_RSDP_REVISION && rsdp.xsdt_address != 0 {
-            rsdp.xsdt_address as usize
-        } else {
-            rsdp.rsdt_address as usize
-        }
+fn install_bpbt_table(
+    phys_addr: u64,
+    length_bytes: u64,
+    oem: OemIdentity
+) -> Result<InstalledTable> {
+    // TBD: test my own ACPI tables
+    let payload = MyPayload {
+        magic: MY_PAYLOAD_MAGIC,
+        physical_address: phys_addr,
+        length_bytes,
     };
-    if sdt_ptr == 0 {
-        error!("Could not find RSD pointer table!");
-        return Err(uefi::Status::UNSUPPORTED.into());
-    }
 
-    let sdt = unsafe {
-        (sdt_ptr as *const DescriptionHeader)
-            .read_unaligned()
-    };
+    TableBuilder::new(MY_TABLE_SIGNATURE, MY_TABLE_REVISION)
+        .oem(oem)
+        .body_struct(&payload)
+        .install()
+}
 
-    let mut acpi_table_data = FixedDescriptionTable3 {
-        header: DescriptionHeader {
-            signature: ACPI_3_FADT_SIGNATURE,
-            length: acpi_table_size as u32,
-            revision: ACPI_3_FADT_REVISION,
-            checksum: 0,
-            oem_id: sdt.oem_id,
-            oem_table_id: sdt.oem_table_id,
-            oem_revision: sdt.oem_revision,
-            creator_id: 0,
-            creator_revision: 0
-        },
-        firmware_ctrl: 0,
-        dsdt: 0,
-        reserved0: 0,
-        preferred_pm_profile: 0,
-        sci_int: 0,
-        smi_cmd: 0,
-        acpi_enable: 0,
-        acpi_disable: 0,
-        s4_bios_req: 0,
-        pstate_cnt: 0,
-        pm1a_evt_blk: 0,
-        pm1b_evt_blk: 0,
-        pm1a_cnt_blk: 0,
-        pm1b_cnt_blk: 0,
-        pm2_cnt_blk: 0,
-        pm_tmr_blk: 0,
-        gpe0_blk: 0,
-        gpe1_blk: 0,
-        pm1_evt_len: 0,
-        pm1_cnt_len: 0,
-        pm2_cnt_len: 0,
-        pm_tm_len: 0,
-        gpe0_blk_len: 0,
-        gpe1_blk_len: 0,
-        gpe1_base: 0,
-        cst_cnt: 0,
-        p_lvl2_lat: 0,
-        p_lvl3_lat: 0,
-        flush_size: 0,
-        flush_stride: 0,
-        duty_offset: 0,
-        duty_width: 0,
-        day_alrm: 0,
-        mon_alrm: 0,
-        century: 0,
-        iapc_boot_arch: 0,
-        reserved1: 0,
-        flags: 0,
-        reset_reg: GenericAddressSpace::new(),
-        reset_value: 0,
-        reserved2: 0,
-        reserved3: 0,
-        reserved4: 0,
-        x_firmware_ctrl: 0,
-        x_dsdt: 0,
-        x_pm1a_evt_blk: GenericAddressSpace::new(),
-        x_pm1b_evt_blk: GenericAddressSpace::new(),
-        x_pm1a_cnt_blk: GenericAddressSpace::new(),
-        x_pm1b_cnt_blk: GenericAddressSpace::new(),
-        x_pm2_cnt_blk: GenericAddressSpace::new(),
-        x_pm_tmr_blk: GenericAddressSpace::new(),
-        x_gpe0_blk: GenericAddressSpace::new(),
-        x_gpe1_blk: GenericAddressSpace::new(),
-    };
+/// A `UEFI` table with our vendor GUID as its identifier, carrying
+/// `data` right after the data offset.
+fn install_uefi_table(data: &[u8], oem: OemIdentity) -> Result<InstalledTable> {
+    let data_offset = mem::size_of::<UefiTableHeader>() as u16;
+    let mut body = Vec::with_capacity(data_offset as usize + data.len());
+    body.extend_from_slice(&MY_VENDOR_GUID_BYTES);
+    body.extend_from_slice(&data_offset.to_le_bytes());
+    body.extend_from_slice(data);
+
+    TableBuilder::new(UEFI_TABLE_SIGNATURE, UEFI_TABLE_REVISION)
+        .oem(oem)
+        .body(&body)
+        .install()
+}
 
-    // SAFETY: acpi_table_data must be properly initialized e.g. packed
-    unsafe {
-        acpi_table_data.header.checksum = checksum8(&acpi_table_data);
+/// The steps `publish` will take for `options`, in order.
+fn planned_steps(options: &Options) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if cfg!(feature = "runtime-driver") {
+        steps.push(Step::Runtime);
     }
-
-    unsafe {
-        // Entire table must sum to zero
-        assert!(checksum8(&acpi_table_data) == 0);
+    if options.mmio {
+        steps.push(Step::Mmio);
     }
-
-    // SAFETY: not safe at all
-    unsafe {
-        bs.memmove(
-            acpi_table,
-            &acpi_table_data as *const FixedDescriptionTable3 as *const u8,
-            acpi_table_size
-        );
+    steps.push(Step::Bpb);
+    if options.has(Transport::Hook) {
+        steps.push(Step::Hook);
     }
-
-    let table_key = unsafe {
-        acpi.install_acpi_table(acpi_table as *const c_void, acpi_table_size)
-            .map_err(inspect("install_acpi_table"))
-            .ignore_warning()?
-    };
-
-    Ok(table_key.into())
-}
-
-fn install_fadt1(phys_addr: u64) -> uefi::Result<usize> {
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
-
-    let acpi = bs
-        .locate_protocol::<AcpiTable>()
-        .ignore_warning()?;
-    let acpi = unsafe { &mut *acpi.get() };
-
-    let acpi_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let acpi_table_size = mem::size_of::<FixedDescriptionTable1>();
-    let acpi_table = bs.allocate_pool(acpi_table_pool, acpi_table_size)
-        .map_err(inspect("allocate_pool (acpi_table)"))
-        .ignore_warning()?;
-    info!("acpi_table: {:?}", acpi_table);
-
-    let rsdp_ptr = find_configuration_table(&ACPI2_GUID)
-        .map_err(inspect("find_configuration_table (ACPI2)"))
-        .or_else(|_| find_configuration_table(&ACPI_GUID))
-        .map_err(inspect("find_configuration_table (ACPI1)"))
-        .ignore_warning()?;
-
-    let rsdp = unsafe {
-        (rsdp_ptr.address as *const RootSystemDescriptionPointer3)
-            .read_unaligned()
-    };
-
-    let sdt_ptr = {
-        if rsdp.revision >= ACPI_2_RSDP_REVISION && rsdp.xsdt_address != 0 {
-            rsdp.xsdt_address as usize
-        } else {
-            rsdp.rsdt_address as usize
+    if options.has(Transport::ConfigTable) {
+        steps.push(Step::ConfigTable);
+    }
+    if let Some(patches) = &options.fadt {
+        if fadt::dsdt_address(patches).is_some() {
+            steps.push(Step::Dsdt);
         }
-    };
-    if sdt_ptr == 0 {
-        error!("Could not find RSD pointer table!");
-        return Err(uefi::Status::UNSUPPORTED.into());
     }
-
-    let sdt = unsafe {
-        (sdt_ptr as *const DescriptionHeader)
-            .read_unaligned()
-    };
-
-    let mut acpi_table_data = FixedDescriptionTable1 {
-        header: DescriptionHeader {
-            signature: ACPI_1_FADT_SIGNATURE,
-            length: acpi_table_size as u32,
-            revision: ACPI_1_FADT_REVISION,
-            checksum: 0,
-            oem_id: sdt.oem_id,
-            oem_table_id: sdt.oem_table_id,
-            oem_revision: sdt.oem_revision,
-            creator_id: 0,
-            creator_revision: 0
-        },
-        firmware_ctrl: 0,
-        dsdt: 0,
-        int_model: 0,
-        reserved0: 0,
-        sci_int: 0,
-        smi_cmd: 0,
-        acpi_enable: 0,
-        acpi_disable: 0,
-        s4_bios_req: 0,
-        reserved1: 0,
-        pm1a_evt_blk: 0,
-        pm1b_evt_blk: 0,
-        pm1a_cnt_blk: 0,
-        pm1b_cnt_blk: 0,
-        pm2_cnt_blk: 0,
-        pm_tmr_blk: 0,
-        gpe0_blk: 0,
-        gpe1_blk: 0,
-        pm1_evt_len: 0,
-        pm1_cnt_len: 0,
-        pm2_cnt_len: 0,
-        pm_tm_len: 0,
-        gpe0_blk_len: 0,
-        gpe1_blk_len: 0,
-        gpe1_base: 0,
-        reserved2: 0,
-        p_lvl2_lat: 0,
-        p_lvl3_lat: 0,
-        flush_size: 0,
-        flush_stride: 0,
-        duty_offset: 0,
-        duty_width: 0,
-        day_alrm: 0,
-        mon_alrm: 0,
-        century: 0,
-        reserved3: 0,
-        reserved4: 0,
-        reserved5: 0,
-        flags: 0,
-    };
-
-    // SAFETY: acpi_table_data must be properly initialized e.g. packed
-    unsafe {
-        acpi_table_data.header.checksum = checksum8(&acpi_table_data);
+    if options.fadt.is_some() {
+        steps.push(Step::Fadt);
     }
-
-    unsafe {
-        // Entire table must sum to zero
-        assert!(checksum8(&acpi_table_data) == 0);
+    if options.has(Transport::Acpi) {
+        steps.push(Step::Bpbt);
+        match options.device {
+            DevicePlacement::Ssdt => {
+                steps.push(Step::SsdtPatch);
+                steps.push(Step::SsdtInstall);
+            },
+            _ => steps.push(Step::SsdtPlace),
+        }
     }
-
-    // SAFETY: not safe at all
-    unsafe {
-        bs.memmove(
-            acpi_table,
-            &acpi_table_data as *const FixedDescriptionTable1 as *const u8,
-            acpi_table_size
-        );
+    if options.has(Transport::Uefi) {
+        steps.push(Step::Uefi);
     }
-
-    let table_key = unsafe {
-        acpi.install_acpi_table(acpi_table as *const c_void, acpi_table_size)
-            .map_err(inspect("install_acpi_table"))
-            .ignore_warning()?
-    };
-
-    Ok(table_key.into())
-}
-
-fn patch_dword(v: &mut [u8], old: u32, new: u32) {
-    let mut start = 0;
-    let mut end = 4;
-    while end <= v.len() {
-        let window = &mut v[start..end];
-        if *window == u32::to_le_bytes(old) {
-            window[0] = new as u8;
-            window[1] = (new >> 8) as u8;
-            window[2] = (new >> 16) as u8;
-            window[3] = (new >> 24) as u8;
-        }
-        start += 1;
-        end += 1;
+    if options.runtime_list {
+        steps.push(Step::RuntimeList);
+    }
+    if options.has(Transport::Variable) {
+        steps.push(Step::Variables);
     }
+    steps.push(Step::State);
+    steps
 }
 
-fn install_my_ssdt_table(phys_addr: u64) -> uefi::Result<usize> {
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
+/// Allocates and fills the BPB and publishes it on every transport
+/// in `options`. Stops at the first failure, leaving the rollback
+/// to the caller. Returns the BPB pages and the payload size.
+fn publish(
+    handle: Handle,
+    transaction: &mut Transaction,
+    options: &Options,
+    config: &config::Config,
+    oem: &OemPolicy,
+    system_table: &SystemTable<Boot>
+) -> Result<(BpbRegion, usize)> {
+    #[cfg(feature = "runtime-driver")]
+    {
+        transaction.run(Step::Runtime, || runtime::init(system_table))?;
+        transaction.undo(Undo::CloseRuntimeEvent);
+    }
 
-    let acpi = bs
-        .locate_protocol::<AcpiTable>()
-        .ignore_warning()?;
-    let acpi = unsafe { &mut *acpi.get() };
-
-    let acpi_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let acpi_table_size = mem::size_of::<MySsdtTable>();
-    let acpi_table = bs.allocate_pool(acpi_table_pool, acpi_table_size)
-        .map_err(inspect("allocate_pool (acpi_table)"))
-        .ignore_warning()?;
-    info!("acpi_table: {:?}", acpi_table);
-
-    let rsdp_ptr = find_configuration_table(&ACPI2_GUID)
-        .map_err(inspect("find_configuration_table (ACPI2)"))
-        .or_else(|_| find_configuration_table(&ACPI_GUID))
-        .map_err(inspect("find_configuration_table (ACPI1)"))
-        .ignore_warning()?;
-
-    let rsdp = unsafe {
-        (rsdp_ptr.address as *const RootSystemDescriptionPointer3)
-            .read_unaligned()
-    };
+    let mmio = if options.mmio {
+        let mmio_addr = transaction.run(Step::Mmio, || gcd::reserve_mmio(handle, PAGE_SIZE))?;
+        info!("mmio_addr: {:#x}", mmio_addr);
+        transaction.undo(Undo::ReleaseMmio { base: mmio_addr, length: PAGE_SIZE });
 
-    let sdt_ptr = {
-        if rsdp.revision >= ACPI_2_RSDP_REVISION && rsdp.xsdt_address != 0 {
-            rsdp.xsdt_address as usize
-        } else {
-            rsdp.rsdt_address as usize
-        }
+        let mmio_region = find_region(mmio_addr)?;
+        info!("mmio_region: {:#?}", mmio_region);
+        Some((mmio_addr, PAGE_SIZE))
+    } else {
+        None
     };
-    if sdt_ptr == 0 {
-        error!("Could not find RSD pointer table!");
-        return Err(uefi::Status::UNSUPPORTED.into());
-    }
 
-    let sdt = unsafe {
-        (sdt_ptr as *const DescriptionHeader)
-            .read_unaligned()
-    };
+    let (bpb_region, payload_size) = transaction.run(Step::Bpb, || {
+        let bpb_region = options.policy.allocate()?;
+        info!("phys_addr: {:#x}", bpb_region.address);
 
-    // Patching AML code is fine because we don't change its length
-    let mut aml_code = *MY_AML_CODE;
-    patch_dword(&mut aml_code, 0x11223344, phys_addr as u32);
-
-    let mut acpi_table_data = MySsdtTable {
-        header: DescriptionHeader {
-            signature: SSDT_SIGNATURE,
-            length: acpi_table_size as u32,
-            revision: SSDT_REVISION,
-            checksum: 0,
-            // oem_id: sdt.oem_id,
-            // oem_table_id: sdt.oem_table_id,
-            // oem_revision: sdt.oem_revision,
-            oem_id: OEM_VENDOR_ID,
-            oem_table_id: OEM_TABLE_ID,
-            oem_revision: OEM_REVISION,
-            creator_id: 0,
-            creator_revision: 0
-        },
-        aml_code
-    };
+        // SAFETY: the pages were just allocated for us
+        let bpb = unsafe {
+            slice::from_raw_parts_mut(bpb_region.address as *mut u8, bpb_region.length as usize)
+        };
+        let payload_size = match write_payload(bpb, bpb_region.address, &config.parameters) {
+            Ok(payload_size) => payload_size,
+            Err(error) => {
+                let bs = system_table.boot_services();
+                bs.free_pages(bpb_region.address, (bpb_region.length / PAGE_SIZE) as usize)
+                    .or_warn("free_pages (bpb)");
+                return Err(error);
+            },
+        };
+        Ok((bpb_region, payload_size))
+    })?;
+    transaction.undo(Undo::FreePages {
+        address: bpb_region.address,
+        pages: (bpb_region.length / PAGE_SIZE) as usize,
+    });
+    let phys_addr = bpb_region.address;
+    let bpb_length = bpb_region.length;
+    info!("payload_size: {}", payload_size);
+
+    let region = find_region(phys_addr)?;
+    info!("region: {:#?}", region);
 
-    // SAFETY: acpi_table_data must be properly initialized e.g. packed
-    unsafe {
-        acpi_table_data.header.checksum = checksum8(&acpi_table_data);
+    #[cfg(feature = "runtime-driver")]
+    if options.has(Transport::Hook) {
+        // SAFETY: the payload was just written to the BPB pages
+        let bpb = unsafe { slice::from_raw_parts(phys_addr as *const u8, payload_size) };
+        transaction.run(Step::Hook, || variable_hook::install(bpb, &options.vendor_guid))?;
+        transaction.undo(Undo::UnhookVariables);
     }
 
-    unsafe {
-        // Entire table must sum to zero
-        assert!(checksum8(&acpi_table_data) == 0);
+    if options.has(Transport::ConfigTable) {
+        let payload = transaction.run(Step::ConfigTable, || install_configuration_table(phys_addr, bpb_length))?;
+        transaction.undo(Undo::FreePool(payload));
+        transaction.undo(Undo::RemoveConfigurationTable(MY_CONFIGURATION_TABLE_GUID));
     }
 
-    // SAFETY: not safe at all
-    unsafe {
-        bs.memmove(
-            acpi_table,
-            &acpi_table_data as *const MySsdtTable as *const u8,
-            acpi_table_size
-        );
+    // The replaced FADT is not kept for the next run, which must not
+    // take the FADT away; it patches the patched copy instead
+    if let Some(patches) = &options.fadt {
+        if let Some(address) = fadt::dsdt_address(patches) {
+            let replaced = transaction.run(Step::Dsdt, || fadt::replace_dsdt(address))?;
+            transaction.undo(Undo::ReinstallTable(replaced.original));
+            transaction.undo(Undo::UninstallTable(replaced.table.key));
+        }
+        let replaced = transaction.run(Step::Fadt, || fadt::replace(patches))?;
+        transaction.undo(Undo::ReinstallTable(replaced.original));
+        transaction.undo(Undo::UninstallTable(replaced.table.key));
     }
 
-    let table_key = unsafe {
-        acpi.install_acpi_table(acpi_table as *const c_void, acpi_table_size)
-            .map_err(inspect("install_acpi_table"))
-            .ignore_warning()?
-    };
-
-    Ok(0.into())
-}
+    let mut state = previous::RunState {
+        bpb_address: phys_addr,
+        bpb_pages: bpb_length / PAGE_SIZE,
+        vendor_guid: Some(options.vendor_guid),
+        mmio,
+        ..Default::default()
+    };
+
+    if options.has(Transport::Acpi) {
+        let table_key = transaction.run(Step::Bpbt, || {
+            install_bpbt_table(phys_addr, bpb_length, oem.identity(OemTable::Bpbt)?)
+        })?;
+        info!("table_key: {:?}", table_key);
+        transaction.undo(Undo::UninstallTable(table_key.key));
+        state.bpbt_key = Some(table_key.key);
+
+        let bpb0 = BpbDevice {
+            scope: &options.device_scope,
+            hid: BPB_DEVICE_HID,
+            uid: BPB_DEVICE_UID,
+            address: phys_addr,
+            length: bpb_length,
+        };
+        match options.device {
+            // An earlier inject or patch run left a BPB0 in a
+            // platform table; a second one would clash with it
+            DevicePlacement::Ssdt => match transaction.run(Step::SsdtPatch, || device::patch(&bpb0))? {
+                Some(patched) => {
+                    warn!("{} exists, patched it instead of installing an SSDT", BPB_DEVICE_NAME);
+                    transaction.undo(Undo::RestoreTable {
+                        address: patched.address,
+                        original: patched.original,
+                    });
+                },
+                None => {
+                    let table_key1 = transaction.run(Step::SsdtInstall, || {
+                        install_my_ssdt_table(&bpb0, oem.identity(OemTable::Ssdt)?)
+                    })?;
+                    info!("table_key1: {:?}", table_key1);
+                    transaction.undo(Undo::UninstallTable(table_key1.key));
+                    state.ssdt_key = Some(table_key1.key);
+                },
+            },
+            // Neither is kept for the next run, which must not take
+            // a platform table away; it patches BPB0 again instead
+            placement => {
+                match transaction.run(Step::SsdtPlace, || device::place(placement, &bpb0))? {
+                    device::Placed::Patched(patched) => {
+                        transaction.undo(Undo::RestoreTable {
+                            address: patched.address,
+                            original: patched.original,
+                        });
+                    },
+                    device::Placed::Injected(injected) => {
+                        transaction.undo(Undo::ReinstallTable(injected.original));
+                        transaction.undo(Undo::UninstallTable(injected.table.key));
+                    },
+                }
+            },
+        }
+    }
 
-fn install_bpbt_table(phys_addr: u64) -> uefi::Result<usize> {
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
+    if options.has(Transport::Uefi) {
+        let uefi_table = transaction.run(Step::Uefi, || match options.uefi_data {
+            UefiData::Pointer => {
+                let payload = MyPayload {
+                    magic: MY_PAYLOAD_MAGIC,
+                    physical_address: phys_addr,
+                    length_bytes: bpb_length,
+                };
+                install_uefi_table(as_bytes(&payload), oem.identity(OemTable::Uefi)?)
+            },
+            UefiData::Payload => {
+                // SAFETY: the payload was just written to the BPB pages
+                let bpb = unsafe { slice::from_raw_parts(phys_addr as *const u8, payload_size) };
+                install_uefi_table(bpb, oem.identity(OemTable::Uefi)?)
+            },
+        })?;
+        info!("uefi_table: {:?}", uefi_table);
+        // Not kept in BpbState; the next run finds it by identifier
+        transaction.undo(Undo::UninstallTable(uefi_table.key));
+    }
 
-    let acpi = bs
-        .locate_protocol::<AcpiTable>()
-        .ignore_warning()?;
-    let acpi = unsafe { &mut *acpi.get() };
-
-    let acpi_table_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let acpi_table_size = mem::size_of::<MyBpbtTable>();
-    let acpi_table = bs.allocate_pool(acpi_table_pool, acpi_table_size)
-        .map_err(inspect("allocate_pool (acpi_table)"))
-        .ignore_warning()?;
-    info!("acpi_table: {:?}", acpi_table);
-
-    let rsdp_ptr = find_configuration_table(&ACPI2_GUID)
-        .map_err(inspect("find_configuration_table (ACPI2)"))
-        .or_else(|_| find_configuration_table(&ACPI_GUID))
-        .map_err(inspect("find_configuration_table (ACPI1)"))
-        .ignore_warning()?;
-
-    let rsdp = unsafe {
-        (rsdp_ptr.address as *const RootSystemDescriptionPointer3)
-            .read_unaligned()
-    };
+    if options.runtime_list {
+        let runtime_list = transaction.run(Step::RuntimeList, || {
+            runtime_list::publish_runtime_list(&options.vendor_guid)
+        })?;
+        info!("runtime_list: {:#x}", runtime_list);
+        state.runtime_list = Some(runtime_list);
+        transaction.undo(Undo::FreeRuntimeList(runtime_list));
+        transaction.undo(Undo::DeleteVariable {
+            name: runtime_list::RUNTIME_LIST_VARIABLE,
+            vendor: options.vendor_guid,
+        });
+    }
 
-    let sdt_ptr = {
-        if rsdp.revision >= ACPI_2_RSDP_REVISION && rsdp.xsdt_address != 0 {
-            rsdp.xsdt_address as usize
-        } else {
-            rsdp.rsdt_address as usize
+    if options.has(Transport::Variable) {
+        // SystemTable<Boot> is a thin wrapper around the pointer to the
+        // EFI_SYSTEM_TABLE, which is what OS readers need
+        let system_table_addr = unsafe {
+            *(system_table as *const SystemTable<Boot> as *const u64)
+        };
+        let attributes = VariableAttributes::RUNTIME_ACCESS | VariableAttributes::BOOTSERVICE_ACCESS;
+        let variables = [
+            (BPB_ADDRESS_VARIABLE, phys_addr),
+            (SYSTEM_TABLE_VARIABLE, system_table_addr),
+        ];
+        let mut written = 0;
+        let result = transaction.run(Step::Variables, || {
+            for (name, value) in &variables {
+                variable::write_variable(name, &options.vendor_guid, attributes, &value.to_le_bytes())?;
+                written += 1;
+            }
+            Ok(())
+        });
+        // A failed second write leaves the first one to take back
+        for &(name, _) in &variables[..written] {
+            transaction.undo(Undo::DeleteVariable { name, vendor: options.vendor_guid });
         }
-    };
-    if sdt_ptr == 0 {
-        error!("Could not find RSD pointer table!");
-        return Err(uefi::Status::UNSUPPORTED.into());
+        result?;
     }
 
-    let sdt = unsafe {
-        (sdt_ptr as *const DescriptionHeader)
-            .read_unaligned()
-    };
+    transaction.run(Step::State, || previous::save(&state))?;
+    transaction.undo(Undo::DeleteVariable {
+        name: previous::STATE_VARIABLE,
+        vendor: MY_VENDOR_GUID,
+    });
 
-    // TBD: test my own ACPI tables
-    let mut acpi_table_data = MyBpbtTable {
-        header: DescriptionHeader {
-            signature: MY_TABLE_SIGNATURE,
-            length: acpi_table_size as u32,
-            revision: MY_TABLE_REVISION,
-            checksum: 0,
-            oem_id: sdt.oem_id,
-            oem_table_id: sdt.oem_table_id,
-            oem_revision: sdt.oem_revision,
-            creator_id: 0,
-            creator_revision: 0
-        },
-        payload: MyPayload {
-            magic: 0xfeeddead,
-            physical_address: phys_addr,
-            length_bytes: PAGE_COUNT as u64 * 4096,
-        }
-    };
+    Ok((bpb_region, payload_size))
+}
 
-    // SAFETY: acpi_table_data must be properly initialized e.g. packed
-    unsafe {
-        acpi_table_data.header.checksum = checksum8(&acpi_table_data);
+fn bpb_main(handle: Handle, system_table: &SystemTable<Boot>) -> Result {
+    let loaded_image = image::loaded_image(handle)?;
+    let config = config::load(handle, loaded_image)?
+        .unwrap_or_default();
+    info!("config: {:?}", config);
+
+    let command_line = split_command_line(loaded_image.load_options());
+    let mut args = config.args.clone();
+    args.extend(command_line.iter().cloned());
+    let options = Options::parse(&args)
+        .map_err(|error| {
+            info!("\n{}", USAGE);
+            Error::from(error)
+        })?;
+    if options.help {
+        info!("\n{}", USAGE);
+        return Ok(());
     }
-
-    unsafe {
-        // Entire table must sum to zero
-        assert!(checksum8(&acpi_table_data) == 0);
+    info!("options: {:?}", options);
+
+    if options.install {
+        // The entry runs us again with everything but --install
+        let load_options = command_line.iter()
+            .filter(|arg| arg.as_str() != "--install")
+            .cloned()
+            .collect::<Vec<_>>();
+        boot_option::install(boot_option::OptionKind::current(), loaded_image, &join_command_line(&load_options))?;
+        return Ok(());
+    }
+    if options.uninstall {
+        boot_option::uninstall(boot_option::OptionKind::current())?;
+        return Ok(());
     }
 
-    // SAFETY: not safe at all
-    unsafe {
-        bs.memmove(
-            acpi_table,
-            &acpi_table_data as *const MyBpbtTable as *const u8,
-            acpi_table_size
-        );
+    if options.dump_acpi {
+        enum_acpi_table_protocols()?;
+        dump_acpi_tables()?;
     }
 
-    let table_key = unsafe {
-        acpi.install_acpi_table(acpi_table as *const c_void, acpi_table_size)
-            .map_err(inspect("install_acpi_table"))
-            .ignore_warning()?
-    };
+    if options.dump_mmap {
+        dump_mmap()?;
+    }
 
-    Ok(0.into())
-}
+    // A second run from the shell replaces what the first published.
+    // That happens before the transaction, so a failed run leaves
+    // neither published, see USAGE
+    let revisions = previous::clean_up(&options.vendor_guid)?;
+    let oem = OemPolicy::new(&config, &options, revisions);
+    info!("oem: {:?}", oem);
+
+    let mut transaction = Transaction::new(&planned_steps(&options));
+    let published = publish(handle, &mut transaction, &options, &config, &oem, system_table)
+        .and_then(|(bpb_region, payload_size)| {
+            // Before the commit, so a failure takes the hook and the
+            // virtual address change event back before we are unloaded
+            if options.selftest {
+                selftest::run(&options, &bpb_region, payload_size)?;
+            }
+            Ok(())
+        });
+    if let Err(error) = published {
+        transaction.rollback();
+        return Err(error);
+    }
+    transaction.commit();
 
-fn allocate_mmio_page() -> uefi::Result<u64> {
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
+    info!("bpb_main -- ok");
 
-    for n in 0..256 {
-        let pages_type = AllocateType::Address (4096 * n);
-        let pages_pool = MemoryType::MMIO;
-        let pages_count = PAGE_COUNT;
-        let result = bs.allocate_pages(pages_type, pages_pool, pages_count)
-            .map_err(inspect("allocate_pages"))
-            .ignore_warning();
-        match result {
-            Ok(mmio_addr) => {
-                info!("allocate_mmio_page {:#x} -> SUCCESS", mmio_addr);
-                return Ok(mmio_addr.into())
-            },
-            Err(error) => {
-                info!("allocate_mmio_page {:#x} -> {:?}", 4096*n, error.status());
-            },
-        }
+    if let Some(path) = &options.chainload {
+        chainload::chainload(handle, system_table, path, options.chainload_options.as_deref())?;
     }
-    Err(uefi::Status::NOT_FOUND.into())
+
+    Ok(())
 }
 
 #[entry]
@@ -756,89 +671,12 @@ fn efi_main(handle: Handle, system_table: SystemTable<Boot>) -> uefi::Status {
     uefi_services::init(&system_table)
         .expect_success("this is only the beginning");
     info!("bpb_main");
-    let bs = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .boot_services()
-    };
-
-    enum_acpi_table_protocols()?;
-
-    let mmio_addr = allocate_mmio_page()
-        .ignore_warning()
-        .map_err(inspect("allocate_mmio_page"))?;
-    info!("mmio_addr: {:#x}", mmio_addr);
-
-    let pages_type = AllocateType::AnyPages;
-    let pages_pool = MemoryType::RUNTIME_SERVICES_DATA;
-    let pages_count = PAGE_COUNT;
-    let phys_addr = bs.allocate_pages(pages_type, pages_pool, pages_count)
-        .map_err(inspect("allocate_pages"))
-        .ignore_warning()?;
-    info!("phys_addr: {:#x}", phys_addr);
-
-    let region = find_region(phys_addr)
-        .ignore_warning()?;
-    info!("region: {:#?}", region);
 
-    // SAFETY: looks safe to me
-    unsafe {
-        let probe: u32 = 0xfeaddead;
-        bs.memmove(
-            phys_addr as *mut u8,
-            &probe as *const u32 as *const u8,
-            mem::size_of::<u32>()
-        );
+    match bpb_main(handle, &system_table) {
+        Ok(()) => uefi::Status::SUCCESS,
+        Err(error) => {
+            error.report();
+            error.status()
+        },
     }
-
-    // TBD: hang in the gBS call
-    // install_configuration_table(phys_addr)
-    //     .map_err(inspect("install_configuration_table"))?;
-
-    // TBD: access denied
-    // let table_key = install_fadt1(phys_addr)
-    //     .map_err(inspect("install_fadt1"))
-    //     .ignore_warning()?;
-    // info!("table_key: {:?}", table_key);
-
-    // TBD: access denied
-    // let table_key = install_fadt3(phys_addr)
-    //     .map_err(inspect("install_fadt3"))
-    //     .ignore_warning()?;
-    // info!("table_key: {:?}", table_key);
-
-    let table_key = install_bpbt_table(phys_addr)
-        .map_err(inspect("install_bpbt_table"))
-        .ignore_warning()?;
-    info!("table_key: {:?}", table_key);
-
-    let table_key1 = install_my_ssdt_table(phys_addr)
-        .map_err(inspect("install_my_ssdt_table"))
-        .ignore_warning()?;
-    info!("table_key1: {:?}", table_key1);
-
-    let rt = unsafe {
-        uefi_services::system_table()
-            .as_ref()
-            .runtime_services()
-    };
-
-    let buffer = &mut [0u16; 256];
-    rt.set_variable(
-        CStr16::from_str_with_buf("BpbAddress", buffer).ok().unwrap(),
-        &MY_VENDOR_GUID,
-        VariableAttributes::RUNTIME_ACCESS | VariableAttributes::BOOTSERVICE_ACCESS,
-        &phys_addr.to_le_bytes())
-        .map_err(inspect("set_variable"));
-
-    let system_table_addr = &system_table as *const _ as u64;
-    rt.set_variable(
-        CStr16::from_str_with_buf("SystemTable", buffer).ok().unwrap(),
-        &MY_VENDOR_GUID,
-        VariableAttributes::RUNTIME_ACCESS | VariableAttributes::BOOTSERVICE_ACCESS,
-        &system_table_addr.to_le_bytes())
-        .map_err(inspect("set_variable"));
-
-    info!("bpb_main -- ok");
-    uefi::Status::SUCCESS
 }
@@ -0,0 +1,162 @@
+//! The OEM identity of the tables we install.
+//!
+//! Each table either inherits `oem_id`, `oem_table_id` and
+//! `oem_revision` from the platform's root table or gets an explicit
+//! identity. The build picks the defaults, see `build.rs`; `bpb.cfg`
+//! and `--oem-inherit` change them per table at run time.
+//!
+//! Firmware and OS table overrides match on these fields and take
+//! the higher revision, so a table that replaces one an earlier run
+//! installed gets a revision above the earlier one's.
+
+use alloc::vec::Vec;
+
+use crate::acpi::{root_sdt_header, OemIdentity, SSDT_SIGNATURE};
+use crate::config::Config;
+use crate::error::Result;
+use crate::options::{parse_number, Options};
+use bpb::{MY_TABLE_SIGNATURE, UEFI_TABLE_SIGNATURE};
+
+/// The explicit identity, that of `ssdt_bpb.dsl`, unless the build
+/// sets `BPB_OEM_ID`, `BPB_OEM_TABLE_ID` or `BPB_OEM_REVISION`.
+const OEM_ID: &str = "OEM";
+const OEM_TABLE_ID: &str = "BPBT0";
+const OEM_REVISION: u32 = 4;
+
+/// Tables that inherit unless the build sets `BPB_OEM_INHERIT`.
+const OEM_INHERIT: &str = "bpbt,uefi";
+
+/// The tables whose identity we choose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OemTable {
+    Bpbt,
+    Ssdt,
+    Uefi,
+}
+
+impl OemTable {
+    pub const ALL: [OemTable; 3] = [OemTable::Bpbt, OemTable::Ssdt, OemTable::Uefi];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OemTable::Bpbt => "bpbt",
+            OemTable::Ssdt => "ssdt",
+            OemTable::Uefi => "uefi",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<OemTable> {
+        OemTable::ALL.iter().copied().find(|table| table.name() == name)
+    }
+
+    pub fn signature(self) -> u32 {
+        match self {
+            OemTable::Bpbt => MY_TABLE_SIGNATURE,
+            OemTable::Ssdt => SSDT_SIGNATURE,
+            OemTable::Uefi => UEFI_TABLE_SIGNATURE,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Pads an ASCII id with NULs to a fixed field, as iasl does, or
+/// truncates it.
+pub fn oem_field<const N: usize>(value: &str) -> [u8; N] {
+    let mut field = [0u8; N];
+    for (byte, value) in field.iter_mut().zip(value.bytes()) {
+        *byte = value;
+    }
+    field
+}
+
+/// `oem_id`, `oem_table_id` and `oem_revision` of an explicit table
+/// where `bpb.cfg` leaves them out.
+pub fn default_fields() -> ([u8; 6], u64, u32) {
+    let oem_id = option_env!("BPB_OEM_ID").unwrap_or(OEM_ID);
+    let oem_table_id = option_env!("BPB_OEM_TABLE_ID").unwrap_or(OEM_TABLE_ID);
+    // build.rs refuses a revision that does not parse
+    let oem_revision = option_env!("BPB_OEM_REVISION")
+        .and_then(parse_number)
+        .map_or(OEM_REVISION, |revision| revision as u32);
+    (oem_field(oem_id), u64::from_le_bytes(oem_field(oem_table_id)), oem_revision)
+}
+
+pub fn default_explicit() -> OemIdentity {
+    let (oem_id, oem_table_id, oem_revision) = default_fields();
+    OemIdentity::Explicit { oem_id, oem_table_id, oem_revision }
+}
+
+fn default_identity(table: OemTable) -> OemIdentity {
+    let inherit = option_env!("BPB_OEM_INHERIT").unwrap_or(OEM_INHERIT);
+    match inherit.split(',').any(|name| name.trim() == table.name()) {
+        true => OemIdentity::Inherit,
+        false => default_explicit(),
+    }
+}
+
+/// OEM revisions of the tables an earlier run installed, by
+/// signature.
+#[derive(Clone, Debug, Default)]
+pub struct Revisions {
+    entries: Vec<(u32, u32)>,
+}
+
+impl Revisions {
+    pub fn record(&mut self, signature: u32, oem_revision: u32) {
+        self.entries.push((signature, oem_revision));
+    }
+
+    /// The highest revision recorded for the signature.
+    pub fn get(&self, signature: u32) -> Option<u32> {
+        self.entries.iter()
+            .filter(|(entry, _)| *entry == signature)
+            .map(|&(_, oem_revision)| oem_revision)
+            .max()
+    }
+}
+
+/// The identity of every table we install this run.
+#[derive(Debug)]
+pub struct OemPolicy {
+    identities: [OemIdentity; 3],
+    previous: Revisions,
+}
+
+impl OemPolicy {
+    /// The build's defaults, then `bpb.cfg`, then `--oem-inherit`.
+    pub fn new(config: &Config, options: &Options, previous: Revisions) -> OemPolicy {
+        let mut identities = [OemIdentity::Inherit; 3];
+        for &table in &OemTable::ALL {
+            let identity = config.oem[table.index()].unwrap_or_else(|| default_identity(table));
+            identities[table.index()] = match &options.oem_inherit {
+                Some(inherit) if inherit.contains(&table) => OemIdentity::Inherit,
+                Some(_) if matches!(identity, OemIdentity::Inherit) => default_explicit(),
+                _ => identity,
+            };
+        }
+        OemPolicy { identities, previous }
+    }
+
+    /// The identity to install `table` with: an inherited one read
+    /// from the root table, with the revision raised above that of
+    /// the table an earlier run installed.
+    pub fn identity(&self, table: OemTable) -> Result<OemIdentity> {
+        let (oem_id, oem_table_id, oem_revision) = match self.identities[table.index()] {
+            OemIdentity::Inherit => {
+                let sdt = root_sdt_header()?;
+                (sdt.oem_id, sdt.oem_table_id, sdt.oem_revision)
+            },
+            OemIdentity::Explicit { oem_id, oem_table_id, oem_revision } => {
+                (oem_id, oem_table_id, oem_revision)
+            },
+        };
+        let oem_revision = match self.previous.get(table.signature()) {
+            Some(previous) => oem_revision.max(previous.saturating_add(1)),
+            None => oem_revision,
+        };
+        Ok(OemIdentity::Explicit { oem_id, oem_table_id, oem_revision })
+    }
+}
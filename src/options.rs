@@ -0,0 +1,408 @@
+//! Command line options from `EFI_LOADED_IMAGE_PROTOCOL.LoadOptions`,
+//! e.g. from the UEFI shell:
+//!
+//!     bpb-test.efi --transport acpi,var --pages 4 --below-4g --dump-mmap
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use uefi::table::boot::MemoryType;
+
+use crate::fadt::FadtPatch;
+use crate::oem::OemTable;
+use crate::placement::{BpbSize, Placement, PlacementPolicy};
+use crate::MY_VENDOR_GUID;
+
+pub const USAGE: &str = "\
+usage: bpb-test.efi [options]
+
+A run first takes back everything an earlier run published in this
+boot, since it reuses the same variables, configuration table entry,
+address and MMIO range. If this run then fails, it rolls back its own
+steps only: the BPB of the earlier run stays unpublished.
+
+options:
+  --transport LIST     comma separated subset of acpi,uefi,var,cfg,hook
+                       (default: acpi,var,cfg and hook in runtime driver builds)
+  --uefi-data WHAT     what the ACPI UEFI table carries: pointer (where
+                       the BPB is) or payload (a copy of it)
+                       (default: pointer)
+  --vendor-guid GUID   vendor GUID of the BPB variables
+  --pages N            BPB size in pages (default: 1)
+  --bytes N            BPB size in bytes, rounded up to pages
+  --below-4g           place the BPB below 4 GiB
+  --below ADDRESS      place the BPB below ADDRESS
+  --address ADDRESS    place the BPB at ADDRESS
+  --fallback           with --address, take any address if that is taken
+  --align N            alignment of the BPB in bytes (default: 4096)
+  --memory-type TYPE   runtime, reclaim, nvs, reserved or persistent
+                       (default: runtime)
+  --bpb-device WHERE   where the BPB0 device goes: ssdt (our own SSDT),
+                       patch (an existing BPB0 in the platform's
+                       tables) or inject (added to the platform table
+                       that defines --bpb-scope) (default: ssdt)
+  --bpb-scope PATH     ASL scope of the BPB0 device (default: \\_SB)
+  --oem-inherit LIST   comma separated subset of bpbt,ssdt,uefi, or none,
+                       that copy the platform's OEM identity; the others
+                       get the explicit one (default: bpbt,uefi unless
+                       the build or bpb.cfg says otherwise)
+  --fadt platform      replace the platform's FADT with a patched copy
+  --fadt-patch FIELD=VALUE
+                       with --fadt platform, set a FADT field; also
+                       FIELD|=BITS and FIELD&=MASK, e.g. flags|=0x100000;
+                       x_dsdt=ADDRESS installs the DSDT at ADDRESS
+                       (may be repeated, implies --fadt platform)
+  --mmio               reserve an MMIO page through the GCD
+  --runtime-list       publish the RuntimeListHead list
+  --dump-mmap          log the memory map
+  --dump-acpi          log the ACPI table protocols and tables
+  --selftest           find the BPB again through every transport
+                       and check it
+  --chainload PATH     start PATH from our volume when done, e.g.
+                       \\EFI\\Microsoft\\Boot\\bootmgfw.efi
+  --chainload-options TEXT
+                       load options for the chainloaded image
+  --install            add a Boot#### entry (Driver#### in runtime
+                       driver builds) that runs us with the other options
+  --uninstall          remove our Boot#### or Driver#### entries
+  -h, --help           print this message
+";
+
+/// The channels the BPB is published on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// `BPBT` and the `BPB0` device in an SSDT.
+    Acpi,
+    /// An ACPI `UEFI` table identified by our vendor GUID.
+    Uefi,
+    /// The `BpbAddress` and `SystemTable` variables.
+    Variable,
+    /// An entry in the system configuration table.
+    ConfigTable,
+    /// The `MyInternalBpb` variable served by hooked runtime services.
+    Hook,
+}
+
+impl Transport {
+    fn parse(name: &str) -> Result<Transport, OptionsError> {
+        match name {
+            "acpi" => Ok(Transport::Acpi),
+            "uefi" => Ok(Transport::Uefi),
+            "var" => Ok(Transport::Variable),
+            "cfg" => Ok(Transport::ConfigTable),
+            "hook" => Ok(Transport::Hook),
+            _ => Err(OptionsError::BadValue(String::from("--transport"), String::from(name))),
+        }
+    }
+}
+
+/// Where the `BPB0` device goes, see `src/device.rs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DevicePlacement {
+    /// Our own SSDT.
+    Ssdt,
+    /// An existing `BPB0` in the platform's tables, changed in place.
+    Patch,
+    /// Added to the platform's table that defines the scope.
+    Inject,
+}
+
+/// The data of the `UEFI` table, see `bpb::UefiTableData`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UefiData {
+    /// A `MyPayload`.
+    Pointer,
+    /// A copy of the payload in the BPB.
+    Payload,
+}
+
+#[derive(Debug)]
+pub enum OptionsError {
+    UnknownOption(String),
+    MissingValue(String),
+    BadValue(String, String),
+    /// `hook` needs a runtime driver build.
+    Unsupported(Transport),
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub transports: Vec<Transport>,
+    pub vendor_guid: uefi::Guid,
+    pub policy: PlacementPolicy,
+    pub device: DevicePlacement,
+    /// An ASL path such as `\_SB.PCI0`.
+    pub device_scope: String,
+    pub uefi_data: UefiData,
+    /// Overrides which tables inherit, see `src/oem.rs`.
+    pub oem_inherit: Option<Vec<OemTable>>,
+    /// `--fadt platform`: replace the platform's FADT with a copy
+    /// that has these patches applied.
+    pub fadt: Option<Vec<FadtPatch>>,
+    pub mmio: bool,
+    pub runtime_list: bool,
+    pub dump_mmap: bool,
+    pub dump_acpi: bool,
+    pub selftest: bool,
+    pub chainload: Option<String>,
+    pub chainload_options: Option<String>,
+    pub install: bool,
+    pub uninstall: bool,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        let mut transports = vec![Transport::Acpi, Transport::Variable, Transport::ConfigTable];
+        if cfg!(feature = "runtime-driver") {
+            transports.push(Transport::Hook);
+        }
+        Options {
+            transports,
+            vendor_guid: MY_VENDOR_GUID,
+            policy: PlacementPolicy::default(),
+            device: DevicePlacement::Ssdt,
+            device_scope: String::from("\\_SB"),
+            uefi_data: UefiData::Pointer,
+            oem_inherit: None,
+            fadt: None,
+            mmio: false,
+            runtime_list: false,
+            dump_mmap: false,
+            dump_acpi: false,
+            selftest: false,
+            chainload: None,
+            chainload_options: None,
+            install: false,
+            uninstall: false,
+            help: false,
+        }
+    }
+}
+
+/// Decimal or `0x` prefixed hexadecimal.
+pub fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+pub fn parse_guid(text: &str) -> Option<uefi::Guid> {
+    let fields = text.split('-').collect::<Vec<_>>();
+    let lengths = [8, 4, 4, 4, 12];
+    if fields.len() != lengths.len()
+        || fields.iter().zip(&lengths).any(|(field, &length)| field.len() != length)
+    {
+        return None;
+    }
+    let hex = |field: &str| u64::from_str_radix(field, 16).ok();
+    let node = hex(fields[4])?.to_be_bytes();
+    let mut node_bytes = [0u8; 6];
+    node_bytes.copy_from_slice(&node[2..]);
+    Some(uefi::Guid::from_values(
+        hex(fields[0])? as u32,
+        hex(fields[1])? as u16,
+        hex(fields[2])? as u16,
+        hex(fields[3])? as u16,
+        node_bytes
+    ))
+}
+
+/// An absolute ASL path of name segments, e.g. `\_SB.PCI0`.
+pub fn is_asl_path(path: &str) -> bool {
+    let segments = match path.strip_prefix('\\') {
+        Some("") => return true,
+        Some(segments) => segments,
+        None => return false,
+    };
+    segments.split('.').all(|segment| {
+        let bytes = segment.as_bytes();
+        !bytes.is_empty() && bytes.len() <= 4
+            && (bytes[0].is_ascii_uppercase() || bytes[0] == b'_')
+            && bytes.iter().all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_')
+    })
+}
+
+pub fn parse_memory_type(name: &str) -> Option<MemoryType> {
+    match name {
+        "runtime" => Some(MemoryType::RUNTIME_SERVICES_DATA),
+        "reclaim" => Some(MemoryType::ACPI_RECLAIM),
+        "nvs" => Some(MemoryType::ACPI_NON_VOLATILE),
+        "reserved" => Some(MemoryType::RESERVED),
+        "persistent" => Some(MemoryType::PERSISTENT_MEMORY),
+        _ => None,
+    }
+}
+
+/// Splits a UCS-2 command line on whitespace outside double quotes.
+/// The first word is dropped if it is the image name, as the shell
+/// passes it. `RawLoadedImage::load_options` has already dropped
+/// LoadOptions that are not text.
+pub fn split_command_line(line: &[u16]) -> Vec<String> {
+    let line = String::from_utf16_lossy(line);
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            },
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    if words.first().map_or(false, |first| !first.starts_with('-')) {
+        words.remove(0);
+    }
+    words
+}
+
+/// The inverse of `split_command_line`, quoting words with spaces.
+pub fn join_command_line(words: &[String]) -> String {
+    words.iter()
+        .map(|word| match word.contains(char::is_whitespace) || word.is_empty() {
+            true => format!("\"{}\"", word),
+            false => word.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Options {
+    /// Parses `args`; a later option overrides an earlier one.
+    pub fn parse(args: &[String]) -> Result<Options, OptionsError> {
+        let mut options = Options::default();
+        let mut fixed_address = None;
+        let mut fallback = false;
+        let mut fadt_patches = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| OptionsError::MissingValue(arg.clone()))
+            };
+            let bad_value = |value: &str| OptionsError::BadValue(arg.clone(), String::from(value));
+            match arg.as_str() {
+                "--transport" => {
+                    options.transports = value()?
+                        .split(',')
+                        .map(Transport::parse)
+                        .collect::<Result<Vec<_>, _>>()?;
+                },
+                "--vendor-guid" => {
+                    let value = value()?;
+                    options.vendor_guid = parse_guid(value).ok_or_else(|| bad_value(value))?;
+                },
+                "--pages" => {
+                    let value = value()?;
+                    let pages = parse_number(value)
+                        .and_then(|pages| usize::try_from(pages).ok())
+                        .ok_or_else(|| bad_value(value))?;
+                    options.policy.size = BpbSize::Pages(pages);
+                },
+                "--bytes" => {
+                    let value = value()?;
+                    let bytes = parse_number(value).ok_or_else(|| bad_value(value))?;
+                    options.policy.size = BpbSize::Bytes(bytes);
+                },
+                "--below-4g" => options.policy.placement = Placement::Below(0x1_0000_0000),
+                "--below" => {
+                    let value = value()?;
+                    let limit = parse_number(value).ok_or_else(|| bad_value(value))?;
+                    options.policy.placement = Placement::Below(limit);
+                },
+                "--address" => {
+                    let value = value()?;
+                    fixed_address = Some(parse_number(value).ok_or_else(|| bad_value(value))?);
+                },
+                "--fallback" => fallback = true,
+                "--align" => {
+                    let value = value()?;
+                    options.policy.alignment = parse_number(value).ok_or_else(|| bad_value(value))?;
+                },
+                "--memory-type" => {
+                    let value = value()?;
+                    options.policy.memory_type = parse_memory_type(value)
+                        .ok_or_else(|| bad_value(value))?;
+                },
+                "--bpb-device" => {
+                    options.device = match value()? {
+                        "ssdt" => DevicePlacement::Ssdt,
+                        "patch" => DevicePlacement::Patch,
+                        "inject" => DevicePlacement::Inject,
+                        value => return Err(bad_value(value)),
+                    };
+                },
+                "--bpb-scope" => {
+                    let value = value()?;
+                    if !is_asl_path(value) {
+                        return Err(bad_value(value));
+                    }
+                    options.device_scope = String::from(value);
+                },
+                "--uefi-data" => {
+                    options.uefi_data = match value()? {
+                        "pointer" => UefiData::Pointer,
+                        "payload" => UefiData::Payload,
+                        value => return Err(bad_value(value)),
+                    };
+                },
+                "--oem-inherit" => {
+                    let value = value()?;
+                    options.oem_inherit = Some(match value {
+                        "none" => Vec::new(),
+                        _ => value.split(',')
+                            .map(|name| OemTable::parse(name).ok_or_else(|| bad_value(name)))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    });
+                },
+                "--fadt" => {
+                    options.fadt = match value()? {
+                        "platform" => Some(Vec::new()),
+                        value => return Err(bad_value(value)),
+                    };
+                },
+                "--fadt-patch" => {
+                    let value = value()?;
+                    fadt_patches.push(FadtPatch::parse(value).ok_or_else(|| bad_value(value))?);
+                },
+                "--mmio" => options.mmio = true,
+                "--runtime-list" => options.runtime_list = true,
+                "--dump-mmap" => options.dump_mmap = true,
+                "--dump-acpi" => options.dump_acpi = true,
+                "--selftest" => options.selftest = true,
+                "--chainload" => options.chainload = Some(String::from(value()?)),
+                "--chainload-options" => options.chainload_options = Some(String::from(value()?)),
+                "--install" => options.install = true,
+                "--uninstall" => options.uninstall = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(OptionsError::UnknownOption(arg.clone())),
+            }
+        }
+
+        if let Some(address) = fixed_address {
+            options.policy.placement = Placement::Fixed { address, fallback };
+        }
+        if !fadt_patches.is_empty() {
+            options.fadt = Some(fadt_patches);
+        }
+        if options.install && options.uninstall {
+            return Err(OptionsError::BadValue(String::from("--install"), String::from("--uninstall")));
+        }
+        if !cfg!(feature = "runtime-driver") && options.has(Transport::Hook) {
+            return Err(OptionsError::Unsupported(Transport::Hook));
+        }
+        Ok(options)
+    }
+
+    pub fn has(&self, transport: Transport) -> bool {
+        self.transports.contains(&transport)
+    }
+}
@@ -0,0 +1,184 @@
+//! Where the BPB pages go and what memory type they get.
+//!
+//! The memory type decides what the OS is allowed to do with the
+//! region: RUNTIME_SERVICES_DATA and ACPI_NON_VOLATILE survive into
+//! the OS memory map as reserved, ACPI_RECLAIM survives until the OS
+//! has read the ACPI tables, RESERVED is never touched by either side
+//! and PERSISTENT_MEMORY is kept across reboots where the firmware
+//! lets us allocate it at all.
+
+use uefi::prelude::*;
+use uefi::table::boot::{AllocateType, MemoryType};
+
+use crate::error::{Context, Error, Result};
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Where to look for free pages.
+#[derive(Clone, Copy, Debug)]
+pub enum Placement {
+    /// Wherever the firmware likes.
+    Any,
+    /// Entirely below `limit`, e.g. 4 GiB for 32-bit consumers.
+    Below(u64),
+    /// At `address`; with `fallback` try `Any` if that is taken.
+    Fixed { address: u64, fallback: bool },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BpbSize {
+    Pages(usize),
+    /// Rounded up to whole pages.
+    Bytes(u64),
+}
+
+impl BpbSize {
+    pub fn pages(self) -> Result<usize> {
+        match self {
+            BpbSize::Pages(pages) => Ok(pages),
+            BpbSize::Bytes(bytes) => bytes.checked_add(PAGE_SIZE - 1)
+                .map(|bytes| (bytes / PAGE_SIZE) as usize)
+                .ok_or(Error::BadPlacement("size does not fit the address space")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PlacementPolicy {
+    pub placement: Placement,
+    pub size: BpbSize,
+    /// A power of two, at least `PAGE_SIZE`.
+    pub alignment: u64,
+    pub memory_type: MemoryType,
+}
+
+impl Default for PlacementPolicy {
+    fn default() -> Self {
+        PlacementPolicy {
+            placement: Placement::Any,
+            size: BpbSize::Pages(1),
+            alignment: PAGE_SIZE,
+            memory_type: MemoryType::RUNTIME_SERVICES_DATA,
+        }
+    }
+}
+
+/// The pages the BPB ended up in.
+#[derive(Clone, Copy, Debug)]
+pub struct BpbRegion {
+    pub address: u64,
+    pub length: u64,
+    pub memory_type: MemoryType,
+}
+
+/// The memory types a BPB may be allocated as.
+pub fn is_supported_memory_type(memory_type: MemoryType) -> bool {
+    [
+        MemoryType::RUNTIME_SERVICES_DATA,
+        MemoryType::ACPI_RECLAIM,
+        MemoryType::ACPI_NON_VOLATILE,
+        MemoryType::RESERVED,
+        MemoryType::PERSISTENT_MEMORY,
+    ].contains(&memory_type)
+}
+
+impl PlacementPolicy {
+    fn validate(&self) -> Result {
+        let pages = self.size.pages()?;
+        if pages == 0 {
+            return Err(Error::BadPlacement("size is zero"));
+        }
+        if (pages as u64).checked_mul(PAGE_SIZE).is_none() {
+            return Err(Error::BadPlacement("size does not fit the address space"));
+        }
+        if self.alignment < PAGE_SIZE || !self.alignment.is_power_of_two() {
+            return Err(Error::BadPlacement("alignment is not a power of two of at least a page"));
+        }
+        if !is_supported_memory_type(self.memory_type) {
+            return Err(Error::BadPlacement("unsupported memory type"));
+        }
+        match self.placement {
+            Placement::Below(limit) if limit < PAGE_SIZE => {
+                Err(Error::BadPlacement("limit is below the first page"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn allocation_failed(&self, pages: usize) -> impl FnOnce(uefi::Error) -> Error {
+        let memory_type = self.memory_type;
+        move |error| Error::AllocationFailed { memory_type, pages, status: error.status() }
+    }
+
+    /// Allocates `pages` plus enough slack to align the start, then
+    /// gives the slack back.
+    fn allocate_aligned(&self, allocate_type: AllocateType) -> Result<u64> {
+        let bs = unsafe {
+            uefi_services::system_table()
+                .as_ref()
+                .boot_services()
+        };
+
+        let pages = self.size.pages()?;
+        let slack = (self.alignment / PAGE_SIZE) as usize - 1;
+        let start = bs.allocate_pages(allocate_type, self.memory_type, pages + slack)
+            .ignore_warning()
+            .map_err(self.allocation_failed(pages + slack))?;
+
+        let address = (start + self.alignment - 1) & !(self.alignment - 1);
+        let head = ((address - start) / PAGE_SIZE) as usize;
+        let tail = slack - head;
+        if head > 0 {
+            bs.free_pages(start, head)
+                .or_warn("free_pages (head)");
+        }
+        if tail > 0 {
+            bs.free_pages(address + pages as u64 * PAGE_SIZE, tail)
+                .or_warn("free_pages (tail)");
+        }
+        Ok(address)
+    }
+
+    pub fn allocate(&self) -> Result<BpbRegion> {
+        let bs = unsafe {
+            uefi_services::system_table()
+                .as_ref()
+                .boot_services()
+        };
+
+        self.validate()?;
+
+        let pages = self.size.pages()?;
+        let address = match self.placement {
+            Placement::Any => self.allocate_aligned(AllocateType::AnyPages)?,
+            Placement::Below(limit) => {
+                // MaxAddress is the highest address the last byte may have
+                self.allocate_aligned(AllocateType::MaxAddress(limit as usize - 1))?
+            },
+            Placement::Fixed { address, fallback } => {
+                let result = if address & (self.alignment - 1) == 0 {
+                    bs.allocate_pages(AllocateType::Address(address as usize), self.memory_type, pages)
+                        .ignore_warning()
+                        .map_err(self.allocation_failed(pages))
+                } else {
+                    Err(Error::BadPlacement("fixed address is not aligned"))
+                };
+                match result {
+                    Ok(address) => address,
+                    Err(error) if fallback => {
+                        warn!("{:#x}: {}, falling back to any address", address, error);
+                        self.allocate_aligned(AllocateType::AnyPages)?
+                    },
+                    Err(error) => return Err(error),
+                }
+            },
+        };
+
+        let region = BpbRegion {
+            address,
+            length: pages as u64 * PAGE_SIZE,
+            memory_type: self.memory_type,
+        };
+        Ok(region)
+    }
+}
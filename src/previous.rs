@@ -0,0 +1,283 @@
+//! What an earlier run of bpb-test left behind in this boot, so a
+//! second run replaces it instead of stacking a second `BPBT` and
+//! another SSDT with a duplicate `\_SB.BPB0`.
+//!
+//! Each run records its table keys, BPB pages and `RuntimeListHead`
+//! list in the volatile `BpbState` variable. Without one, e.g. after a build that did not
+//! keep state, our tables are looked up through
+//! `EFI_ACPI_SDT_PROTOCOL` and the BPB pages through the `BPBT`.
+//! The `UEFI` table is never recorded; it is found by its identifier.
+//!
+//! The OEM revisions of the tables are handed on, so the new ones
+//! can supersede them, see `src/oem.rs`.
+
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::mem;
+use uefi::prelude::*;
+use uefi::table::runtime::VariableAttributes;
+
+use crate::acpi::{self, platform_tables, DescriptionHeader, InstalledTable, SSDT_SIGNATURE};
+use crate::acpi_sdt::Sdt;
+use crate::gcd;
+use crate::oem::Revisions;
+use crate::placement::PAGE_SIZE;
+use crate::runtime_list::{self, RUNTIME_LIST_VARIABLE};
+use crate::variable::{delete_variable, read_variable, write_variable};
+use crate::error::{Context, Result};
+use crate::find_configuration_table;
+use crate::{BPB_ADDRESS_VARIABLE, MY_CONFIGURATION_TABLE_GUID, MY_VENDOR_GUID, SYSTEM_TABLE_VARIABLE};
+use bpb::{aml, parse_uefi_table, MyPayload, BPB_DEVICE_NAME, MY_TABLE_SIGNATURE, UEFI_TABLE_SIGNATURE};
+
+pub const STATE_VARIABLE: &str = "BpbState";
+
+/// Stands for "no table" in `BpbState`.
+const NO_KEY: u64 = u64::MAX;
+
+/// `BpbState`: little-endian `u64`s in this order, then the vendor
+/// GUID of the variables (all zero for none), then the base and
+/// length of the MMIO range and the head of the runtime list (zero
+/// for none).
+#[derive(Clone, Debug, Default)]
+pub struct RunState {
+    pub bpb_address: u64,
+    pub bpb_pages: u64,
+    pub bpbt_key: Option<usize>,
+    pub ssdt_key: Option<usize>,
+    pub vendor_guid: Option<uefi::Guid>,
+    /// The GCD range of `--mmio`.
+    pub mmio: Option<(u64, u64)>,
+    /// The `RuntimeListHead` list of `--runtime-list`.
+    pub runtime_list: Option<u64>,
+}
+
+impl RunState {
+    fn to_bytes(&self) -> Vec<u8> {
+        let key = |key: Option<usize>| key.map_or(NO_KEY, |key| key as u64);
+        let mut bytes = Vec::new();
+        for value in &[
+            self.bpb_address,
+            self.bpb_pages,
+            key(self.bpbt_key),
+            key(self.ssdt_key),
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match &self.vendor_guid {
+            // SAFETY: Guid is a plain 16 byte structure
+            Some(guid) => bytes.extend_from_slice(unsafe {
+                core::slice::from_raw_parts(guid as *const _ as *const u8, mem::size_of::<uefi::Guid>())
+            }),
+            None => bytes.extend_from_slice(&[0; 16]),
+        }
+        let (base, length) = self.mmio.unwrap_or_default();
+        for value in &[base, length, self.runtime_list.unwrap_or_default()] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<RunState> {
+        let value = |index: usize| {
+            bytes.get(index * 8..index * 8 + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let key = |index: usize| value(index).map(|key| match key {
+            NO_KEY => None,
+            key => Some(key as usize),
+        });
+        let vendor_guid = bytes.get(32..32 + mem::size_of::<uefi::Guid>())
+            .filter(|bytes| bytes.iter().any(|&byte| byte != 0))
+            // SAFETY: any 16 bytes are a Guid
+            .map(|bytes| unsafe { (bytes.as_ptr() as *const uefi::Guid).read_unaligned() });
+        let mmio = match (value(6), value(7)) {
+            (Some(base), Some(length)) if length > 0 => Some((base, length)),
+            _ => None,
+        };
+        let runtime_list = value(8).filter(|&head| head != 0);
+        Some(RunState {
+            bpb_address: value(0)?,
+            bpb_pages: value(1)?,
+            bpbt_key: key(2)?,
+            ssdt_key: key(3)?,
+            vendor_guid,
+            mmio,
+            runtime_list,
+        })
+    }
+}
+
+/// Records what this run installed for the next one.
+pub fn save(state: &RunState) -> Result {
+    write_variable(
+        STATE_VARIABLE,
+        &MY_VENDOR_GUID,
+        VariableAttributes::BOOTSERVICE_ACCESS,
+        &state.to_bytes())
+}
+
+fn load() -> Result<Option<RunState>> {
+    let bytes = read_variable(STATE_VARIABLE, &MY_VENDOR_GUID)?;
+    let state = bytes.as_deref().and_then(RunState::from_bytes);
+    if bytes.is_some() && state.is_none() {
+        warn!("ignoring a malformed {}", STATE_VARIABLE);
+    }
+    Ok(state)
+}
+
+/// A `BPBT`, a `UEFI` table with our identifier, or an SSDT that is
+/// nothing but a scope with `BPB0`. A platform SSDT that `BPB0` was
+/// injected into has more to it.
+fn is_our_table(table: &[u8]) -> bool {
+    let signature = u32::from_le_bytes([table[0], table[1], table[2], table[3]]);
+    if signature == MY_TABLE_SIGNATURE {
+        return true;
+    }
+    if signature == UEFI_TABLE_SIGNATURE {
+        return parse_uefi_table(table).is_some();
+    }
+    let body = &table[mem::size_of::<DescriptionHeader>()..];
+    signature == SSDT_SIGNATURE
+        && aml::is_single_scope(body)
+        && aml::find_device(body, BPB_DEVICE_NAME).is_some()
+}
+
+/// The BPB pages named by a `BPBT` in the XSDT.
+fn bpbt_region() -> Option<(u64, u64)> {
+    let tables = platform_tables().ok()?;
+    let bpbt = tables.find(MY_TABLE_SIGNATURE)?;
+    let body = bpbt.body();
+    if body.len() < mem::size_of::<MyPayload>() {
+        return None;
+    }
+    // SAFETY: the body is at least a MyPayload long
+    let payload = unsafe { (body.as_ptr() as *const MyPayload).read_unaligned() };
+    let pages = (payload.length_bytes + PAGE_SIZE - 1) / PAGE_SIZE;
+    Some((payload.physical_address, pages))
+}
+
+fn stored_keys(state: Option<&RunState>) -> Vec<usize> {
+    state.map_or(Vec::new(), |state| {
+        [state.bpbt_key, state.ssdt_key]
+            .iter()
+            .flatten()
+            .copied()
+            .collect()
+    })
+}
+
+/// OEM revisions of the tables `uninstall_tables` will take away.
+fn installed_revisions(state: Option<&RunState>) -> Revisions {
+    let mut revisions = Revisions::default();
+    let tables = match Sdt::locate().and_then(|sdt| sdt.tables()) {
+        Ok(tables) => tables,
+        Err(error) => {
+            warn!("cannot read previous table revisions: {}", error);
+            return revisions;
+        },
+    };
+    let stored = stored_keys(state);
+    for table in tables.iter().filter(|table| stored.contains(&table.key) || is_our_table(table.bytes())) {
+        revisions.record(table.header.signature, table.header.oem_revision);
+    }
+    revisions
+}
+
+fn uninstall_tables(state: Option<&RunState>) {
+    for key in stored_keys(state) {
+        if acpi::uninstall_table(key).is_ok() {
+            info!("uninstalled previous table {}", key);
+        }
+    }
+
+    // Tables from a run that left no state, or that survived their key
+    let leftovers = match acpi::find_installed(is_our_table) {
+        Ok(leftovers) => leftovers,
+        Err(error) => {
+            warn!("cannot look for previous tables: {}", error);
+            Vec::new()
+        },
+    };
+    for InstalledTable { signature, key, .. } in leftovers {
+        if acpi::uninstall_table(key).is_ok() {
+            info!("uninstalled previous {} table {}", acpi::signature_str(&signature), key);
+        }
+    }
+}
+
+fn remove_configuration_table() {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let address = match find_configuration_table(&MY_CONFIGURATION_TABLE_GUID) {
+        Some(entry) => entry.address as *mut u8,
+        None => return,
+    };
+    let removed = unsafe {
+        bs.install_configuration_table(&MY_CONFIGURATION_TABLE_GUID, core::ptr::null())
+            .or_warn("InstallConfigurationTable (remove)")
+    };
+    if removed.is_some() {
+        bs.free_pool(address)
+            .or_warn("free_pool (previous payload)");
+        info!("removed previous configuration table");
+    }
+}
+
+/// Takes back everything an earlier run published: its ACPI tables,
+/// configuration table entry, MMIO range, runtime list, variables and
+/// BPB pages.
+/// Returns the OEM revisions of its tables.
+pub fn clean_up(vendor_guid: &uefi::Guid) -> Result<Revisions> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    let state = load()?;
+    info!("previous run: {:?}", state);
+
+    // Must be read before the BPBT goes away
+    let region = match &state {
+        Some(state) => Some((state.bpb_address, state.bpb_pages)),
+        None => bpbt_region(),
+    };
+
+    let revisions = installed_revisions(state.as_ref());
+    uninstall_tables(state.as_ref());
+    remove_configuration_table();
+
+    if let Some((base, length)) = state.as_ref().and_then(|state| state.mmio) {
+        match gcd::release_mmio(base, length) {
+            Ok(()) => info!("released previous mmio at {:#x}", base),
+            Err(error) => warn!("releasing previous mmio at {:#x}: {}", base, error),
+        }
+    }
+
+    let previous_guid = state.as_ref()
+        .and_then(|state| state.vendor_guid)
+        .unwrap_or(*vendor_guid);
+    for name in &[BPB_ADDRESS_VARIABLE, SYSTEM_TABLE_VARIABLE, RUNTIME_LIST_VARIABLE] {
+        delete_variable(name, &previous_guid)?;
+    }
+
+    // Only once nothing points at it
+    if let Some(head) = state.as_ref().and_then(|state| state.runtime_list) {
+        runtime_list::free_runtime_list(head);
+        info!("freed previous runtime list at {:#x}", head);
+    }
+
+    if let Some((address, pages)) = region {
+        if pages > 0 {
+            if bs.free_pages(address, pages as usize).or_warn("free_pages (previous bpb)").is_some() {
+                info!("freed previous bpb at {:#x}", address);
+            }
+        }
+    }
+
+    delete_variable(STATE_VARIABLE, &MY_VENDOR_GUID)?;
+    Ok(revisions)
+}
@@ -0,0 +1,158 @@
+//! Support for running as an `EFI_RUNTIME_DRIVER`.
+//!
+//! Built with the `runtime-driver` feature the image gets the
+//! `efi_runtime_driver` subsystem, so the loader places its code and
+//! data in RUNTIME_SERVICES_CODE and RUNTIME_SERVICES_DATA and keeps
+//! them after ExitBootServices. Anything we still dereference after
+//! SetVirtualAddressMap has to be registered here so that it gets
+//! converted when the OS switches to virtual addressing.
+
+use core::ffi::c_void;
+use core::{mem, ptr};
+use uefi::prelude::*;
+use uefi::table::boot::{EventType, Tpl};
+use uefi::table::Header;
+use uefi::Event;
+
+use crate::error::{check, Context, Error, Result};
+
+/// Upper bound of runtime pointers we own.
+const MAX_RUNTIME_POINTERS: usize = 32;
+
+/// `EFI_RUNTIME_SERVICES` as laid out in memory, for patching and
+/// calling the services after boot services are gone.
+#[repr(C)]
+pub struct RawRuntimeServices {
+    pub header: Header,
+    pub get_time: usize,
+    pub set_time: usize,
+    pub get_wakeup_time: usize,
+    pub set_wakeup_time: usize,
+    pub set_virtual_address_map: usize,
+    pub convert_pointer: extern "efiapi" fn(
+        debug_disposition: usize,
+        address: *mut *mut c_void
+    ) -> Status,
+    pub get_variable: GetVariableFn,
+    pub get_next_variable_name: usize,
+    pub set_variable: SetVariableFn,
+    pub get_next_high_monotonic_count: usize,
+    pub reset_system: usize,
+    pub update_capsule: usize,
+    pub query_capsule_capabilities: usize,
+    pub query_variable_info: usize,
+}
+
+pub type GetVariableFn = extern "efiapi" fn(
+    variable_name: *const u16,
+    vendor_guid: *const uefi::Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut u8
+) -> Status;
+
+pub type SetVariableFn = extern "efiapi" fn(
+    variable_name: *const u16,
+    vendor_guid: *const uefi::Guid,
+    attributes: u32,
+    data_size: usize,
+    data: *const u8
+) -> Status;
+
+/// `EFI_BOOT_SERVICES` up to CloseEvent, which uefi-rs does not
+/// expose.
+#[repr(C)]
+struct RawBootServices {
+    header: Header,
+    /// RaiseTPL through SignalEvent.
+    before_close_event: [usize; 11],
+    close_event: extern "efiapi" fn(event: *mut c_void) -> Status,
+}
+
+/// Pointers converted on virtual address change; the image data is
+/// runtime memory so these survive ExitBootServices.
+static mut RUNTIME_POINTERS: [*mut *mut c_void; MAX_RUNTIME_POINTERS] =
+    [ptr::null_mut(); MAX_RUNTIME_POINTERS];
+static mut RUNTIME_POINTER_COUNT: usize = 0;
+
+/// The firmware's runtime services table, converted last.
+static mut RUNTIME_SERVICES: *mut RawRuntimeServices = ptr::null_mut();
+
+/// The event `init` created, until `fini` closes it.
+static mut VIRTUAL_ADDRESS_CHANGE_EVENT: Option<Event> = None;
+
+/// Registers a pointer for conversion on virtual address change.
+///
+/// # Safety
+///
+/// `pointer` must live in runtime memory, e.g. in a `static`, and
+/// must hold a physical address of runtime memory or null.
+pub unsafe fn register_pointer(pointer: *mut *mut c_void) -> Result {
+    if RUNTIME_POINTER_COUNT == MAX_RUNTIME_POINTERS {
+        return Err(Error::TooManyRuntimePointers);
+    }
+    RUNTIME_POINTERS[RUNTIME_POINTER_COUNT] = pointer;
+    RUNTIME_POINTER_COUNT += 1;
+    Ok(())
+}
+
+/// The firmware's runtime services table; virtual after
+/// SetVirtualAddressMap.
+///
+/// # Safety
+///
+/// `init` must have been called, and the caller must not race with
+/// the virtual address change notification.
+pub unsafe fn runtime_services() -> &'static mut RawRuntimeServices {
+    &mut *RUNTIME_SERVICES
+}
+
+/// Runs in physical mode from inside SetVirtualAddressMap, after
+/// boot services are gone: no logging and no allocations here.
+fn on_virtual_address_change(_event: Event) {
+    unsafe {
+        let rt = &*RUNTIME_SERVICES;
+        let convert_pointer = rt.convert_pointer;
+        for &pointer in &RUNTIME_POINTERS[..RUNTIME_POINTER_COUNT] {
+            if !(*pointer).is_null() {
+                convert_pointer(0, pointer);
+            }
+        }
+        convert_pointer(0, &mut RUNTIME_SERVICES as *mut _ as *mut *mut c_void);
+    }
+}
+
+/// Remembers the runtime services table and registers the virtual
+/// address change event. Call once, from the driver entry point,
+/// and `fini` before the image is unloaded.
+pub fn init(system_table: &SystemTable<Boot>) -> Result {
+    let bs = system_table.boot_services();
+    unsafe {
+        RUNTIME_SERVICES = system_table.runtime_services()
+            as *const _ as *mut RawRuntimeServices;
+        let event = bs.create_event(
+            EventType::SIGNAL_VIRTUAL_ADDRESS_CHANGE,
+            Tpl::NOTIFY,
+            Some(on_virtual_address_change)
+        )
+        .context("create_event (virtual address change)")?;
+        VIRTUAL_ADDRESS_CHANGE_EVENT = Some(event);
+    }
+    Ok(())
+}
+
+/// Closes the virtual address change event, which must not outlive
+/// an image that fails and gets unloaded.
+pub fn fini() -> Result {
+    unsafe {
+        let event = match VIRTUAL_ADDRESS_CHANGE_EVENT.take() {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        let bs = uefi_services::system_table()
+            .as_ref()
+            .boot_services() as *const _ as *const RawBootServices;
+        // SAFETY: Event is a transparent EFI_EVENT
+        check(((*bs).close_event)(mem::transmute::<Event, *mut c_void>(event)), "CloseEvent")
+    }
+}
@@ -0,0 +1,183 @@
+//! The `RuntimeListHead` list of images loaded in runtime memory,
+//! read by `linux/lsefi` and `linux/lsefi-mod`.
+
+use core::ffi::c_void;
+use core::{mem, ptr};
+use uefi::prelude::*;
+use uefi::proto::Protocol;
+use uefi::table::boot::MemoryType;
+use uefi::table::runtime::VariableAttributes;
+use uefi::unsafe_guid;
+
+use bpb::{RuntimeModule, RUNTIME_MAX_DP, RUNTIME_MAX_MOD};
+
+use crate::image::RawLoadedImage;
+use crate::error::{Context, Error, Result};
+use crate::variable::write_variable;
+
+pub const RUNTIME_LIST_VARIABLE: &str = "RuntimeListHead";
+
+/// `EFI_LOADED_IMAGE_DEVICE_PATH_PROTOCOL`: the interface is the
+/// first node of the full device path of the image.
+#[repr(C)]
+#[unsafe_guid("bc62157e-3e33-4fec-9920-2d3b36d750df")]
+#[derive(Protocol)]
+struct LoadedImageDevicePath {
+    node_type: u8,
+    sub_type: u8,
+    length: [u8; 2],
+}
+
+/// `EFI_DEVICE_PATH_TO_TEXT_PROTOCOL`.
+#[repr(C)]
+#[unsafe_guid("8b843e20-8132-4852-90cc-551a4e4a7f1c")]
+#[derive(Protocol)]
+struct DevicePathToText {
+    convert_device_node_to_text: usize,
+    convert_device_path_to_text: extern "efiapi" fn(
+        device_path: *const c_void,
+        display_only: bool,
+        allow_shortcuts: bool
+    ) -> *mut u16,
+}
+
+fn is_runtime_image(image: &RawLoadedImage) -> bool {
+    image.image_code_type == MemoryType::RUNTIME_SERVICES_CODE
+        || image.image_data_type == MemoryType::RUNTIME_SERVICES_DATA
+}
+
+/// Renders `device_path` into `out`, truncating and leaving it
+/// null-terminated; leaves `out` empty without the protocol.
+fn device_path_text(to_text: Option<&DevicePathToText>, device_path: *const c_void, out: &mut [u16]) {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let to_text = match to_text {
+        Some(to_text) if !device_path.is_null() => to_text,
+        _ => return,
+    };
+    let text = (to_text.convert_device_path_to_text)(device_path, false, false);
+    if text.is_null() {
+        return;
+    }
+    unsafe {
+        let mut index = 0;
+        while index + 1 < out.len() && *text.add(index) != 0 {
+            out[index] = *text.add(index);
+            index += 1;
+        }
+    }
+    bs.free_pool(text as *mut u8)
+        .or_warn("free_pool (device path text)");
+}
+
+/// Records every image loaded in runtime memory in a list of
+/// `RuntimeModule`s in RUNTIME_SERVICES_DATA and returns the
+/// physical address of the head, 0 if there are none.
+pub fn build_runtime_list() -> Result<u64> {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    let to_text = bs.locate_protocol::<DevicePathToText>()
+        .or_warn("locate_protocol (DevicePathToText)")
+        .map(|to_text| unsafe { &*to_text.get() });
+
+    let handles = bs.find_handles::<RawLoadedImage>()
+        .context("find_handles (LoadedImage)")?;
+
+    let mut head = 0u64;
+    let mut tail: *mut RuntimeModule = ptr::null_mut();
+    for handle in handles {
+        let image = match bs.handle_protocol::<RawLoadedImage>(handle).ignore_warning() {
+            Ok(image) => unsafe { &*image.get() },
+            Err(_) => continue,
+        };
+        if !is_runtime_image(image) {
+            continue;
+        }
+
+        let module = match bs.allocate_pool(MemoryType::RUNTIME_SERVICES_DATA, mem::size_of::<RuntimeModule>())
+            .ignore_warning()
+        {
+            Ok(module) => module as *mut RuntimeModule,
+            Err(error) => {
+                free_runtime_list(head);
+                return Err(Error::PoolAllocationFailed {
+                    memory_type: MemoryType::RUNTIME_SERVICES_DATA,
+                    size: mem::size_of::<RuntimeModule>(),
+                    status: error.status(),
+                });
+            },
+        };
+
+        let mut device_path = [0u16; RUNTIME_MAX_DP];
+        if let Ok(path) = bs.handle_protocol::<LoadedImageDevicePath>(handle).ignore_warning() {
+            let path = path.get() as *const c_void;
+            device_path_text(to_text, path, &mut device_path);
+        }
+        let mut name = [0u16; RUNTIME_MAX_MOD];
+        device_path_text(to_text, image.file_path, &mut name);
+
+        // SAFETY: module is a fresh pool of the right size
+        unsafe {
+            module.write_unaligned(RuntimeModule {
+                next: 0,
+                base: image.image_base,
+                size: image.image_size,
+                seg_text: image.image_code_type.0,
+                seg_data: image.image_data_type.0,
+                device_path,
+                module: name,
+            });
+            if tail.is_null() {
+                head = module as u64;
+            } else {
+                ptr::addr_of_mut!((*tail).next).write_unaligned(module as u64);
+            }
+        }
+        tail = module;
+        info!("runtime image at {:#x} ({} bytes)", image.image_base, image.image_size);
+    }
+
+    Ok(head)
+}
+
+/// Frees the records of a list from `build_runtime_list`.
+pub fn free_runtime_list(head: u64) {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+    let mut module = head as *mut RuntimeModule;
+    while !module.is_null() {
+        // SAFETY: the records were allocated and linked by us
+        let next = unsafe { ptr::addr_of!((*module).next).read_unaligned() };
+        bs.free_pool(module as *mut u8)
+            .or_warn("free_pool (runtime module)");
+        module = next as *mut RuntimeModule;
+    }
+}
+
+/// Builds the list and publishes its head in the `RuntimeListHead`
+/// variable under `vendor_guid`.
+pub fn publish_runtime_list(vendor_guid: &uefi::Guid) -> Result<u64> {
+    let head = build_runtime_list()?;
+
+    let result = write_variable(
+        RUNTIME_LIST_VARIABLE,
+        vendor_guid,
+        VariableAttributes::RUNTIME_ACCESS | VariableAttributes::BOOTSERVICE_ACCESS,
+        &head.to_le_bytes());
+    if let Err(error) = result {
+        free_runtime_list(head);
+        return Err(error);
+    }
+
+    Ok(head)
+}
@@ -0,0 +1,235 @@
+//! `--selftest`: after publishing, look the BPB up again the way
+//! each OS reader does and check that every path leads to the page
+//! that was published, with its payload intact.
+//!
+//! `linux/bpbctl` does the same from the other side of
+//! ExitBootServices.
+
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::{fmt, mem, slice};
+use uefi::table::boot::MemoryType;
+
+use crate::acpi::{as_bytes, platform_tables, validate_table, SSDT_SIGNATURE};
+use crate::error::{Error, Result};
+use crate::options::{Options, Transport};
+use crate::placement::BpbRegion;
+use crate::variable::read_variable;
+#[cfg(feature = "runtime-driver")]
+use crate::variable::variable_size;
+#[cfg(feature = "runtime-driver")]
+use crate::variable_hook::MY_VARIABLE_NAME;
+use crate::{find_configuration_table, find_region, BPB_ADDRESS_VARIABLE, MY_CONFIGURATION_TABLE_GUID};
+use bpb::aml::{self, Resources};
+use bpb::payload::{Payload, PayloadError};
+use bpb::{
+    parse_uefi_table, MyPayload, UefiTableData, BPB_DEVICE_NAME, MY_PAYLOAD_MAGIC, MY_TABLE_SIGNATURE,
+    UEFI_TABLE_SIGNATURE,
+};
+
+/// Why one consumer path does not lead to the published BPB.
+#[derive(Debug)]
+enum Failure {
+    Error(Error),
+    NotFound(&'static str),
+    WrongAddress { found: u64, expected: u64 },
+    WrongLength { found: u64, expected: u64 },
+    /// `MyPayload.magic` of a `BPBT` or the configuration table.
+    BadMagic(u64),
+    Payload(PayloadError),
+    WrongMemoryType { found: MemoryType, expected: MemoryType },
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        Failure::Error(error)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Error(error) => write!(f, "{}", error),
+            Failure::NotFound(what) => write!(f, "{}", what),
+            Failure::WrongAddress { found, expected } => {
+                write!(f, "points at {:#x}, published at {:#x}", found, expected)
+            },
+            Failure::WrongLength { found, expected } => {
+                write!(f, "says {:#x} bytes, published {:#x}", found, expected)
+            },
+            Failure::BadMagic(magic) => write!(f, "bad MyPayload magic {:#x}", magic),
+            Failure::Payload(error) => write!(f, "payload does not parse: {:?}", error),
+            Failure::WrongMemoryType { found, expected } => {
+                write!(f, "page is {:?}, allocated as {:?}", found, expected)
+            },
+        }
+    }
+}
+
+type Check<T = ()> = core::result::Result<T, Failure>;
+
+/// The address a path leads to and the length, if it tells one.
+type Location = (u64, Option<u64>);
+
+fn read_my_payload(bytes: &[u8]) -> Check<Location> {
+    if bytes.len() < mem::size_of::<MyPayload>() {
+        return Err(Failure::NotFound("MyPayload is truncated"));
+    }
+    // SAFETY: bytes holds a whole MyPayload
+    let payload = unsafe { (bytes.as_ptr() as *const MyPayload).read_unaligned() };
+    if payload.magic != MY_PAYLOAD_MAGIC {
+        return Err(Failure::BadMagic(payload.magic));
+    }
+    Ok((payload.physical_address, Some(payload.length_bytes)))
+}
+
+fn locate_variable(vendor_guid: &uefi::Guid) -> Check<Location> {
+    let value = read_variable(BPB_ADDRESS_VARIABLE, vendor_guid)?
+        .ok_or(Failure::NotFound("no BpbAddress variable"))?;
+    let address = value.get(..8)
+        .ok_or(Failure::NotFound("BpbAddress is shorter than 8 bytes"))?;
+    Ok((u64::from_le_bytes(address.try_into().unwrap()), None))
+}
+
+/// The `BPBT` listed in the XSDT, checksum and all.
+fn locate_bpbt() -> Check<Location> {
+    let tables = platform_tables()?;
+    let bpbt = tables.find(MY_TABLE_SIGNATURE)
+        .ok_or(Failure::NotFound("no BPBT with a valid checksum in the XSDT"))?;
+    validate_table(bpbt.bytes)
+        .map_err(|error| Error::table(MY_TABLE_SIGNATURE, error))?;
+    read_my_payload(bpbt.body())
+}
+
+/// The `UEFI` table with our identifier listed in the XSDT. A copy
+/// of the payload leads to the page through its `bpb.address` blob.
+fn locate_uefi() -> Check<Location> {
+    let tables = platform_tables()?;
+    let (bytes, data) = tables.iter()
+        .filter_map(|table| table.ok())
+        .filter(|table| table.header.signature == UEFI_TABLE_SIGNATURE)
+        .find_map(|table| Some((table.bytes, parse_uefi_table(table.bytes)?)))
+        .ok_or(Failure::NotFound("no UEFI table with our identifier in the XSDT"))?;
+    validate_table(bytes)
+        .map_err(|error| Error::table(UEFI_TABLE_SIGNATURE, error))?;
+    match data {
+        UefiTableData::Pointer(payload) => read_my_payload(as_bytes(&payload)),
+        UefiTableData::Payload(data) => {
+            let payload = Payload::parse(data)
+                .map_err(Failure::Payload)?;
+            let address = payload.blob("bpb.address")
+                .and_then(|address| address.try_into().ok())
+                .ok_or(Failure::NotFound("payload copy has no bpb.address"))?;
+            Ok((u64::from_le_bytes(address), None))
+        },
+    }
+}
+
+/// The first memory descriptor of `BPB0._CRS` in an SSDT listed
+/// in the XSDT, or in the DSDT it was injected into.
+fn locate_ssdt() -> Check<Location> {
+    let tables = platform_tables()?;
+    tables.iter()
+        .filter_map(|table| table.ok())
+        .filter(|table| table.header.signature == SSDT_SIGNATURE)
+        .chain(tables.dsdt().ok())
+        .find_map(|table| {
+            let device = aml::find_device(table.body(), BPB_DEVICE_NAME)?;
+            let crs = aml::find_name_buffer(device, "_CRS")?;
+            Resources::new(crs).find_map(|resource| resource.memory_range())
+        })
+        .map(|(address, length)| (address, Some(length)))
+        .ok_or(Failure::NotFound("no SSDT or DSDT defines BPB0 with a memory resource"))
+}
+
+fn locate_config_table() -> Check<Location> {
+    let entry = find_configuration_table(&MY_CONFIGURATION_TABLE_GUID)
+        .ok_or(Failure::NotFound("no configuration table entry with our GUID"))?;
+    // SAFETY: the entry points at the MyPayload we installed
+    let bytes = unsafe {
+        slice::from_raw_parts(entry.address as *const u8, mem::size_of::<MyPayload>())
+    };
+    read_my_payload(bytes)
+}
+
+/// The hook serves a copy rather than the page, and reading it
+/// clears it, so only its size is asked for.
+#[cfg(feature = "runtime-driver")]
+fn check_hook(vendor_guid: &uefi::Guid, payload_size: usize) -> Check {
+    let size = variable_size(MY_VARIABLE_NAME, vendor_guid)?
+        .ok_or(Failure::NotFound("MyInternalBpb is not served"))?;
+    if size != payload_size {
+        return Err(Failure::WrongLength { found: size as u64, expected: payload_size as u64 });
+    }
+    Ok(())
+}
+
+/// Follows `location` to the page and checks what is there.
+fn verify(location: Check<Location>, region: &BpbRegion) -> Check {
+    let (address, length) = location?;
+    if address != region.address {
+        return Err(Failure::WrongAddress { found: address, expected: region.address });
+    }
+    if let Some(length) = length {
+        if length != region.length {
+            return Err(Failure::WrongLength { found: length, expected: region.length });
+        }
+    }
+
+    // SAFETY: the address is that of the pages we allocated
+    let page = unsafe { slice::from_raw_parts(address as *const u8, region.length as usize) };
+    Payload::parse(page)
+        .map_err(Failure::Payload)?;
+
+    let descriptor = find_region(address)?;
+    if descriptor.ty != region.memory_type {
+        return Err(Failure::WrongMemoryType { found: descriptor.ty, expected: region.memory_type });
+    }
+    Ok(())
+}
+
+/// Checks every transport in `options` and logs a pass/fail line
+/// for each.
+pub fn run(options: &Options, region: &BpbRegion, payload_size: usize) -> Result {
+    let mut results: Vec<(&'static str, Check)> = Vec::new();
+    for transport in &options.transports {
+        match transport {
+            Transport::Acpi => {
+                results.push(("bpbt", verify(locate_bpbt(), region)));
+                results.push(("ssdt", verify(locate_ssdt(), region)));
+            },
+            Transport::Uefi => {
+                results.push(("uefi", verify(locate_uefi(), region)));
+            },
+            Transport::Variable => {
+                results.push(("var", verify(locate_variable(&options.vendor_guid), region)));
+            },
+            Transport::ConfigTable => {
+                results.push(("cfg", verify(locate_config_table(), region)));
+            },
+            #[cfg(feature = "runtime-driver")]
+            Transport::Hook => {
+                results.push(("hook", check_hook(&options.vendor_guid, payload_size)));
+            },
+            #[cfg(not(feature = "runtime-driver"))]
+            Transport::Hook => (),
+        }
+    }
+
+    info!("selftest:");
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => info!("  {:<8} pass", name),
+            Err(failure) => {
+                error!("  {:<8} FAIL: {}", name, failure);
+                failed += 1;
+            },
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::SelfTestFailed { failed, checked: results.len() });
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+//! Publishing the BPB all or nothing.
+//!
+//! Every step that changes firmware state runs through a
+//! `Transaction` and then records how to take the change back. If a
+//! step fails, the earlier ones are undone in reverse order, so no
+//! table is left pointing at pages nobody describes.
+
+use alloc::vec::Vec;
+use uefi::prelude::*;
+
+use crate::acpi;
+use crate::error::{Context, Result};
+use crate::variable::delete_variable;
+
+/// The steps of a publish, in the order they run. `planned_steps`
+/// lists the ones `publish` will take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Runtime,
+    Mmio,
+    Bpb,
+    Hook,
+    ConfigTable,
+    Dsdt,
+    Fadt,
+    Bpbt,
+    /// A `BPB0` an earlier run left in a platform table.
+    SsdtPatch,
+    /// Our own SSDT.
+    SsdtInstall,
+    /// `BPB0` patched or injected into a platform table.
+    SsdtPlace,
+    Uefi,
+    RuntimeList,
+    Variables,
+    State,
+}
+
+impl Step {
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Runtime => "runtime",
+            Step::Mmio => "mmio",
+            Step::Bpb => "bpb",
+            Step::Hook => "hook",
+            Step::ConfigTable => "config table",
+            Step::Dsdt => "dsdt",
+            Step::Fadt => "fadt",
+            Step::Bpbt => "bpbt",
+            Step::SsdtPatch => "ssdt patch",
+            Step::SsdtInstall => "ssdt install",
+            Step::SsdtPlace => "ssdt place",
+            Step::Uefi => "uefi",
+            Step::RuntimeList => "runtime list",
+            Step::Variables => "variables",
+            Step::State => "state",
+        }
+    }
+}
+
+/// How to take back one change.
+#[derive(Debug)]
+pub enum Undo {
+    FreePool(*mut u8),
+    FreePages { address: u64, pages: usize },
+    /// An ACPI table by its key.
+    UninstallTable(usize),
+    /// Puts back a table that was replaced, e.g. the platform's FADT.
+    ReinstallTable(Vec<u8>),
+    /// Writes back the bytes of a table changed in place.
+    RestoreTable { address: u64, original: Vec<u8> },
+    DeleteVariable { name: &'static str, vendor: uefi::Guid },
+    RemoveConfigurationTable(uefi::Guid),
+    /// A GCD range `gcd::reserve_mmio` took.
+    ReleaseMmio { base: u64, length: u64 },
+    /// The pool records of `RuntimeListHead`, by head address.
+    FreeRuntimeList(u64),
+    /// Closes the virtual address change event of `runtime::init`.
+    #[cfg(feature = "runtime-driver")]
+    CloseRuntimeEvent,
+    /// Puts the firmware's GetVariable and SetVariable back.
+    #[cfg(feature = "runtime-driver")]
+    UnhookVariables,
+}
+
+impl Undo {
+    fn run(&self) -> Result {
+        let bs = unsafe {
+            uefi_services::system_table()
+                .as_ref()
+                .boot_services()
+        };
+        match *self {
+            Undo::FreePool(address) => bs.free_pool(address).context("free_pool"),
+            Undo::FreePages { address, pages } => bs.free_pages(address, pages).context("free_pages"),
+            Undo::UninstallTable(key) => acpi::uninstall_table(key),
+            Undo::ReinstallTable(ref table) => acpi::install_table(table).map(|_| ()),
+            Undo::RestoreTable { address, ref original } => {
+                // SAFETY: the table at address was patched in place
+                // and has not changed size
+                unsafe {
+                    core::ptr::copy_nonoverlapping(original.as_ptr(), address as *mut u8, original.len());
+                }
+                Ok(())
+            },
+            Undo::DeleteVariable { name, ref vendor } => delete_variable(name, vendor),
+            Undo::RemoveConfigurationTable(ref guid) => unsafe {
+                bs.install_configuration_table(guid, core::ptr::null())
+                    .context("InstallConfigurationTable (remove)")
+            },
+            Undo::ReleaseMmio { base, length } => crate::gcd::release_mmio(base, length),
+            Undo::FreeRuntimeList(head) => {
+                crate::runtime_list::free_runtime_list(head);
+                Ok(())
+            },
+            #[cfg(feature = "runtime-driver")]
+            Undo::CloseRuntimeEvent => crate::runtime::fini(),
+            #[cfg(feature = "runtime-driver")]
+            Undo::UnhookVariables => crate::variable_hook::uninstall(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    NotAttempted,
+    Done,
+    Failed,
+    RolledBack,
+}
+
+struct Record {
+    step: Step,
+    outcome: Outcome,
+    undo: Vec<Undo>,
+}
+
+pub struct Transaction {
+    steps: Vec<Record>,
+    /// The step `undo` adds to.
+    current: Option<usize>,
+}
+
+impl Transaction {
+    /// A transaction over the steps in `planned`.
+    pub fn new(planned: &[Step]) -> Transaction {
+        let steps = planned.iter()
+            .map(|&step| Record { step, outcome: Outcome::NotAttempted, undo: Vec::new() })
+            .collect();
+        Transaction { steps, current: None }
+    }
+
+    /// Runs the planned `step`.
+    pub fn run<T>(&mut self, step: Step, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let index = match self.steps.iter().position(|record| record.step == step) {
+            Some(index) => index,
+            None => {
+                self.steps.push(Record { step, outcome: Outcome::NotAttempted, undo: Vec::new() });
+                self.steps.len() - 1
+            },
+        };
+        let result = f();
+        self.steps[index].outcome = match &result {
+            Ok(_) => Outcome::Done,
+            Err(error) => {
+                error!("{}: {}", step.name(), error);
+                Outcome::Failed
+            },
+        };
+        self.current = Some(index);
+        result
+    }
+
+    /// Records how to take back the step that ran last.
+    pub fn undo(&mut self, undo: Undo) {
+        match self.current {
+            Some(index) => self.steps[index].undo.push(undo),
+            None => warn!("undo {:?} outside of a step", undo),
+        }
+    }
+
+    fn summary(&self) {
+        for record in &self.steps {
+            let outcome = match record.outcome {
+                Outcome::NotAttempted => "not attempted",
+                Outcome::Done => "published",
+                Outcome::Failed => "failed",
+                Outcome::RolledBack => "rolled back",
+            };
+            info!("  {:<16} {}", record.step.name(), outcome);
+        }
+    }
+
+    /// Keeps everything.
+    pub fn commit(self) {
+        info!("published:");
+        self.summary();
+    }
+
+    /// Undoes every step that ran, latest first.
+    pub fn rollback(mut self) {
+        for record in self.steps.iter_mut().rev() {
+            for undo in record.undo.drain(..).rev() {
+                if let Err(error) = undo.run() {
+                    error!("{}: undo {:?}: {}", record.step.name(), undo, error);
+                }
+            }
+            if record.outcome == Outcome::Done {
+                record.outcome = Outcome::RolledBack;
+            }
+        }
+        error!("nothing was published:");
+        self.summary();
+    }
+}
@@ -0,0 +1,89 @@
+//! Reading and writing UEFI variables by `&str` name.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::prelude::*;
+use uefi::table::runtime::VariableAttributes;
+use uefi::CStr16;
+
+use crate::error::{Error, Result};
+
+/// Longest variable name we use, plus the terminator.
+const NAME_CAPACITY: usize = 32;
+
+fn with_name<T>(name: &str, f: impl FnOnce(&CStr16) -> Result<T>) -> Result<T> {
+    let buffer = &mut [0u16; NAME_CAPACITY];
+    let ucs2 = CStr16::from_str_with_buf(name, buffer)
+        .map_err(|_| Error::BadVariableName(String::from(name)))?;
+    f(ucs2)
+}
+
+/// Size of the variable without reading it, `None` if it does not
+/// exist.
+pub fn variable_size(name: &str, vendor: &uefi::Guid) -> Result<Option<usize>> {
+    let rt = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .runtime_services()
+    };
+    with_name(name, |ucs2| {
+        match rt.get_variable_size(ucs2, vendor).ignore_warning() {
+            Ok(size) => Ok(Some(size)),
+            Err(error) if error.status() == Status::NOT_FOUND => Ok(None),
+            Err(error) => Err(Error::VariableReadFailed {
+                name: String::from(name),
+                status: error.status(),
+            }),
+        }
+    })
+}
+
+/// `None` if the variable does not exist.
+pub fn read_variable(name: &str, vendor: &uefi::Guid) -> Result<Option<Vec<u8>>> {
+    let rt = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .runtime_services()
+    };
+    let size = match variable_size(name, vendor)? {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    let failed = |status| Error::VariableReadFailed { name: String::from(name), status };
+    with_name(name, |ucs2| {
+        let mut data = vec![0u8; size];
+        rt.get_variable(ucs2, vendor, &mut data)
+            .ignore_warning()
+            .map_err(|error| failed(error.status()))?;
+        Ok(Some(data))
+    })
+}
+
+pub fn write_variable(
+    name: &str,
+    vendor: &uefi::Guid,
+    attributes: VariableAttributes,
+    data: &[u8]
+) -> Result {
+    let rt = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .runtime_services()
+    };
+    with_name(name, |ucs2| {
+        rt.set_variable(ucs2, vendor, attributes, data)
+            .ignore_warning()
+            .map_err(|error| Error::VariableWriteFailed {
+                name: String::from(name),
+                status: error.status(),
+            })
+    })
+}
+
+/// Deletes the variable; one that does not exist is not an error.
+pub fn delete_variable(name: &str, vendor: &uefi::Guid) -> Result {
+    match write_variable(name, vendor, VariableAttributes::empty(), &[]) {
+        Err(Error::VariableWriteFailed { status, .. }) if status == Status::NOT_FOUND => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,190 @@
+//! Serving the BPB through a fake variable, the way
+//! `BpbPkg/BpbDxe/Bpb.c` does.
+//!
+//! GetVariable and SetVariable in the runtime services table are
+//! replaced with our own, which answer for `MyInternalBpb` under
+//! the vendor GUID and chain to the firmware for anything else. The
+//! BPB can be read once: a successful GetVariable or any SetVariable
+//! of our variable clears it for good.
+
+use core::ffi::c_void;
+use core::{ptr, slice};
+use uefi::prelude::*;
+use uefi::table::boot::Tpl;
+
+use crate::runtime::{self, GetVariableFn, SetVariableFn};
+use crate::error::{Error, Result};
+use crate::MY_VENDOR_GUID;
+
+pub const MY_VARIABLE_NAME: &str = "MyInternalBpb";
+
+/// Same size as `BpbBuffer` in Bpb.c.
+const BPB_BUFFER_SIZE: usize = 4096;
+
+const HEADER_SIZE_OFFSET: usize = 12;
+const HEADER_CRC32_OFFSET: usize = 16;
+
+static mut BPB_BUFFER: [u8; BPB_BUFFER_SIZE] = [0; BPB_BUFFER_SIZE];
+static mut BPB_LENGTH: usize = 0;
+static mut BPB_CONSUMED: bool = false;
+static mut VENDOR_GUID: uefi::Guid = MY_VENDOR_GUID;
+
+static mut ORIGINAL_GET_VARIABLE: Option<GetVariableFn> = None;
+static mut ORIGINAL_SET_VARIABLE: Option<SetVariableFn> = None;
+
+/// Compares a null-terminated UCS-2 string with an ASCII one.
+unsafe fn name_matches(name: *const u16, expected: &str) -> bool {
+    let mut index = 0;
+    for byte in expected.bytes() {
+        if *name.add(index) != byte as u16 {
+            return false;
+        }
+        index += 1;
+    }
+    *name.add(index) == 0
+}
+
+unsafe fn is_my_variable(name: *const u16, guid: *const uefi::Guid) -> bool {
+    !name.is_null() && !guid.is_null()
+        && *guid == VENDOR_GUID
+        && name_matches(name, MY_VARIABLE_NAME)
+}
+
+unsafe fn clear_bpb() {
+    ptr::write_bytes(BPB_BUFFER.as_mut_ptr(), 0, BPB_BUFFER_SIZE);
+    BPB_LENGTH = 0;
+    BPB_CONSUMED = true;
+}
+
+extern "efiapi" fn get_variable(
+    variable_name: *const u16,
+    vendor_guid: *const uefi::Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut u8
+) -> Status {
+    unsafe {
+        if is_my_variable(variable_name, vendor_guid) {
+            if BPB_CONSUMED {
+                return Status::NOT_FOUND;
+            }
+            if data_size.is_null() {
+                return Status::INVALID_PARAMETER;
+            }
+            if *data_size < BPB_LENGTH {
+                *data_size = BPB_LENGTH;
+                return Status::BUFFER_TOO_SMALL;
+            }
+            if data.is_null() {
+                return Status::INVALID_PARAMETER;
+            }
+            // Do not let the copy run ahead of the size check
+            core::arch::x86_64::_mm_lfence();
+            if !attributes.is_null() {
+                *attributes = 0;
+            }
+            ptr::copy_nonoverlapping(BPB_BUFFER.as_ptr(), data, BPB_LENGTH);
+            *data_size = BPB_LENGTH;
+            clear_bpb();
+            return Status::SUCCESS;
+        }
+        match ORIGINAL_GET_VARIABLE {
+            Some(original) => original(variable_name, vendor_guid, attributes, data_size, data),
+            None => Status::UNSUPPORTED,
+        }
+    }
+}
+
+extern "efiapi" fn set_variable(
+    variable_name: *const u16,
+    vendor_guid: *const uefi::Guid,
+    attributes: u32,
+    data_size: usize,
+    data: *const u8
+) -> Status {
+    unsafe {
+        if is_my_variable(variable_name, vendor_guid) {
+            clear_bpb();
+            return Status::SUCCESS;
+        }
+        match ORIGINAL_SET_VARIABLE {
+            Some(original) => original(variable_name, vendor_guid, attributes, data_size, data),
+            None => Status::UNSUPPORTED,
+        }
+    }
+}
+
+/// Recomputes `Hdr.CRC32` over `Hdr.HeaderSize` bytes.
+unsafe fn update_header_crc32(table: *mut u8) {
+    let header_size = (table.add(HEADER_SIZE_OFFSET) as *const u32).read_unaligned();
+    let crc32 = table.add(HEADER_CRC32_OFFSET) as *mut u32;
+    crc32.write_unaligned(0);
+    let bytes = slice::from_raw_parts(table, header_size as usize);
+    crc32.write_unaligned(bpb::payload::crc32(bytes));
+}
+
+/// Copies `bpb` into the runtime buffer and hooks the variable
+/// services to serve it under `vendor_guid`. Requires
+/// `runtime::init` and a runtime driver image.
+pub fn install(bpb: &[u8], vendor_guid: &uefi::Guid) -> Result {
+    if bpb.len() > BPB_BUFFER_SIZE {
+        return Err(Error::BpbTooLarge { size: bpb.len(), capacity: BPB_BUFFER_SIZE });
+    }
+
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    unsafe {
+        BPB_BUFFER[..bpb.len()].copy_from_slice(bpb);
+        BPB_LENGTH = bpb.len();
+        BPB_CONSUMED = false;
+        VENDOR_GUID = *vendor_guid;
+
+        let rt = runtime::runtime_services();
+        {
+            let _tpl = bs.raise_tpl(Tpl::HIGH_LEVEL);
+            ORIGINAL_GET_VARIABLE = Some(rt.get_variable);
+            ORIGINAL_SET_VARIABLE = Some(rt.set_variable);
+            rt.get_variable = get_variable;
+            rt.set_variable = set_variable;
+            update_header_crc32(rt as *mut _ as *mut u8);
+        }
+
+        // Option<extern fn> is a nullable pointer
+        runtime::register_pointer(&mut ORIGINAL_GET_VARIABLE as *mut _ as *mut *mut c_void)?;
+        runtime::register_pointer(&mut ORIGINAL_SET_VARIABLE as *mut _ as *mut *mut c_void)?;
+    }
+
+    Ok(())
+}
+
+/// Puts the firmware's GetVariable and SetVariable back and drops
+/// the BPB.
+pub fn uninstall() -> Result {
+    let bs = unsafe {
+        uefi_services::system_table()
+            .as_ref()
+            .boot_services()
+    };
+
+    unsafe {
+        let (get_variable, set_variable) = match (ORIGINAL_GET_VARIABLE, ORIGINAL_SET_VARIABLE) {
+            (Some(get_variable), Some(set_variable)) => (get_variable, set_variable),
+            // Never hooked, nothing to put back
+            _ => return Ok(()),
+        };
+        let rt = runtime::runtime_services();
+        {
+            let _tpl = bs.raise_tpl(Tpl::HIGH_LEVEL);
+            rt.get_variable = get_variable;
+            rt.set_variable = set_variable;
+            update_header_crc32(rt as *mut _ as *mut u8);
+        }
+        clear_bpb();
+    }
+
+    Ok(())
+}
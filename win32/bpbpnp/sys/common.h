@@ -8,7 +8,7 @@ typedef struct tagDEVICE
 {
     WDFDEVICE FxDevice;                                  // Handle to the WDF device.
     PHYSICAL_ADDRESS MemoryStart;                        // Resources obtained by Pnp
-    ULONG MemoryLength;                                  // Resources obtained by Pnp
+    ULONGLONG MemoryLength;                              // Resources obtained by Pnp
     WDFIOTARGET IoTarget;                                // IO controller target
     WDFREQUEST IoRequest;                                // Request object
     WDFMEMORY InputMemory;                               // Input memory for request. Valid while request in progress.
@@ -25,7 +25,7 @@ OnPrepareHardware(
     ULONG Index;
     PCM_PARTIAL_RESOURCE_DESCRIPTOR Descriptor;
     PHYSICAL_ADDRESS Start = { 0 };
-    ULONG Length = 0;
+    ULONGLONG Length = 0;
 
     UNREFERENCED_PARAMETER(FxResourcesRaw);
 
@@ -39,21 +39,24 @@ OnPrepareHardware(
         switch (Descriptor->Type)
         {
         case CmResourceTypeMemory:
+        case CmResourceTypeMemoryLarge:
 
-            Start = Descriptor->u.Memory.Start;
-            Length = Descriptor->u.Memory.Length;
+            // QWordMemory above 4 GiB or longer than 4 GiB is reported
+            // as CmResourceTypeMemoryLarge with a scaled length
+
+            Length = RtlCmDecodeMemIoResource(Descriptor, (PULONGLONG)&Start.QuadPart);
 
             if (!fResourceFound)
             {
-                Device->MemoryStart = Descriptor->u.Memory.Start;
-                Device->MemoryLength = Descriptor->u.Memory.Length;
+                Device->MemoryStart = Start;
+                Device->MemoryLength = Length;
 
                 fResourceFound = TRUE;
 
                 Trace(
                     TRACE_LEVEL_INFORMATION,
                     TRACE_FLAG_WDFLOADING,
-                    "Memory resource found at 0x%llx (%d bytes)",
+                    "Memory resource found at 0x%llx (%llu bytes)",
                     Device->MemoryStart.QuadPart,
                     Device->MemoryLength
                     );
@@ -103,6 +106,19 @@ OnPrepareHardware(
         goto Exit;
     }
 
+    // Cross-check against the configuration table transport
+
+    if (NT_SUCCESS(LocateMyConfigurationTable(&Start, &Length)) &&
+        Start.QuadPart != Device->MemoryStart.QuadPart)
+    {
+        Trace(
+            TRACE_LEVEL_WARNING,
+            TRACE_FLAG_WDFLOADING,
+            "Configuration table points at 0x%llx",
+            Start.QuadPart
+            );
+    }
+
 Exit:
     FuncExit(TRACE_FLAG_WDFLOADING);
 
@@ -10,10 +10,50 @@
 
 #include "payload.tmh"
 
+// {F08AE394-4E98-46E6-B0B3-1BB940AC663D}
+DEFINE_GUID(MY_VENDOR_GUID,
+    0xf08ae394, 0x4e98, 0x46e6, 0xb0, 0xb3, 0x1b, 0xb9, 0x40, 0xac, 0x66, 0x3d);
+
+// {3B8C2A7E-5D41-4F0A-9C6E-2F1D8B7A4E53}
+DEFINE_GUID(MY_CONFIGURATION_TABLE_GUID,
+    0x3b8c2a7e, 0x5d41, 0x4f0a, 0x9c, 0x6e, 0x2f, 0x1d, 0x8b, 0x7a, 0x4e, 0x53);
+
+#define EFI_SYSTEM_TABLE_SIGNATURE 0x5453595320494249ULL
+
+// Only the parts of EFI_SYSTEM_TABLE and friends we read
+
+#pragma pack(push, 1)
+typedef struct {
+    ULONGLONG Signature;
+    ULONG Revision;
+    ULONG HeaderSize;
+    ULONG Crc32;
+    ULONG Reserved;
+    UCHAR Reserved1[80];
+    ULONGLONG NumberOfTableEntries;
+    ULONGLONG ConfigurationTable;
+} MY_EFI_SYSTEM_TABLE;
+
+typedef struct {
+    GUID VendorGuid;
+    ULONGLONG VendorTable;
+} MY_EFI_CONFIGURATION_TABLE;
+
+typedef struct {
+    ULONGLONG Magic;
+    ULONGLONG PhysicalAddress;
+    ULONGLONG LengthBytes;
+} MY_PAYLOAD;
+#pragma pack(pop)
+
+C_ASSERT(FIELD_OFFSET(MY_EFI_SYSTEM_TABLE, NumberOfTableEntries) == 104);
+
+#define MY_PAYLOAD_MAGIC 0xFEEDDEADULL
+
 BOOLEAN
 CheckMyPage (
     PHYSICAL_ADDRESS PhysicalAddress,
-    ULONG Length
+    ULONGLONG Length
     )
 {
     PVOID SystemAddress;
@@ -26,12 +66,14 @@ CheckMyPage (
     Trace(
         TRACE_LEVEL_VERBOSE,
         TRACE_FLAG_WDFLOADING,
-        "PhysicalAddress is 0x%I64x, Length is %d",
+        "PhysicalAddress is 0x%I64x, Length is %I64u",
         PhysicalAddress.QuadPart,
         Length
         );
 
-    if (Length != 4096) {
+    // The BPB spans one or more whole pages
+
+    if (Length < PAGE_SIZE || Length % PAGE_SIZE != 0) {
         goto Exit;
     }
 
@@ -39,7 +81,7 @@ CheckMyPage (
 
     SystemAddress = MmMapIoSpace (
         liPhysicalAddress,
-        4096,
+        PAGE_SIZE,
         MmNonCached
         );
 
@@ -65,7 +107,7 @@ CheckMyPage (
 
     MmUnmapIoSpace (
         SystemAddress,
-        4096
+        PAGE_SIZE
         );
 
     fReturn = (0xFEADDEAD == ProbeBytes);
@@ -75,3 +117,149 @@ Exit:
 
     return fReturn;
 }
+
+static
+NTSTATUS
+ReadPhysical (
+    ULONGLONG Address,
+    PVOID Buffer,
+    SIZE_T Length
+    )
+{
+    PHYSICAL_ADDRESS liPhysicalAddress;
+    PVOID SystemAddress;
+
+    liPhysicalAddress.QuadPart = (LONGLONG)Address;
+
+    SystemAddress = MmMapIoSpace (
+        liPhysicalAddress,
+        Length,
+        MmCached
+        );
+
+    if (!SystemAddress)
+    {
+        return STATUS_INSUFFICIENT_RESOURCES;
+    }
+
+    RtlCopyMemory(Buffer, SystemAddress, Length);
+
+    MmUnmapIoSpace (
+        SystemAddress,
+        Length
+        );
+
+    return STATUS_SUCCESS;
+}
+
+//
+// There is no firmware table provider for EFI configuration tables
+// (ExGetSystemFirmwareTable knows only ACPI, FIRM and RSMB), so walk
+// the system table whose physical address the application stores in
+// the SystemTable variable.
+//
+// SetVirtualAddressMap converts the ConfigurationTable pointer to the
+// runtime mapping; translate it back if the kernel can resolve it.
+// Vendor table pointers under our GUID are never converted.
+//
+
+NTSTATUS
+LocateMyConfigurationTable (
+    PPHYSICAL_ADDRESS PhysicalAddress,
+    PULONGLONG Length
+    )
+{
+    DECLARE_CONST_UNICODE_STRING(VariableName, L"SystemTable");
+    ULONGLONG SystemTableAddress = 0;
+    ULONG ValueLength = sizeof(SystemTableAddress);
+    MY_EFI_SYSTEM_TABLE SystemTable;
+    MY_EFI_CONFIGURATION_TABLE Entry;
+    MY_PAYLOAD Payload;
+    ULONGLONG ConfigurationTable;
+    ULONGLONG Index;
+    NTSTATUS Status;
+
+    FuncEntry(TRACE_FLAG_WDFLOADING);
+
+    Status = ExGetFirmwareEnvironmentVariable(
+        (PUNICODE_STRING)&VariableName,
+        (LPGUID)&MY_VENDOR_GUID,
+        &SystemTableAddress,
+        &ValueLength,
+        NULL
+        );
+
+    if (!NT_SUCCESS(Status) || ValueLength != sizeof(SystemTableAddress))
+    {
+        Trace(
+            TRACE_LEVEL_ERROR,
+            TRACE_FLAG_WDFLOADING,
+            "SystemTable variable not found - %!STATUS!",
+            Status
+            );
+
+        Status = STATUS_NOT_FOUND;
+        goto Exit;
+    }
+
+    Status = ReadPhysical(SystemTableAddress, &SystemTable, sizeof(SystemTable));
+
+    if (!NT_SUCCESS(Status))
+    {
+        goto Exit;
+    }
+
+    if (SystemTable.Signature != EFI_SYSTEM_TABLE_SIGNATURE)
+    {
+        Trace(
+            TRACE_LEVEL_ERROR,
+            TRACE_FLAG_WDFLOADING,
+            "No EFI system table at 0x%I64x",
+            SystemTableAddress
+            );
+
+        Status = STATUS_NOT_FOUND;
+        goto Exit;
+    }
+
+    ConfigurationTable = SystemTable.ConfigurationTable;
+
+    if (MmIsAddressValid((PVOID)(ULONG_PTR)ConfigurationTable))
+    {
+        ConfigurationTable = (ULONGLONG)MmGetPhysicalAddress(
+            (PVOID)(ULONG_PTR)ConfigurationTable).QuadPart;
+    }
+
+    Status = STATUS_NOT_FOUND;
+
+    for (Index = 0; Index < SystemTable.NumberOfTableEntries; Index++)
+    {
+        if (!NT_SUCCESS(ReadPhysical(
+                ConfigurationTable + Index * sizeof(Entry),
+                &Entry,
+                sizeof(Entry))))
+        {
+            break;
+        }
+
+        if (!IsEqualGUID(&Entry.VendorGuid, &MY_CONFIGURATION_TABLE_GUID))
+        {
+            continue;
+        }
+
+        if (NT_SUCCESS(ReadPhysical(Entry.VendorTable, &Payload, sizeof(Payload))) &&
+            Payload.Magic == MY_PAYLOAD_MAGIC)
+        {
+            PhysicalAddress->QuadPart = (LONGLONG)Payload.PhysicalAddress;
+            *Length = Payload.LengthBytes;
+            Status = STATUS_SUCCESS;
+        }
+
+        break;
+    }
+
+Exit:
+    FuncExit(TRACE_FLAG_WDFLOADING);
+
+    return Status;
+}
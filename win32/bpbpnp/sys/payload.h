@@ -3,5 +3,11 @@
 BOOLEAN
 CheckMyPage (
     PHYSICAL_ADDRESS PhysicalAddress,
-    ULONG Length
+    ULONGLONG Length
+    );
+
+NTSTATUS
+LocateMyConfigurationTable (
+    PPHYSICAL_ADDRESS PhysicalAddress,
+    PULONGLONG Length
     );